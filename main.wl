@@ -27,3 +27,42 @@ std:displayln "STARTUP";
         };
     };
 };
+
+!:global on_init = {!(api) = @;
+    # File associations, the first matching one is the default handler.
+    # %p is the file, %d the directory, %s the selection and %o the
+    # directory of the other pane.
+    # api.assoc_glob "*.txt" "gvim %p" "GVim";
+    # api.assoc_mime "text/*" "gvim %p" "GVim";
+    # api.assoc_mime "image/*" "feh %d" "feh";
+
+    # The editor for F4, $t if it has to run in the terminal. %l is the
+    # line, for instance from the grep page:
+    # api.set_editor "vim +%l %p" $t;
+    # api.set_terminal "xterm -e";
+
+    # api.panelize TITLE LIST shows a list of paths as one listing,
+    # relative paths are resolved against the active pane:
+    # api.panelize "sources" $["Cargo.toml", "src/main.rs"];
+
+    # Bookmarks, single letter names are the quick marks for ' in the
+    # file manager. api.bookmarks returns a list of [name, dir] pairs:
+    # api.bookmark "src" "/usr/src";
+    # api.bookmark_remove "src";
+    # api.bookmark_open "src";
+
+    # Listing filters, "." and Shift+"." toggle them per pane. Ignored
+    # entries match the .gitignore files or the ignore globs:
+    # api.show_hidden $f;
+    # api.use_gitignore $t;
+    # api.ignore_glob "*.o";
+    # api.set_ignored_mode "dim";
+
+    # The git status column, Shift+"g" toggles it per pane:
+    # api.show_git_status $f;
+
+    # Listings are colored by $LS_COLORS, api.set_color GLOB CODES
+    # overrides it with the same codes, 1 bold, 3 italic, 4 underline:
+    # api.set_color "*.wl" "01;38;5;208";
+    # api.set_color "Cargo.toml" "04;33";
+};
@@ -0,0 +1,230 @@
+use crate::glob::glob_match_nocase;
+use crate::mime::detect_mime;
+
+/// Command used for files without an association.
+pub const FALLBACK_COMMAND : &str = "xdg-open %p";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AssocMatch {
+    /// A glob on the file name, or on the full path if it contains a `/`.
+    Glob(String),
+    /// A MIME type like `text/plain`, or a group like `image/*`.
+    Mime(String),
+}
+
+impl AssocMatch {
+    fn matches(&self, path: &std::path::Path, mime: &mut Option<String>) -> bool {
+        match self {
+            AssocMatch::Glob(pattern) => {
+                if pattern.contains('/') {
+                    glob_match_nocase(pattern, &path.to_string_lossy())
+                } else {
+                    let name =
+                        path.file_name()
+                            .map(|n| n.to_string_lossy().to_string())
+                            .unwrap_or_default();
+                    glob_match_nocase(pattern, &name)
+                }
+            },
+            AssocMatch::Mime(pattern) => {
+                // Detecting the MIME type reads the file, do it only once:
+                if mime.is_none() {
+                    *mime = Some(detect_mime(path));
+                }
+                let mime = mime.as_ref().unwrap();
+                if pattern.ends_with("/*") {
+                    mime.starts_with(&pattern[0..(pattern.len() - 1)])
+                } else {
+                    mime == pattern
+                }
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Association {
+    pub matcher: AssocMatch,
+    pub command: String,
+    pub name:    String,
+}
+
+/// The table of commands to open files with. The first matching
+/// association is the default handler of a file.
+#[derive(Debug, Clone, Default)]
+pub struct Associations {
+    pub entries: Vec<Association>,
+}
+
+impl Associations {
+    pub fn new() -> Self { Self::default() }
+
+    pub fn add(&mut self, matcher: AssocMatch, command: &str, name: &str) {
+        let name = if name.is_empty() { command } else { name };
+        self.entries.push(Association {
+            matcher,
+            command: command.to_string(),
+            name:    name.to_string(),
+        });
+    }
+
+    /// All associations matching the file, the default one first.
+    pub fn handlers_for(&self, path: &std::path::Path) -> Vec<&Association> {
+        let mut mime = None;
+        self.entries.iter().filter(|a| a.matcher.matches(path, &mut mime)).collect()
+    }
+
+    /// The command to open the file with, `xdg-open` if nothing matches.
+    pub fn default_command(&self, path: &std::path::Path) -> String {
+        self.handlers_for(path)
+            .first()
+            .map(|a| a.command.clone())
+            .unwrap_or_else(|| String::from(FALLBACK_COMMAND))
+    }
+}
+
+/// What the placeholders of a command expand to.
+#[derive(Debug, Clone)]
+pub struct CommandContext {
+    /// The entry under the cursor, `%p`.
+    pub path:      std::path::PathBuf,
+    /// The directory of the active pane, `%d`.
+    pub dir:       std::path::PathBuf,
+    /// The selected entries, or the cursor entry if nothing is
+    /// selected, `%s`.
+    pub selection: Vec<std::path::PathBuf>,
+    /// The directory of the other pane, `%o`.
+    pub other_dir: Option<std::path::PathBuf>,
+}
+
+impl CommandContext {
+    /// Expands the placeholders in `s`. `%s` expands to the selection
+    /// joined with spaces, `%n` to the file name of the entry and `%%`
+    /// to a single `%`.
+    fn expand(&self, s: &str, quote: fn(&str) -> String) -> String {
+        let mut out = String::new();
+        let mut chars = s.chars();
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                out.push(c);
+                continue;
+            }
+            match chars.next() {
+                Some('p') => out += &quote(&self.path.to_string_lossy()),
+                Some('d') => out += &quote(&self.dir.to_string_lossy()),
+                Some('n') => {
+                    let name =
+                        self.path.file_name()
+                            .map(|n| n.to_string_lossy().to_string())
+                            .unwrap_or_default();
+                    out += &quote(&name);
+                },
+                Some('o') => {
+                    let other =
+                        self.other_dir.as_ref().unwrap_or(&self.dir).to_string_lossy().to_string();
+                    out += &quote(&other);
+                },
+                Some('s') => {
+                    let sel : Vec<String> =
+                        self.selection.iter()
+                            .map(|p| quote(&p.to_string_lossy()))
+                            .collect();
+                    out += &sel.join(" ");
+                },
+                Some('%') => out.push('%'),
+                Some(c)   => { out.push('%'); out.push(c); },
+                None      => out.push('%'),
+            }
+        }
+        out
+    }
+
+    /// Splits a command into arguments and expands the placeholders in
+    /// them. An argument that is only `%s` becomes one argument per
+    /// selected entry.
+    pub fn expand_args(&self, command: &str) -> Vec<String> {
+        let mut args = vec![];
+        for word in split_command(command) {
+            if word == "%s" {
+                for p in self.selection.iter() {
+                    args.push(p.to_string_lossy().to_string());
+                }
+            } else {
+                args.push(self.expand(&word, |s| s.to_string()));
+            }
+        }
+        args
+    }
+
+    /// Expands the placeholders for a command line run by `sh -c`,
+    /// quoting the paths.
+    pub fn expand_shell(&self, command: &str) -> String {
+        self.expand(command, shell_quote)
+    }
+}
+
+/// Quotes a string for the shell.
+pub fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
+
+/// Splits a command line at whitespace. Single and double quotes group
+/// words, a backslash escapes the next character.
+pub fn split_command(command: &str) -> Vec<String> {
+    let mut words = vec![];
+    let mut word  = String::new();
+    let mut in_word = false;
+    let mut quote : Option<char> = None;
+    let mut chars = command.chars();
+
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), '\\') | (None, '\\') => {
+                if let Some(c) = chars.next() { word.push(c); }
+                in_word = true;
+            },
+            (Some(_), c) => word.push(c),
+            (None, '\'') | (None, '"') => {
+                quote   = Some(c);
+                in_word = true;
+            },
+            (None, c) if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            },
+            (None, c) => {
+                word.push(c);
+                in_word = true;
+            },
+        }
+    }
+    if in_word {
+        words.push(word);
+    }
+    words
+}
+
+/// Starts a program without waiting for it. Its output is discarded
+/// and a thread reaps it once it exits, so no zombies are left behind.
+pub fn spawn_detached(args: &[String], cwd: &std::path::Path) -> std::io::Result<()> {
+    use std::process::{Command, Stdio};
+
+    if args.is_empty() {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "empty command"));
+    }
+
+    let mut child =
+        Command::new(&args[0])
+            .args(&args[1..])
+            .current_dir(cwd)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()?;
+
+    std::thread::spawn(move || { let _ = child.wait(); });
+    Ok(())
+}
@@ -0,0 +1,380 @@
+use chrono::{DateTime, NaiveDateTime};
+use chrono::offset::Utc;
+
+/// Timestamp format used for displaying and entering times. Times are
+/// in UTC, like in the directory listing.
+pub const TIME_FORMAT : &str = "%Y-%m-%d %H:%M:%S";
+
+pub fn format_time(t: std::time::SystemTime) -> String {
+    let dt : DateTime<Utc> = t.into();
+    format!("{}", dt.format(TIME_FORMAT))
+}
+
+pub fn parse_time(s: &str) -> Option<std::time::SystemTime> {
+    let ndt = NaiveDateTime::parse_from_str(s.trim(), TIME_FORMAT).ok()?;
+    Some(DateTime::<Utc>::from_utc(ndt, Utc).into())
+}
+
+/// Formats permission bits like `ls -l` does, including the
+/// setuid, setgid and sticky bits.
+pub fn mode_string(mode: u32) -> String {
+    let mut s = String::new();
+    let bits = [(0o400, 'r'), (0o200, 'w'), (0o100, 'x'),
+                (0o040, 'r'), (0o020, 'w'), (0o010, 'x'),
+                (0o004, 'r'), (0o002, 'w'), (0o001, 'x')];
+    for (i, (bit, c)) in bits.iter().enumerate() {
+        let special =
+            match i {
+                2 => mode & 0o4000 != 0,
+                5 => mode & 0o2000 != 0,
+                8 => mode & 0o1000 != 0,
+                _ => false,
+            };
+        let set = mode & bit != 0;
+        s.push(
+            if special {
+                let c = if i == 8 { 't' } else { 's' };
+                if set { c } else { c.to_ascii_uppercase() }
+            } else if set {
+                *c
+            } else {
+                '-'
+            });
+    }
+    s
+}
+
+#[cfg(unix)]
+pub fn set_mode(path: &std::path::Path, mode: u32) -> std::io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+}
+
+#[cfg(not(unix))]
+pub fn set_mode(_path: &std::path::Path, _mode: u32) -> std::io::Result<()> {
+    Err(std::io::Error::new(std::io::ErrorKind::Other, "not supported on this platform"))
+}
+
+/// Changes the owner of the entry itself, a symbolic link is not
+/// followed.
+#[cfg(unix)]
+pub fn set_owner(path: &std::path::Path, uid: Option<u32>, gid: Option<u32>) -> std::io::Result<()> {
+    std::os::unix::fs::lchown(path, uid, gid)
+}
+
+#[cfg(not(unix))]
+pub fn set_owner(_path: &std::path::Path, _uid: Option<u32>, _gid: Option<u32>) -> std::io::Result<()> {
+    Err(std::io::Error::new(std::io::ErrorKind::Other, "not supported on this platform"))
+}
+
+#[cfg(unix)]
+fn to_timespec(t: Option<std::time::SystemTime>) -> libc::timespec {
+    let t =
+        match t {
+            Some(t) => t,
+            None    => return libc::timespec { tv_sec: 0, tv_nsec: libc::UTIME_OMIT },
+        };
+    let (secs, nsecs) =
+        match t.duration_since(std::time::UNIX_EPOCH) {
+            Ok(d)  => (d.as_secs() as i64, d.subsec_nanos() as i64),
+            Err(e) => {
+                let d = e.duration();
+                if d.subsec_nanos() == 0 {
+                    (-(d.as_secs() as i64), 0)
+                } else {
+                    (-(d.as_secs() as i64) - 1, 1_000_000_000 - d.subsec_nanos() as i64)
+                }
+            },
+        };
+    libc::timespec { tv_sec: secs as libc::time_t, tv_nsec: nsecs as _ }
+}
+
+/// Sets the access and modification time with `utimensat`, `None`
+/// leaves a time as is. Unlike opening the file this doesn't block on
+/// FIFOs, works without read permission and doesn't follow links.
+#[cfg(unix)]
+fn utimens(path: &std::path::Path,
+           atime: Option<std::time::SystemTime>,
+           mtime: Option<std::time::SystemTime>) -> std::io::Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+    let cpath = std::ffi::CString::new(path.as_os_str().as_bytes())
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    let times = [to_timespec(atime), to_timespec(mtime)];
+    let ret = unsafe {
+        libc::utimensat(libc::AT_FDCWD, cpath.as_ptr(), times.as_ptr(), libc::AT_SYMLINK_NOFOLLOW)
+    };
+    if ret == 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error())
+    }
+}
+
+#[cfg(unix)]
+pub fn set_mtime(path: &std::path::Path, t: std::time::SystemTime) -> std::io::Result<()> {
+    utimens(path, None, Some(t))
+}
+
+#[cfg(not(unix))]
+pub fn set_mtime(_path: &std::path::Path, _t: std::time::SystemTime) -> std::io::Result<()> {
+    Err(std::io::Error::new(std::io::ErrorKind::Other, "not supported on this platform"))
+}
+
+/// Sets both access and modification time. `None` leaves a time as is.
+#[cfg(unix)]
+pub fn set_times(path: &std::path::Path,
+                 atime: Option<std::time::SystemTime>,
+                 mtime: Option<std::time::SystemTime>) -> std::io::Result<()> {
+    utimens(path, atime, mtime)
+}
+
+#[cfg(not(unix))]
+pub fn set_times(_path: &std::path::Path,
+                 _atime: Option<std::time::SystemTime>,
+                 _mtime: Option<std::time::SystemTime>) -> std::io::Result<()> {
+    Err(std::io::Error::new(std::io::ErrorKind::Other, "not supported on this platform"))
+}
+
+/// Which entries a batch operation changes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EntryFilter {
+    All,
+    FilesOnly,
+    DirsOnly,
+}
+
+impl EntryFilter {
+    fn matches(&self, is_dir: bool) -> bool {
+        match self {
+            EntryFilter::All       => true,
+            EntryFilter::FilesOnly => !is_dir,
+            EntryFilter::DirsOnly  => is_dir,
+        }
+    }
+}
+
+/// A change of one entry, like a chmod, for `apply_recursive`.
+pub type EntryOp = Box<dyn FnMut(&std::path::Path) -> std::io::Result<()>>;
+
+/// Calls `f` for `path` and, if `recursive` is set, for everything below
+/// it. Entries not matching `filter` are skipped, but still descended
+/// into. Symbolic links are not descended into, `f` has to take care not
+/// to follow them when changing an entry. Failures don't stop the walk,
+/// they are collected as messages in `log`. Returns the number of entries
+/// `f` succeeded on.
+pub fn apply_recursive<F>(path: &std::path::Path, recursive: bool,
+                          filter: EntryFilter,
+                          f: &mut F, log: &mut Vec<String>) -> usize
+    where F: FnMut(&std::path::Path) -> std::io::Result<()> {
+
+    let is_dir =
+        path.symlink_metadata().map(|md| md.file_type().is_dir()).unwrap_or(false);
+
+    let mut count = 0;
+    if filter.matches(is_dir) {
+        match f(path) {
+            Ok(())  => count += 1,
+            Err(e)  => log.push(format!("{}: {}", path.to_string_lossy(), e)),
+        }
+    }
+
+    if !recursive || !is_dir {
+        return count;
+    }
+
+    match std::fs::read_dir(path) {
+        Ok(entries) => {
+            for e in entries {
+                match e {
+                    Ok(e)  => count += apply_recursive(&e.path(), true, filter, f, log),
+                    Err(e) => log.push(format!("{}: {}", path.to_string_lossy(), e)),
+                }
+            }
+        },
+        Err(e) => log.push(format!("{}: {}", path.to_string_lossy(), e)),
+    }
+    count
+}
+
+/// One clause of a symbolic mode expression, like `go-w`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModeClause {
+    who:   u32,
+    op:    char,
+    perms: u32,
+    /// `X`: execute only for directories or if anyone may execute already.
+    cond_exec: bool,
+}
+
+/// A parsed `chmod` expression, either an octal mode or symbolic
+/// clauses like `u+x,go-w` or `a=rX`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ModeChange {
+    Octal(u32),
+    Symbolic(Vec<ModeClause>),
+}
+
+impl ModeChange {
+    pub fn parse(expr: &str) -> Result<ModeChange, String> {
+        let expr = expr.trim();
+        if !expr.is_empty() && expr.chars().all(|c| c.is_digit(8)) {
+            return match u32::from_str_radix(expr, 8) {
+                Ok(mode) if mode <= 0o7777 => Ok(ModeChange::Octal(mode)),
+                _ => Err(format!("bad octal mode '{}'", expr)),
+            };
+        }
+
+        let mut clauses = vec![];
+        for clause in expr.split(',') {
+            let mut chars = clause.chars().peekable();
+
+            let mut who = 0;
+            while let Some(c) = chars.peek() {
+                match c {
+                    'u' => who |= 0o4700,
+                    'g' => who |= 0o2070,
+                    'o' => who |= 0o1007,
+                    'a' => who |= 0o7777,
+                    _   => break,
+                }
+                chars.next();
+            }
+            if who == 0 { who = 0o7777; }
+
+            let op = match chars.next() {
+                Some(op) if op == '+' || op == '-' || op == '=' => op,
+                _ => return Err(format!("expected +, - or = in '{}'", clause)),
+            };
+
+            let mut perms = 0;
+            let mut cond_exec = false;
+            for c in chars {
+                match c {
+                    'r' => perms |= 0o444,
+                    'w' => perms |= 0o222,
+                    'x' => perms |= 0o111,
+                    'X' => cond_exec = true,
+                    's' => perms |= 0o6000,
+                    't' => perms |= 0o1000,
+                    _   => return Err(format!("bad permission '{}' in '{}'", c, clause)),
+                }
+            }
+
+            clauses.push(ModeClause { who, op, perms, cond_exec });
+        }
+        Ok(ModeChange::Symbolic(clauses))
+    }
+
+    /// Computes the new permission bits from the current ones.
+    pub fn apply(&self, mode: u32, is_dir: bool) -> u32 {
+        let mode = mode & 0o7777;
+        match self {
+            ModeChange::Octal(m) => *m,
+            ModeChange::Symbolic(clauses) => {
+                let mut mode = mode;
+                for c in clauses.iter() {
+                    let mut perms = c.perms;
+                    if c.cond_exec && (is_dir || mode & 0o111 != 0) {
+                        perms |= 0o111;
+                    }
+                    let bits = perms & c.who;
+                    mode =
+                        match c.op {
+                            '+' => mode | bits,
+                            '-' => mode & !bits,
+                            _   => (mode & !c.who) | bits,
+                        };
+                }
+                mode
+            },
+        }
+    }
+}
+
+/// Applies the mode change to the entry. Symbolic links are skipped like
+/// chmod(1) does in a walk: their own mode means nothing, and changing
+/// it would change the target instead.
+#[cfg(unix)]
+pub fn change_mode(path: &std::path::Path, change: &ModeChange) -> std::io::Result<()> {
+    use std::os::unix::fs::MetadataExt;
+    let md = path.symlink_metadata()?;
+    if md.file_type().is_symlink() {
+        return Ok(());
+    }
+    set_mode(path, change.apply(md.mode(), md.is_dir()))
+}
+
+#[cfg(not(unix))]
+pub fn change_mode(path: &std::path::Path, change: &ModeChange) -> std::io::Result<()> {
+    set_mode(path, change.apply(0, false))
+}
+
+/// The options shared by the batch operations, given before the
+/// argument: `-R` for recursive, `-f` for files only and `-d` for
+/// directories only. Options end at the first other word, so `-x` stays
+/// a mode and `-a` a touch option. Returns the options and the remaining
+/// argument.
+pub fn parse_batch_options(input: &str) -> (bool, EntryFilter, String) {
+    let mut recursive = false;
+    let mut filter    = EntryFilter::All;
+    let mut words     = input.split_whitespace().peekable();
+
+    while let Some(word) = words.peek() {
+        match *word {
+            "-R" => recursive = true,
+            "-f" => filter = EntryFilter::FilesOnly,
+            "-d" => filter = EntryFilter::DirsOnly,
+            _    => break,
+        }
+        words.next();
+    }
+
+    (recursive, filter, words.collect::<Vec<&str>>().join(" "))
+}
+
+/// What a `touch` operation sets the times to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TouchSpec {
+    pub atime: Option<std::time::SystemTime>,
+    pub mtime: Option<std::time::SystemTime>,
+}
+
+impl TouchSpec {
+    /// Parses `[-a|-m] now`, `[-a|-m] YYYY-MM-DD HH:MM:SS` or
+    /// `[-a|-m] ref PATH`. With `-a` only the access time is set, with
+    /// `-m` only the modification time. A relative `PATH` is resolved
+    /// against `base`.
+    pub fn parse(arg: &str, base: &std::path::Path) -> Result<TouchSpec, String> {
+        let mut arg = arg.trim();
+        let (mut set_a, mut set_m) = (true, true);
+        let flag = arg.split_whitespace().next().unwrap_or("");
+        if flag == "-a" {
+            set_m = false;
+            arg = arg[2..].trim();
+        } else if flag == "-m" {
+            set_a = false;
+            arg = arg[2..].trim();
+        }
+
+        let (atime, mtime) =
+            if arg == "now" || arg.is_empty() {
+                let now = std::time::SystemTime::now();
+                (now, now)
+            } else if let Some(ref_path) = arg.strip_prefix("ref ") {
+                let ref_path = ref_path.trim();
+                let md = std::fs::metadata(base.join(ref_path))
+                    .map_err(|e| format!("{}: {}", ref_path, e))?;
+                (md.accessed().map_err(|e| e.to_string())?,
+                 md.modified().map_err(|e| e.to_string())?)
+            } else {
+                let t = parse_time(arg)
+                    .ok_or_else(|| format!("bad time '{}', expected YYYY-MM-DD HH:MM:SS", arg))?;
+                (t, t)
+            };
+
+        Ok(TouchSpec {
+            atime: if set_a { Some(atime) } else { None },
+            mtime: if set_m { Some(mtime) } else { None },
+        })
+    }
+}
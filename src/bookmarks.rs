@@ -0,0 +1,284 @@
+use crate::fm_page::*;
+use crate::cursor::PageCursor;
+
+/// The configuration directory of the file manager, following the
+/// XDG base directory specification.
+pub fn config_dir() -> Option<std::path::PathBuf> {
+    let config =
+        match std::env::var_os("XDG_CONFIG_HOME") {
+            Some(dir) if !dir.is_empty() => std::path::PathBuf::from(dir),
+            _ => std::path::PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+        };
+    Some(config.join("wcdemengine"))
+}
+
+/// Named directory bookmarks, stored one `name<TAB>path` per line.
+/// Bookmarks with a single letter name are the quick marks.
+#[derive(Debug, Clone, Default)]
+pub struct Bookmarks {
+    pub file: Option<std::path::PathBuf>,
+    pub list: Vec<(String, std::path::PathBuf)>,
+}
+
+impl Bookmarks {
+    /// Loads the bookmarks from the config directory. A missing file
+    /// is an empty list.
+    pub fn load() -> std::io::Result<Bookmarks> {
+        match config_dir() {
+            Some(dir) => Self::load_from(&dir.join("bookmarks")),
+            None      => Ok(Bookmarks::default()),
+        }
+    }
+
+    pub fn load_from(file: &std::path::Path) -> std::io::Result<Bookmarks> {
+        let mut bm = Bookmarks { file: Some(file.to_path_buf()), list: vec![] };
+        let text =
+            match std::fs::read_to_string(file) {
+                Ok(text) => text,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(bm),
+                Err(e) => return Err(e),
+            };
+        for line in text.lines() {
+            let mut parts = line.splitn(2, '\t');
+            if let (Some(name), Some(path)) = (parts.next(), parts.next()) {
+                if !name.is_empty() && !path.is_empty() {
+                    bm.list.push((name.to_string(), std::path::PathBuf::from(path)));
+                }
+            }
+        }
+        Ok(bm)
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        let file =
+            match &self.file {
+                Some(file) => file,
+                None => {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::NotFound, "no config directory, $HOME is not set"));
+                },
+            };
+        if let Some(dir) = file.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let mut text = String::new();
+        for (name, path) in self.list.iter() {
+            text += &format!("{}\t{}\n", name, path.to_string_lossy());
+        }
+        std::fs::write(file, text)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&std::path::Path> {
+        self.list.iter().find(|(n, _)| n == name).map(|(_, p)| p.as_path())
+    }
+
+    /// Adds a bookmark, or points an existing one with the same name to
+    /// `path`.
+    pub fn set(&mut self, name: &str, path: &std::path::Path) {
+        let name = name.trim().replace(|c| c == '\t' || c == '\n', " ");
+        match self.list.iter_mut().find(|(n, _)| *n == name) {
+            Some(bm) => bm.1 = path.to_path_buf(),
+            None     => self.list.push((name, path.to_path_buf())),
+        }
+    }
+
+    pub fn remove(&mut self, name: &str) -> bool {
+        let len = self.list.len();
+        self.list.retain(|(n, _)| n != name);
+        self.list.len() != len
+    }
+}
+
+/// Lists the bookmarks. Access opens a bookmark in the pane, bookmarks
+/// whose directory does not exist anymore are highlighted.
+pub struct BookmarkSheet {
+    /// The directory of the pane, which new bookmarks point to.
+    pub base:            std::path::PathBuf,
+    pub bookmarks:       Bookmarks,
+    /// The bookmarked directories that didn't exist at the last reload.
+    pub missing:         std::collections::HashSet<std::path::PathBuf>,
+    pub status:          String,
+    pub render_feedback: RenderFeedback,
+    pub cursor:          PageCursor,
+    pub rendered:        TableRef,
+}
+
+impl BookmarkSheet {
+    pub fn new(base: &std::path::Path) -> BookmarkSheet {
+        let mut bs = BookmarkSheet {
+            base:            base.to_path_buf(),
+            bookmarks:       Bookmarks::default(),
+            missing:         std::collections::HashSet::new(),
+            status:          String::new(),
+            render_feedback: RenderFeedback::new(),
+            cursor:          PageCursor::new(),
+            rendered:        Table::new_ref(),
+        };
+        bs.reload();
+        bs
+    }
+
+    /// Reads the bookmarks again and checks which directories are
+    /// missing, so redraws don't stat them.
+    fn reload(&mut self) {
+        match Bookmarks::load() {
+            Ok(bm) => self.bookmarks = bm,
+            Err(e) => self.status = format!("can't read bookmarks: {}", e),
+        }
+        self.missing =
+            self.bookmarks.list.iter()
+                .filter(|(_, p)| !p.is_dir())
+                .map(|(_, p)| p.clone())
+                .collect();
+    }
+
+    fn save(&mut self) {
+        match self.bookmarks.save() {
+            Ok(())  => self.status = String::new(),
+            Err(e)  => self.status = format!("can't save bookmarks: {}", e),
+        }
+    }
+
+    fn cursor_bookmark(&self) -> Option<(String, std::path::PathBuf)> {
+        self.bookmarks.list.get(self.cursor.cursor_idx).cloned()
+    }
+
+    /// Returns the event to open the bookmark, or reports a missing
+    /// target in the title.
+    fn open_event(&mut self, new_tab: bool) -> Option<PageEvent> {
+        let (name, path) = self.cursor_bookmark()?;
+        if !path.is_dir() {
+            self.status = format!("'{}' is missing: {}", name, path.to_string_lossy());
+            return None;
+        }
+        if new_tab {
+            Some(PageEvent::OpenTab(path))
+        } else {
+            Some(PageEvent::OpenDir(path))
+        }
+    }
+}
+
+impl FmPage for BookmarkSheet {
+    fn len(&self) -> usize { self.bookmarks.list.len() }
+    fn get_scroll_offs(&self) -> usize { self.cursor.scroll_offset }
+    fn is_cursor_idx(&self, idx: usize) -> bool { self.cursor.is_cursor_idx(idx) }
+    fn is_selected(&self, _idx: usize) -> bool { false }
+    fn needs_repage(&self) -> bool { true }
+    fn needs_redraw(&self) -> bool { true }
+
+    fn is_highlighted(&self, idx: usize) -> bool {
+        self.bookmarks.list.get(idx).map(|(_, p)| self.missing.contains(p)).unwrap_or(false)
+    }
+
+    fn sort_by_column(&mut self, col_idx: usize) {
+        match col_idx {
+            0 => self.bookmarks.list.sort_by(|a, b| a.0.cmp(&b.0)),
+            1 => self.bookmarks.list.sort_by(|a, b| a.1.cmp(&b.1)),
+            _ => (),
+        }
+    }
+
+    fn set_render_feedback(&mut self, fb: RenderFeedback) {
+        self.render_feedback = fb;
+    }
+
+    fn is_inside_screen_rect(&self, x: i32, y: i32) -> bool {
+        self.render_feedback.is_inside_screen_rect(x, y)
+    }
+
+    fn cursor_path(&self) -> Option<std::path::PathBuf> {
+        self.cursor_bookmark().map(|(_, p)| p)
+    }
+
+    fn do_control(&mut self, ctrl: PageControl) -> Option<PageEvent> {
+        match ctrl {
+            PageControl::Access => self.open_event(false),
+            PageControl::Back => Some(PageEvent::Close),
+            PageControl::Refresh => {
+                self.reload();
+                self.cursor.do_control(self.len(), &self.render_feedback, ctrl);
+                None
+            },
+            PageControl::CycleMode => {
+                let name = self.base.file_name().unwrap_or_default().to_string_lossy().to_string();
+                Some(PageEvent::Prompt(
+                    format!("[a]dd NAME for {}, [d]elete, open in new [t]ab: ",
+                        self.base.to_string_lossy()),
+                    format!("a {}", name)))
+            },
+            PageControl::Edit(text) => {
+                let text = text.trim();
+                let (cmd, arg) =
+                    match text.find(' ') {
+                        Some(i) => (&text[..i], text[i..].trim()),
+                        None    => (text, ""),
+                    };
+                match cmd {
+                    "a" if !arg.is_empty() => {
+                        let base = self.base.clone();
+                        self.bookmarks.set(arg, &base);
+                        self.save();
+                        if let Some(idx) = self.bookmarks.list.iter().position(|(n, _)| n == arg) {
+                            self.cursor.cursor_idx = idx;
+                        }
+                    },
+                    "d" => {
+                        if let Some((name, _)) = self.cursor_bookmark() {
+                            self.bookmarks.remove(&name);
+                            self.save();
+                        }
+                    },
+                    "t" => return self.open_event(true),
+                    _ => self.status = format!("unknown bookmark command: {}", text),
+                }
+                self.cursor.do_control(self.len(), &self.render_feedback, PageControl::Refresh);
+                None
+            },
+            _ => {
+                self.cursor.do_control(self.len(), &self.render_feedback, ctrl);
+                None
+            },
+        }
+    }
+
+    fn as_drawable_table(&mut self) -> TableRef {
+        let mut title = format!("bookmarks [{}]", self.bookmarks.list.len());
+        if !self.status.is_empty() {
+            title += " ";
+            title += &self.status;
+        }
+
+        self.rendered =
+            std::rc::Rc::new(std::cell::RefCell::new(Table {
+                title,
+                row_gap: 2,
+                col_gap: 4,
+                columns: vec![
+                    Column {
+                        head: String::from("name"),
+                        size: ColumnSizing::TextWidth(String::from("MMMMMMMMMMMM")),
+                        calc_size: None,
+                        rows: self.bookmarks.list.iter().map(|(name, _)| StyleString {
+                            text: name.clone(),
+                            style: Style::Default,
+                            highlight: vec![],
+                        }).collect(),
+                    },
+                    Column {
+                        head: String::from("directory"),
+                        size: ColumnSizing::ExpandFract(1),
+                        calc_size: None,
+                        rows: self.bookmarks.list.iter().map(|(_, path)| {
+                            let mut text = path.to_string_lossy().to_string();
+                            if self.missing.contains(path) {
+                                text += " (missing)";
+                            }
+                            StyleString { text, style: Style::Dir, highlight: vec![] }
+                        }).collect(),
+                    },
+                ],
+            }));
+        self.rendered.clone()
+    }
+}
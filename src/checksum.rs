@@ -0,0 +1,534 @@
+use crate::fm_page::*;
+use crate::cursor::PageCursor;
+use std::io::Read;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HashAlgo {
+    Md5,
+    Sha1,
+    Sha256,
+    Blake3,
+}
+
+impl HashAlgo {
+    pub fn name(&self) -> &'static str {
+        match self {
+            HashAlgo::Md5    => "md5",
+            HashAlgo::Sha1   => "sha1",
+            HashAlgo::Sha256 => "sha256",
+            HashAlgo::Blake3 => "blake3",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<HashAlgo> {
+        match name.trim().to_lowercase().as_str() {
+            "md5"              => Some(HashAlgo::Md5),
+            "sha1"             => Some(HashAlgo::Sha1),
+            "sha256"           => Some(HashAlgo::Sha256),
+            "blake3" | "b3"    => Some(HashAlgo::Blake3),
+            _                  => None,
+        }
+    }
+
+    /// The extension of checksum files written with this algorithm.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            HashAlgo::Blake3 => "b3",
+            algo             => algo.name(),
+        }
+    }
+
+    /// The algorithm of a checksum file, from its extension like
+    /// `.sha256` or its name like `SHA256SUMS`.
+    pub fn for_checksum_file(path: &std::path::Path) -> Option<HashAlgo> {
+        if let Some(ext) = path.extension() {
+            if let Some(algo) = Self::from_name(&ext.to_string_lossy()) {
+                return Some(algo);
+            }
+        }
+        let name = path.file_name()?.to_string_lossy().to_lowercase();
+        for algo in [HashAlgo::Md5, HashAlgo::Sha1, HashAlgo::Sha256, HashAlgo::Blake3].iter() {
+            if name == format!("{}sums", algo.extension()) {
+                return Some(*algo);
+            }
+        }
+        None
+    }
+}
+
+enum Hasher {
+    Md5(md5::Context),
+    Sha1(sha1::Sha1),
+    Sha256(sha2::Sha256),
+    Blake3(Box<blake3::Hasher>),
+}
+
+impl Hasher {
+    fn new(algo: HashAlgo) -> Hasher {
+        use sha2::Digest;
+        match algo {
+            HashAlgo::Md5    => Hasher::Md5(md5::Context::new()),
+            HashAlgo::Sha1   => Hasher::Sha1(sha1::Sha1::new()),
+            HashAlgo::Sha256 => Hasher::Sha256(sha2::Sha256::new()),
+            HashAlgo::Blake3 => Hasher::Blake3(Box::new(blake3::Hasher::new())),
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        use sha2::Digest;
+        match self {
+            Hasher::Md5(ctx)    => ctx.consume(data),
+            Hasher::Sha1(h)     => h.update(data),
+            Hasher::Sha256(h)   => h.input(data),
+            Hasher::Blake3(h)   => { h.update(data); },
+        }
+    }
+
+    /// Returns the checksum as lower case hex string.
+    fn finish(self) -> String {
+        use sha2::Digest;
+        match self {
+            Hasher::Md5(ctx)    => format!("{:x}", ctx.compute()),
+            Hasher::Sha1(h)     => h.digest().to_string(),
+            Hasher::Sha256(h)   => h.result().iter().map(|b| format!("{:02x}", b)).collect(),
+            Hasher::Blake3(h)   => h.finalize().to_hex().to_string(),
+        }
+    }
+}
+
+pub fn hash_file(path: &std::path::Path, algo: HashAlgo, stop: &AtomicBool)
+    -> std::io::Result<String> {
+
+    let mut file   = std::fs::File::open(path)?;
+    let mut hasher = Hasher::new(algo);
+    let mut buf    = vec![0; 64 * 1024];
+    loop {
+        if stop.load(Ordering::Relaxed) {
+            return Err(std::io::Error::new(std::io::ErrorKind::Interrupted, "canceled"));
+        }
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[0..n]);
+    }
+    Ok(hasher.finish())
+}
+
+/// Parses a line of a checksum file, in the format of `sha256sum`
+/// (`HASH  NAME`, or `HASH *NAME` for binary mode) or the BSD format
+/// (`SHA256 (NAME) = HASH`). Returns the hash and the file name.
+pub fn parse_checksum_line(line: &str) -> Option<(String, String)> {
+    let line = line.trim_end_matches(|c| c == '\r' || c == '\n');
+    if line.trim().is_empty() || line.starts_with('#') {
+        return None;
+    }
+
+    if let Some(open) = line.find(" (") {
+        if let Some(close) = line.rfind(") = ") {
+            if close > open {
+                let name = &line[(open + 2)..close];
+                let hash = line[(close + 4)..].trim();
+                return Some((hash.to_lowercase(), name.to_string()));
+            }
+        }
+    }
+
+    let sep  = line.find(' ')?;
+    let hash = &line[0..sep];
+    if hash.is_empty() || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let rest = &line[(sep + 1)..];
+    let name = if rest.starts_with(' ') || rest.starts_with('*') { &rest[1..] } else { rest };
+    Some((hash.to_lowercase(), name.to_string()))
+}
+
+/// Calls `emit` for the regular files of the given paths, directories
+/// are walked recursively. Symbolic links to directories are not
+/// followed, FIFOs, sockets and devices are skipped. Returns false if
+/// `emit` did, to stop the walk.
+pub fn walk_files<F>(paths: &[std::path::PathBuf], emit: &mut F) -> bool
+    where F: FnMut(&std::path::Path) -> bool {
+
+    for path in paths.iter() {
+        let is_dir =
+            path.symlink_metadata().map(|md| md.file_type().is_dir()).unwrap_or(false);
+        if !is_dir {
+            if path.metadata().map(|md| md.is_file()).unwrap_or(false) && !emit(path) {
+                return false;
+            }
+            continue;
+        }
+
+        let mut entries : Vec<std::path::PathBuf> =
+            match std::fs::read_dir(path) {
+                Ok(rd) => rd.filter_map(|e| e.ok()).map(|e| e.path()).collect(),
+                Err(_) => continue,
+            };
+        entries.sort();
+        if !walk_files(&entries, emit) {
+            return false;
+        }
+    }
+    true
+}
+
+pub enum ChecksumMsg {
+    /// A file found by the walk, it gets the next row.
+    File(std::path::PathBuf),
+    /// The row index and the checksum, or the error.
+    Result(usize, std::io::Result<String>),
+    Done,
+}
+
+/// Hashes files on a thread. Dropping the job stops it.
+pub struct ChecksumJob {
+    cancel: Arc<AtomicBool>,
+    rx:     Receiver<ChecksumMsg>,
+}
+
+impl ChecksumJob {
+    /// Hashes a list of files, the rows of which exist already.
+    pub fn start(files: Vec<std::path::PathBuf>, algo: HashAlgo) -> ChecksumJob {
+        let cancel = Arc::new(AtomicBool::new(false));
+        let (tx, rx) = channel();
+
+        let stop = cancel.clone();
+        std::thread::spawn(move || {
+            for (i, path) in files.iter().enumerate() {
+                if stop.load(Ordering::Relaxed) {
+                    return;
+                }
+                if tx.send(ChecksumMsg::Result(i, hash_file(path, algo, &stop))).is_err() {
+                    return;
+                }
+            }
+            let _ = tx.send(ChecksumMsg::Done);
+        });
+
+        ChecksumJob { cancel, rx }
+    }
+
+    /// Walks the paths and hashes the files found, reporting each one
+    /// with `ChecksumMsg::File` first.
+    pub fn walk(paths: Vec<std::path::PathBuf>, algo: HashAlgo) -> ChecksumJob {
+        let cancel = Arc::new(AtomicBool::new(false));
+        let (tx, rx) = channel();
+
+        let stop = cancel.clone();
+        std::thread::spawn(move || {
+            let mut i = 0;
+            let complete = walk_files(&paths, &mut |path| {
+                if stop.load(Ordering::Relaxed)
+                   || tx.send(ChecksumMsg::File(path.to_path_buf())).is_err() {
+                    return false;
+                }
+                let res = hash_file(path, algo, &stop);
+                i += 1;
+                tx.send(ChecksumMsg::Result(i - 1, res)).is_ok()
+            });
+            if complete {
+                let _ = tx.send(ChecksumMsg::Done);
+            }
+        });
+
+        ChecksumJob { cancel, rx }
+    }
+
+    pub fn poll(&self) -> Vec<ChecksumMsg> {
+        let mut msgs = vec![];
+        loop {
+            match self.rx.try_recv() {
+                Ok(msg) => msgs.push(msg),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    msgs.push(ChecksumMsg::Done);
+                    break;
+                },
+            }
+        }
+        msgs
+    }
+}
+
+impl Drop for ChecksumJob {
+    fn drop(&mut self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChecksumStatus {
+    Pending,
+    Computed,
+    Ok,
+    Failed,
+    Missing,
+}
+
+#[derive(Debug, Clone)]
+pub struct ChecksumRow {
+    pub path:     std::path::PathBuf,
+    /// The name relative to the base directory, as written to or read
+    /// from the checksum file.
+    pub name:     String,
+    pub expected: Option<String>,
+    pub checksum: Option<String>,
+    pub status:   ChecksumStatus,
+}
+
+/// Shows the checksums of a list of files while they are computed. The
+/// page either computes them for export, or verifies the files listed
+/// in a checksum file.
+pub struct ChecksumSheet {
+    pub base:            std::path::PathBuf,
+    pub algo:            HashAlgo,
+    /// The checksum file that is verified.
+    pub verify:          Option<std::path::PathBuf>,
+    pub rows:            Vec<ChecksumRow>,
+    pub job:             Option<ChecksumJob>,
+    /// Set after an export, so the directory listings are read again.
+    pub exported:        bool,
+    pub render_feedback: RenderFeedback,
+    pub cursor:          PageCursor,
+    pub rendered:        TableRef,
+}
+
+impl ChecksumSheet {
+    fn with_rows(base: &std::path::Path, algo: HashAlgo, rows: Vec<ChecksumRow>,
+                 job: ChecksumJob) -> Self {
+        ChecksumSheet {
+            base:            base.to_path_buf(),
+            algo,
+            verify:          None,
+            rows,
+            job:             Some(job),
+            exported:        false,
+            render_feedback: RenderFeedback::new(),
+            cursor:          PageCursor::new(),
+            rendered:        Table::new_ref(),
+        }
+    }
+
+    /// Computes the checksums of the files below the paths, named
+    /// relative to `base`. The rows are added as the files are found.
+    pub fn compute(base: &std::path::Path, paths: Vec<std::path::PathBuf>, algo: HashAlgo) -> Self {
+        Self::with_rows(base, algo, vec![], ChecksumJob::walk(paths, algo))
+    }
+
+    /// Verifies the files listed in a checksum file, relative to its
+    /// directory.
+    pub fn verify(file: &std::path::Path, algo: HashAlgo) -> std::io::Result<Self> {
+        let base    = file.parent().unwrap_or(file).to_path_buf();
+        let content = std::fs::read_to_string(file)?;
+        let rows =
+            content.lines().filter_map(parse_checksum_line).map(|(hash, name)| ChecksumRow {
+                path:     base.join(&name),
+                name,
+                expected: Some(hash),
+                checksum: None,
+                status:   ChecksumStatus::Pending,
+            }).collect::<Vec<ChecksumRow>>();
+        let files = rows.iter().map(|r| r.path.clone()).collect();
+        let mut sheet = Self::with_rows(&base, algo, rows, ChecksumJob::start(files, algo));
+        sheet.verify = Some(file.to_path_buf());
+        Ok(sheet)
+    }
+
+    fn count(&self, status: ChecksumStatus) -> usize {
+        self.rows.iter().filter(|r| r.status == status).count()
+    }
+
+    /// Writes the computed checksums in the format of `sha256sum`.
+    fn export(&mut self, name: &str) -> Vec<String> {
+        if self.job.is_some() {
+            return vec![String::from("export: checksums are still computed")];
+        }
+        let path = self.base.join(name.trim());
+        let mut out = String::new();
+        for row in self.rows.iter() {
+            if let Some(sum) = &row.checksum {
+                out += &format!("{}  {}\n", sum, row.name);
+            }
+        }
+        match std::fs::write(&path, out) {
+            Ok(()) => {
+                self.exported = true;
+                vec![format!("Wrote {} checksums to {}", self.count(ChecksumStatus::Computed),
+                    path.to_string_lossy())]
+            },
+            Err(e) => vec![format!("Can't write {}: {}", path.to_string_lossy(), e)],
+        }
+    }
+}
+
+impl FmPage for ChecksumSheet {
+    fn len(&self) -> usize { self.rows.len() }
+    fn get_scroll_offs(&self) -> usize { self.cursor.scroll_offset }
+    fn is_cursor_idx(&self, idx: usize) -> bool { self.cursor.is_cursor_idx(idx) }
+    fn is_selected(&self, _idx: usize) -> bool { false }
+    fn is_highlighted(&self, idx: usize) -> bool {
+        match self.rows.get(idx).map(|r| r.status) {
+            Some(ChecksumStatus::Failed) | Some(ChecksumStatus::Missing) => true,
+            _ => false,
+        }
+    }
+    fn needs_repage(&self) -> bool { true }
+    fn needs_redraw(&self) -> bool { true }
+    fn sort_by_column(&mut self, _col_idx: usize) { }
+
+    fn set_render_feedback(&mut self, fb: RenderFeedback) {
+        self.render_feedback = fb;
+    }
+
+    fn is_inside_screen_rect(&self, x: i32, y: i32) -> bool {
+        self.render_feedback.is_inside_screen_rect(x, y)
+    }
+
+    fn cursor_path(&self) -> Option<std::path::PathBuf> {
+        self.rows.get(self.cursor.cursor_idx).map(|r| r.path.clone())
+    }
+
+    fn is_busy(&self) -> bool { self.job.is_some() }
+
+    fn update(&mut self) -> Option<PageEvent> {
+        if self.exported {
+            self.exported = false;
+            return Some(PageEvent::RefreshDirs);
+        }
+
+        let msgs = self.job.as_ref()?.poll();
+        if msgs.is_empty() {
+            return None;
+        }
+
+        let mut log = vec![];
+        for msg in msgs {
+            match msg {
+                ChecksumMsg::File(path) => {
+                    self.rows.push(ChecksumRow {
+                        name: path.strip_prefix(&self.base).unwrap_or(&path)
+                                  .to_string_lossy().to_string(),
+                        path,
+                        expected: None,
+                        checksum: None,
+                        status:   ChecksumStatus::Pending,
+                    });
+                },
+                ChecksumMsg::Result(idx, res) => {
+                    let row = &mut self.rows[idx];
+                    match res {
+                        Ok(sum) => {
+                            row.status =
+                                match &row.expected {
+                                    None                       => ChecksumStatus::Computed,
+                                    Some(exp) if *exp == sum   => ChecksumStatus::Ok,
+                                    Some(_)                    => ChecksumStatus::Failed,
+                                };
+                            row.checksum = Some(sum);
+                        },
+                        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                            row.status = ChecksumStatus::Missing;
+                        },
+                        Err(e) => {
+                            row.status = ChecksumStatus::Failed;
+                            log.push(format!("{}: {}", row.path.to_string_lossy(), e));
+                        },
+                    }
+                },
+                ChecksumMsg::Done => self.job = None,
+            }
+        }
+
+        if log.is_empty() {
+            Some(PageEvent::Redraw)
+        } else {
+            Some(PageEvent::Log(log))
+        }
+    }
+
+    fn do_control(&mut self, ctrl: PageControl) -> Option<PageEvent> {
+        match ctrl {
+            PageControl::Back => Some(PageEvent::Close),
+            PageControl::Access => Some(PageEvent::ShowEntry(self.cursor_path()?)),
+            PageControl::CycleMode if self.verify.is_none() => {
+                Some(PageEvent::Prompt(
+                    String::from("export checksums to: "),
+                    format!("checksums.{}", self.algo.extension())))
+            },
+            PageControl::Edit(name) => Some(PageEvent::Log(self.export(&name))),
+            _ => {
+                self.cursor.do_control(self.rows.len(), &self.render_feedback, ctrl);
+                None
+            },
+        }
+    }
+
+    fn as_drawable_table(&mut self) -> TableRef {
+        let pending = self.count(ChecksumStatus::Pending);
+        let title =
+            match &self.verify {
+                Some(file) => format!("verify {} [{}: {} OK, {} FAILED, {} MISSING, {} pending]",
+                    file.to_string_lossy(), self.algo.name(),
+                    self.count(ChecksumStatus::Ok), self.count(ChecksumStatus::Failed),
+                    self.count(ChecksumStatus::Missing), pending),
+                None => format!("{} checksums in {} [{} files, {} pending]",
+                    self.algo.name(), self.base.to_string_lossy(), self.rows.len(), pending),
+            };
+
+        self.rendered =
+            std::rc::Rc::new(std::cell::RefCell::new(Table {
+                title,
+                row_gap: 2,
+                col_gap: 4,
+                columns: vec![
+                    Column {
+                        head: String::from("name"),
+                        size: ColumnSizing::ExpandFract(1),
+                        calc_size: None,
+                        rows: self.rows.iter().map(|r| StyleString {
+                            text: r.name.clone(),
+                            style: Style::File,
+                            highlight: vec![],
+                        }).collect(),
+                    },
+                    Column {
+                        head: String::from("status"),
+                        size: ColumnSizing::TextWidth(String::from("MMMMMMM")),
+                        calc_size: None,
+                        rows: self.rows.iter().map(|r| StyleString {
+                            text: String::from(match r.status {
+                                ChecksumStatus::Pending  => "...",
+                                ChecksumStatus::Computed => "",
+                                ChecksumStatus::Ok       => "OK",
+                                ChecksumStatus::Failed   => "FAILED",
+                                ChecksumStatus::Missing  => "MISSING",
+                            }),
+                            style: match r.status {
+                                ChecksumStatus::Ok      => Style::Success,
+                                ChecksumStatus::Failed
+                                | ChecksumStatus::Missing => Style::Error,
+                                _                       => Style::Default,
+                            },
+                            highlight: vec![],
+                        }).collect(),
+                    },
+                    Column {
+                        head: String::from(self.algo.name()),
+                        size: ColumnSizing::ExpandFract(1),
+                        calc_size: None,
+                        rows: self.rows.iter().map(|r| StyleString {
+                            text: r.checksum.clone().unwrap_or_default(),
+                            style: Style::Default,
+                            highlight: vec![],
+                        }).collect(),
+                    },
+                ],
+            }));
+        self.rendered.clone()
+    }
+}
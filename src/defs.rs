@@ -13,7 +13,22 @@ pub const SLCT_FG_COLOR  : Color = Color { r:   0, g:   0, b:   0, a: 0xff };
 pub const SCRIND_COLOR   : Color = Color { r:  96, g: 255, b:  96, a: 0xff };
 pub const DIR_FG_COLOR   : Color = Color { r:  64, g: 255, b: 255, a: 0xff };
 pub const LNK_FG_COLOR   : Color = Color { r: 255, g: 128, b: 255, a: 0xff };
+pub const IGN_FG_COLOR   : Color = Color { r: 128, g: 128, b: 128, a: 0xff };
+pub const EXE_FG_COLOR   : Color = Color { r:  96, g: 255, b:  96, a: 0xff };
+pub const ARC_FG_COLOR   : Color = Color { r: 255, g:  96, b:  96, a: 0xff };
+pub const IMG_FG_COLOR   : Color = Color { r: 224, g: 160, b: 255, a: 0xff };
+pub const MED_FG_COLOR   : Color = Color { r: 255, g: 192, b: 112, a: 0xff };
+pub const SRC_FG_COLOR   : Color = Color { r: 255, g: 255, b: 160, a: 0xff };
+pub const HID_FG_COLOR   : Color = Color { r: 160, g: 160, b: 160, a: 0xff };
+pub const ORPH_FG_COLOR  : Color = Color { r: 255, g:  64, b:  64, a: 0xff };
+pub const SPCL_FG_COLOR  : Color = Color { r: 255, g: 255, b:  64, a: 0xff };
+pub const SUID_FG_COLOR  : Color = Color { r: 255, g: 160, b: 160, a: 0xff };
+pub const STKY_FG_COLOR  : Color = Color { r:  96, g: 160, b: 255, a: 0xff };
+pub const ADD_FG_COLOR   : Color = Color { r:  96, g: 255, b:  96, a: 0xff };
+pub const DEL_FG_COLOR   : Color = Color { r: 255, g:  96, b:  96, a: 0xff };
+pub const CHG_FG_COLOR   : Color = Color { r: 255, g: 255, b:  96, a: 0xff };
 pub const DIVIDER_COLOR  : Color = Color { r:  34, g:  69, b:  34, a: 0xff };
+pub const BAR_BG_COLOR   : Color = Color { r:  34, g:  90, b: 110, a: 0xff };
 
 pub const MIN_EXPAND_WIDTH : i32 = 50;
 
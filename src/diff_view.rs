@@ -0,0 +1,440 @@
+use crate::fm_page::*;
+use crate::cursor::PageCursor;
+use crate::text_view::{TextFile, is_binary_file};
+use std::io::Read;
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+
+/// Above this many cells the LCS table is not computed, the differing
+/// middle part of the files is shown as one changed hunk instead.
+const MAX_LCS_CELLS : usize = 16 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DiffKind {
+    Same,
+    Changed,
+    Removed,
+    Added,
+}
+
+/// One aligned row, with the line numbers (starting at 1) and texts of
+/// both sides.
+#[derive(Debug, Clone)]
+pub struct DiffRow {
+    pub kind:  DiffKind,
+    pub left:  Option<(usize, String)>,
+    pub right: Option<(usize, String)>,
+}
+
+fn read_lines(path: &std::path::Path) -> std::io::Result<Vec<String>> {
+    let tf = TextFile::open(path)?;
+    let mut rd    = tf.reader_at(tf.data_start)?;
+    let mut buf   = vec![];
+    let mut lines = vec![];
+    while tf.read_line_bytes(&mut rd, &mut buf)? {
+        lines.push(tf.encoding.decode(&buf));
+    }
+    Ok(lines)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Equal,
+    Delete,
+    Insert,
+}
+
+/// Computes the edit script between two line lists, with the longest
+/// common subsequence of the lines between the common prefix and
+/// suffix.
+fn diff_ops(a: &[String], b: &[String]) -> Vec<Op> {
+    let prefix = a.iter().zip(b.iter()).take_while(|(x, y)| x == y).count();
+    let suffix =
+        a[prefix..].iter().rev().zip(b[prefix..].iter().rev())
+            .take_while(|(x, y)| x == y).count();
+    let a_mid = &a[prefix..(a.len() - suffix)];
+    let b_mid = &b[prefix..(b.len() - suffix)];
+
+    let mut ops = vec![Op::Equal; prefix];
+    let (n, m) = (a_mid.len(), b_mid.len());
+    if n * m > MAX_LCS_CELLS {
+        ops.extend(std::iter::repeat_n(Op::Delete, n));
+        ops.extend(std::iter::repeat_n(Op::Insert, m));
+    } else {
+        // lcs[i][j] is the LCS length of a_mid[i..] and b_mid[j..]:
+        let mut lcs = vec![0u32; (n + 1) * (m + 1)];
+        for i in (0..n).rev() {
+            for j in (0..m).rev() {
+                lcs[i * (m + 1) + j] =
+                    if a_mid[i] == b_mid[j] {
+                        lcs[(i + 1) * (m + 1) + j + 1] + 1
+                    } else {
+                        lcs[(i + 1) * (m + 1) + j].max(lcs[i * (m + 1) + j + 1])
+                    };
+            }
+        }
+
+        let (mut i, mut j) = (0, 0);
+        while i < n || j < m {
+            if i < n && j < m && a_mid[i] == b_mid[j] {
+                ops.push(Op::Equal);
+                i += 1;
+                j += 1;
+            } else if j < m && (i == n || lcs[i * (m + 1) + j + 1] >= lcs[(i + 1) * (m + 1) + j]) {
+                ops.push(Op::Insert);
+                j += 1;
+            } else {
+                ops.push(Op::Delete);
+                i += 1;
+            }
+        }
+    }
+    ops.extend(std::iter::repeat_n(Op::Equal, suffix));
+    ops
+}
+
+/// Aligns the lines of both texts. Removed and added lines between two
+/// common lines are paired up as changed lines.
+pub fn diff_rows(a: &[String], b: &[String]) -> Vec<DiffRow> {
+    let mut rows = vec![];
+    let (mut i, mut j) = (0, 0);
+    let mut removed = vec![];
+    let mut added   = vec![];
+
+    let flush = |rows: &mut Vec<DiffRow>, removed: &mut Vec<usize>, added: &mut Vec<usize>| {
+        for k in 0..removed.len().max(added.len()) {
+            let left  = removed.get(k).map(|i| (*i + 1, a[*i].clone()));
+            let right = added.get(k).map(|j| (*j + 1, b[*j].clone()));
+            let kind =
+                match (&left, &right) {
+                    (Some(_), Some(_)) => DiffKind::Changed,
+                    (Some(_), None)    => DiffKind::Removed,
+                    _                  => DiffKind::Added,
+                };
+            rows.push(DiffRow { kind, left, right });
+        }
+        removed.clear();
+        added.clear();
+    };
+
+    for op in diff_ops(a, b) {
+        match op {
+            Op::Equal => {
+                flush(&mut rows, &mut removed, &mut added);
+                rows.push(DiffRow {
+                    kind:  DiffKind::Same,
+                    left:  Some((i + 1, a[i].clone())),
+                    right: Some((j + 1, b[j].clone())),
+                });
+                i += 1;
+                j += 1;
+            },
+            Op::Delete => { removed.push(i); i += 1; },
+            Op::Insert => { added.push(j);   j += 1; },
+        }
+    }
+    flush(&mut rows, &mut removed, &mut added);
+    rows
+}
+
+/// The character range of `s` that differs from `other`, without their
+/// common prefix and suffix.
+fn changed_range(s: &str, other: &str) -> Vec<(usize, usize)> {
+    let a : Vec<char> = s.chars().collect();
+    let b : Vec<char> = other.chars().collect();
+    let prefix = a.iter().zip(b.iter()).take_while(|(x, y)| x == y).count();
+    let suffix =
+        a[prefix..].iter().rev().zip(b[prefix..].iter().rev())
+            .take_while(|(x, y)| x == y).count();
+    if prefix + suffix >= a.len() {
+        return vec![];
+    }
+    vec![(prefix, a.len() - suffix)]
+}
+
+/// Reads until `buf` is full or the end of the file is reached.
+fn read_chunk<R: Read>(rd: &mut R, buf: &mut [u8]) -> std::io::Result<usize> {
+    let mut len = 0;
+    while len < buf.len() {
+        match rd.read(&mut buf[len..])? {
+            0 => break,
+            n => len += n,
+        }
+    }
+    Ok(len)
+}
+
+/// Compares two binary files. Returns `None` if they are equal, or the
+/// offset of the first differing byte. If one file is a prefix of the
+/// other, that is the length of the shorter one.
+pub fn first_difference(a: &std::path::Path, b: &std::path::Path) -> std::io::Result<Option<u64>> {
+    let mut fa = std::fs::File::open(a)?;
+    let mut fb = std::fs::File::open(b)?;
+    let mut ba = vec![0; 64 * 1024];
+    let mut bb = vec![0; 64 * 1024];
+    let mut offs = 0;
+    loop {
+        let na = read_chunk(&mut fa, &mut ba)?;
+        let nb = read_chunk(&mut fb, &mut bb)?;
+        if let Some(pos) = ba[0..na].iter().zip(bb[0..nb].iter()).position(|(x, y)| x != y) {
+            return Ok(Some(offs + pos as u64));
+        }
+        if na != nb {
+            return Ok(Some(offs + na.min(nb) as u64));
+        }
+        if na == 0 {
+            return Ok(None);
+        }
+        offs += na as u64;
+    }
+}
+
+pub enum DiffResult {
+    /// The aligned lines of two text files.
+    Rows(Vec<DiffRow>),
+    /// The outcome of comparing binary files.
+    Report(String),
+}
+
+/// Compares two files: text files are diffed line by line, for binary
+/// files the offset of the first difference is reported.
+pub fn compare_files(left: &std::path::Path, right: &std::path::Path)
+    -> std::io::Result<DiffResult> {
+
+    if is_binary_file(left)? || is_binary_file(right)? {
+        let report =
+            match first_difference(left, right)? {
+                Some(offs) => format!("{} and {} differ at byte offset {} (0x{:x})",
+                    left.to_string_lossy(), right.to_string_lossy(), offs, offs),
+                None => format!("{} and {} are identical",
+                    left.to_string_lossy(), right.to_string_lossy()),
+            };
+        return Ok(DiffResult::Report(report));
+    }
+    Ok(DiffResult::Rows(diff_rows(&read_lines(left)?, &read_lines(right)?)))
+}
+
+/// Compares two files on a thread, reading them and aligning their lines
+/// can take a while for big files.
+pub struct DiffJob {
+    rx: Receiver<std::io::Result<DiffResult>>,
+}
+
+impl DiffJob {
+    pub fn start(left: &std::path::Path, right: &std::path::Path) -> DiffJob {
+        let (tx, rx) = channel();
+        let (left, right) = (left.to_path_buf(), right.to_path_buf());
+        std::thread::spawn(move || {
+            let _ = tx.send(compare_files(&left, &right));
+        });
+        DiffJob { rx }
+    }
+
+    /// Returns the result once the job is done.
+    pub fn poll(&self) -> Option<std::io::Result<DiffResult>> {
+        match self.rx.try_recv() {
+            Ok(res) => Some(res),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) =>
+                Some(Err(std::io::Error::new(std::io::ErrorKind::Other, "compare job stopped"))),
+        }
+    }
+}
+
+/// Shows two text files side by side, aligned line by line.
+pub struct DiffSheet {
+    pub left:            std::path::PathBuf,
+    pub right:           std::path::PathBuf,
+    pub rows:            Vec<DiffRow>,
+    pub job:             Option<DiffJob>,
+    pub status:          String,
+    pub render_feedback: RenderFeedback,
+    pub cursor:          PageCursor,
+    pub rendered:        TableRef,
+}
+
+impl DiffSheet {
+    /// Starts comparing two files, the page fills in when the job is
+    /// done. For binary files it only shows the report.
+    pub fn compare(left: &std::path::Path, right: &std::path::Path) -> DiffSheet {
+        DiffSheet {
+            left:            left.to_path_buf(),
+            right:           right.to_path_buf(),
+            rows:            vec![],
+            job:             Some(DiffJob::start(left, right)),
+            status:          String::from("comparing..."),
+            render_feedback: RenderFeedback::new(),
+            cursor:          PageCursor::new(),
+            rendered:        Table::new_ref(),
+        }
+    }
+
+    fn hunk_count(&self) -> usize {
+        let mut count = 0;
+        let mut in_hunk = false;
+        for row in self.rows.iter() {
+            let changed = row.kind != DiffKind::Same;
+            if changed && !in_hunk {
+                count += 1;
+            }
+            in_hunk = changed;
+        }
+        count
+    }
+
+    /// Moves the cursor to the start of the next block of differing
+    /// lines.
+    fn next_hunk(&mut self) {
+        let start = self.cursor.cursor_idx;
+        let mut idx = start;
+        // Skip the rest of the hunk the cursor is in:
+        while idx < self.rows.len() && self.rows[idx].kind != DiffKind::Same {
+            idx += 1;
+        }
+        while idx < self.rows.len() && self.rows[idx].kind == DiffKind::Same {
+            idx += 1;
+        }
+
+        if idx < self.rows.len() {
+            self.status = String::new();
+            self.cursor.cursor_idx = idx;
+            self.cursor.do_control(self.rows.len(), &self.render_feedback, PageControl::Refresh);
+        } else {
+            self.status = String::from("no more differences");
+        }
+    }
+}
+
+impl FmPage for DiffSheet {
+    fn len(&self) -> usize { self.rows.len() }
+    fn get_scroll_offs(&self) -> usize { self.cursor.scroll_offset }
+    fn is_cursor_idx(&self, idx: usize) -> bool { self.cursor.is_cursor_idx(idx) }
+    fn is_selected(&self, _idx: usize) -> bool { false }
+    fn is_highlighted(&self, _idx: usize) -> bool { false }
+    fn needs_repage(&self) -> bool { true }
+    fn needs_redraw(&self) -> bool { true }
+    fn sort_by_column(&mut self, _col_idx: usize) { }
+
+    fn set_render_feedback(&mut self, fb: RenderFeedback) {
+        self.render_feedback = fb;
+    }
+
+    fn is_inside_screen_rect(&self, x: i32, y: i32) -> bool {
+        self.render_feedback.is_inside_screen_rect(x, y)
+    }
+
+    fn cursor_path(&self) -> Option<std::path::PathBuf> {
+        let row = self.rows.get(self.cursor.cursor_idx)?;
+        if row.left.is_some() { Some(self.left.clone()) } else { Some(self.right.clone()) }
+    }
+
+    fn cursor_line(&self) -> Option<usize> {
+        let row = self.rows.get(self.cursor.cursor_idx)?;
+        row.left.as_ref().or_else(|| row.right.as_ref()).map(|(line, _)| *line)
+    }
+
+    fn is_busy(&self) -> bool { self.job.is_some() }
+
+    fn update(&mut self) -> Option<PageEvent> {
+        let res = self.job.as_ref()?.poll()?;
+        self.job    = None;
+        self.status = String::new();
+        match res {
+            Ok(DiffResult::Rows(rows)) => {
+                self.rows = rows;
+                self.cursor.do_control(self.rows.len(), &self.render_feedback, PageControl::Refresh);
+                Some(PageEvent::Redraw)
+            },
+            Ok(DiffResult::Report(report)) => {
+                self.status = report.clone();
+                Some(PageEvent::Log(vec![report]))
+            },
+            Err(e) => {
+                self.status = e.to_string();
+                Some(PageEvent::Log(vec![format!("compare: {}", e)]))
+            },
+        }
+    }
+
+    fn do_control(&mut self, ctrl: PageControl) -> Option<PageEvent> {
+        match ctrl {
+            PageControl::Back => Some(PageEvent::Close),
+            PageControl::SearchNext => {
+                self.next_hunk();
+                None
+            },
+            _ => {
+                self.cursor.do_control(self.rows.len(), &self.render_feedback, ctrl);
+                None
+            },
+        }
+    }
+
+    fn as_drawable_table(&mut self) -> TableRef {
+        let mut title = format!("diff {} {} [{} hunks]",
+            self.left.to_string_lossy(), self.right.to_string_lossy(), self.hunk_count());
+        if !self.status.is_empty() {
+            title += " ";
+            title += &self.status;
+        }
+
+        let style = |kind: DiffKind, left: bool| {
+            match kind {
+                DiffKind::Same                => Style::Default,
+                DiffKind::Changed             => Style::Changed,
+                DiffKind::Removed if left     => Style::Removed,
+                DiffKind::Added   if !left    => Style::Added,
+                _                             => Style::Default,
+            }
+        };
+        let line_col = |head: &str, side: Vec<Option<&(usize, String)>>| Column {
+            head: head.to_string(),
+            size: ColumnSizing::TextWidth(String::from("MMMMM")),
+            calc_size: None,
+            rows: side.iter().map(|l| StyleString {
+                text: l.map(|(n, _)| n.to_string()).unwrap_or_default(),
+                style: Style::Default,
+                highlight: vec![],
+            }).collect(),
+        };
+
+        let left  : Vec<Option<&(usize, String)>> = self.rows.iter().map(|r| r.left.as_ref()).collect();
+        let right : Vec<Option<&(usize, String)>> = self.rows.iter().map(|r| r.right.as_ref()).collect();
+
+        let text_rows = |left_side: bool| -> Vec<StyleString> {
+            self.rows.iter().map(|r| {
+                let (this, other) = if left_side { (&r.left, &r.right) } else { (&r.right, &r.left) };
+                let text = this.as_ref().map(|(_, t)| t.clone()).unwrap_or_default();
+                let highlight =
+                    match (r.kind, other) {
+                        (DiffKind::Changed, Some((_, o))) => changed_range(&text, o),
+                        _ => vec![],
+                    };
+                StyleString { text, style: style(r.kind, left_side), highlight }
+            }).collect()
+        };
+
+        self.rendered =
+            std::rc::Rc::new(std::cell::RefCell::new(Table {
+                title,
+                row_gap: 2,
+                col_gap: 4,
+                columns: vec![
+                    line_col("#", left),
+                    Column {
+                        head: self.left.file_name().unwrap_or_default().to_string_lossy().to_string(),
+                        size: ColumnSizing::ExpandFract(1),
+                        calc_size: None,
+                        rows: text_rows(true),
+                    },
+                    line_col("#", right),
+                    Column {
+                        head: self.right.file_name().unwrap_or_default().to_string_lossy().to_string(),
+                        size: ColumnSizing::ExpandFract(1),
+                        calc_size: None,
+                        rows: text_rows(false),
+                    },
+                ],
+            }));
+        self.rendered.clone()
+    }
+}
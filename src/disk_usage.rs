@@ -0,0 +1,513 @@
+use crate::fm_page::*;
+use crate::cursor::PageCursor;
+use crate::path_sheet::format_size;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+
+/// The scan reports its progress after this many entries.
+const PROGRESS_INTERVAL : u64 = 1000;
+
+#[derive(Debug, Clone)]
+pub struct DuNode {
+    pub path:     std::path::PathBuf,
+    pub is_dir:   bool,
+    /// Total size of the entry and everything below it.
+    pub size:     u64,
+    /// Number of files below it, or 1 for a file.
+    pub files:    u64,
+    pub parent:   Option<usize>,
+    pub children: Vec<usize>,
+    /// Set when the entry was deleted, its size is subtracted from the
+    /// parents then.
+    pub removed:  bool,
+}
+
+/// A scanned directory tree. The nodes are stored in a flat list, with
+/// children always after their parents. Node 0 is the root.
+#[derive(Debug, Clone, Default)]
+pub struct DuTree {
+    pub nodes:  Vec<DuNode>,
+    pub errors: Vec<String>,
+}
+
+impl DuTree {
+    /// Marks a node as removed and subtracts it from the totals of all
+    /// parents.
+    pub fn remove(&mut self, idx: usize) {
+        if self.nodes[idx].removed {
+            return;
+        }
+        self.nodes[idx].removed = true;
+        let (size, files) = (self.nodes[idx].size, self.nodes[idx].files);
+
+        if let Some(parent) = self.nodes[idx].parent {
+            self.nodes[parent].children.retain(|c| *c != idx);
+        }
+        let mut cur = self.nodes[idx].parent;
+        while let Some(p) = cur {
+            let node = &mut self.nodes[p];
+            node.size  = node.size.saturating_sub(size);
+            node.files = node.files.saturating_sub(files);
+            cur = node.parent;
+        }
+    }
+}
+
+/// The space an entry takes on disk, which is less than its length for
+/// sparse files and more for small ones.
+#[cfg(unix)]
+fn disk_size(md: &std::fs::Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    md.blocks() * 512
+}
+
+#[cfg(not(unix))]
+fn disk_size(md: &std::fs::Metadata) -> u64 { md.len() }
+
+/// The device of an entry, to stay on the file system of the root.
+#[cfg(unix)]
+fn device(md: &std::fs::Metadata) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    Some(md.dev())
+}
+
+#[cfg(not(unix))]
+fn device(_md: &std::fs::Metadata) -> Option<u64> { None }
+
+/// The device and inode of a file with more than one hard link, so the
+/// links are counted once.
+#[cfg(unix)]
+fn hard_link_id(md: &std::fs::Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    if md.nlink() > 1 && !md.is_dir() { Some((md.dev(), md.ino())) } else { None }
+}
+
+#[cfg(not(unix))]
+fn hard_link_id(_md: &std::fs::Metadata) -> Option<(u64, u64)> { None }
+
+/// Scans the tree like `du -x`: sizes are the allocated disk space, hard
+/// links count once and mount points are listed but not entered.
+fn scan_tree<F>(root: &std::path::Path, stop: &AtomicBool, progress: &mut F) -> DuTree
+    where F: FnMut(u64, u64) {
+
+    let mut tree = DuTree::default();
+    tree.nodes.push(DuNode {
+        path:     root.to_path_buf(),
+        is_dir:   true,
+        size:     0,
+        files:    0,
+        parent:   None,
+        children: vec![],
+        removed:  false,
+    });
+
+    let root_dev  = root.symlink_metadata().ok().and_then(|md| device(&md));
+    let mut seen  = std::collections::HashSet::new();
+    let mut count = 0;
+    let mut bytes = 0;
+    let mut dirs  = vec![0];
+    while let Some(dir_idx) = dirs.pop() {
+        if stop.load(Ordering::Relaxed) {
+            break;
+        }
+        let rd =
+            match std::fs::read_dir(&tree.nodes[dir_idx].path) {
+                Ok(rd) => rd,
+                Err(e) => {
+                    tree.errors.push(
+                        format!("{}: {}", tree.nodes[dir_idx].path.to_string_lossy(), e));
+                    continue;
+                },
+            };
+
+        for entry in rd {
+            let entry = match entry { Ok(e) => e, Err(_) => continue };
+            let path  = entry.path();
+            // Symlinks are not followed, only the link itself counts:
+            let md = match path.symlink_metadata() {
+                Ok(md) => md,
+                Err(e) => {
+                    tree.errors.push(format!("{}: {}", path.to_string_lossy(), e));
+                    continue;
+                },
+            };
+            let is_dir = md.file_type().is_dir();
+            let size =
+                match hard_link_id(&md) {
+                    Some(id) if !seen.insert(id) => 0,
+                    _ => disk_size(&md),
+                };
+
+            let idx = tree.nodes.len();
+            tree.nodes.push(DuNode {
+                path,
+                is_dir,
+                size,
+                files:    if is_dir { 0 } else { 1 },
+                parent:   Some(dir_idx),
+                children: vec![],
+                removed:  false,
+            });
+            tree.nodes[dir_idx].children.push(idx);
+            if is_dir && device(&md) == root_dev {
+                dirs.push(idx);
+            }
+
+            count += 1;
+            bytes += size;
+            if count % PROGRESS_INTERVAL == 0 {
+                progress(count, bytes);
+            }
+        }
+    }
+
+    // Children come after their parents, so going backwards adds every
+    // total before it is added to the parent:
+    for idx in (1..tree.nodes.len()).rev() {
+        let (size, files) = (tree.nodes[idx].size, tree.nodes[idx].files);
+        if let Some(p) = tree.nodes[idx].parent {
+            tree.nodes[p].size  += size;
+            tree.nodes[p].files += files;
+        }
+    }
+    tree
+}
+
+pub enum DuMsg {
+    /// Entries and bytes scanned so far.
+    Progress(u64, u64),
+    Done(DuTree),
+}
+
+/// Scans a directory tree on a thread. Dropping the job stops it.
+pub struct DuJob {
+    cancel: Arc<AtomicBool>,
+    rx:     Receiver<DuMsg>,
+}
+
+impl DuJob {
+    pub fn start(root: &std::path::Path) -> DuJob {
+        let cancel = Arc::new(AtomicBool::new(false));
+        let (tx, rx) = channel();
+
+        let root = root.to_path_buf();
+        let stop = cancel.clone();
+        std::thread::spawn(move || {
+            let ptx  = tx.clone();
+            let tree = scan_tree(&root, &stop, &mut |count, bytes| {
+                let _ = ptx.send(DuMsg::Progress(count, bytes));
+            });
+            let _ = tx.send(DuMsg::Done(tree));
+        });
+
+        DuJob { cancel, rx }
+    }
+
+    pub fn poll(&self) -> Vec<DuMsg> {
+        let mut msgs = vec![];
+        loop {
+            match self.rx.try_recv() {
+                Ok(DuMsg::Done(tree)) => {
+                    msgs.push(DuMsg::Done(tree));
+                    break;
+                },
+                Ok(msg) => msgs.push(msg),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    msgs.push(DuMsg::Done(DuTree::default()));
+                    break;
+                },
+            }
+        }
+        msgs
+    }
+}
+
+impl Drop for DuJob {
+    fn drop(&mut self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Lists the entries of a directory by their total size, ncdu style.
+/// The tree is scanned once, Access and Back move around in it.
+pub struct DiskUsageSheet {
+    pub root:            std::path::PathBuf,
+    pub tree:            DuTree,
+    pub job:             Option<DuJob>,
+    pub progress:        (u64, u64),
+    /// The node whose children are listed.
+    pub current:         usize,
+    /// The listed child nodes, largest first.
+    pub rows:            Vec<usize>,
+    pub sort_by_name:    bool,
+    pub selection:       std::collections::HashSet<usize>,
+    pub render_feedback: RenderFeedback,
+    pub cursor:          PageCursor,
+    pub rendered:        TableRef,
+}
+
+impl DiskUsageSheet {
+    pub fn new(root: &std::path::Path) -> Self {
+        DiskUsageSheet {
+            root:            root.to_path_buf(),
+            tree:            DuTree::default(),
+            job:             Some(DuJob::start(root)),
+            progress:        (0, 0),
+            current:         0,
+            rows:            vec![],
+            sort_by_name:    false,
+            selection:       std::collections::HashSet::new(),
+            render_feedback: RenderFeedback::new(),
+            cursor:          PageCursor::new(),
+            rendered:        Table::new_ref(),
+        }
+    }
+
+    /// Lists the children of `node` and puts the cursor on `cursor`, or
+    /// on the first row.
+    fn show(&mut self, node: usize, cursor: Option<usize>) {
+        self.current = node;
+        self.selection.clear();
+        self.update_rows();
+        self.cursor.cursor_idx =
+            cursor.and_then(|c| self.rows.iter().position(|r| *r == c)).unwrap_or(0);
+        self.cursor.scroll_offset = 0;
+        self.cursor.do_control(self.rows.len(), &self.render_feedback, PageControl::Refresh);
+    }
+
+    fn update_rows(&mut self) {
+        let mut rows = match self.tree.nodes.get(self.current) {
+            Some(node) => node.children.clone(),
+            None       => vec![],
+        };
+        let nodes = &self.tree.nodes;
+        if self.sort_by_name {
+            rows.sort_by_key(|i| {
+                nodes[*i].path.file_name().unwrap_or_default().to_string_lossy().to_lowercase()
+            });
+        } else {
+            rows.sort_by(|a, b| nodes[*b].size.cmp(&nodes[*a].size));
+        }
+        self.rows = rows;
+    }
+
+    /// Removes the listed entries that were deleted from the tree, which
+    /// updates the totals of the parents.
+    fn drop_vanished(&mut self) {
+        let vanished : Vec<usize> =
+            self.rows.iter().copied()
+                .filter(|i| self.tree.nodes[*i].path.symlink_metadata().is_err())
+                .collect();
+        if vanished.is_empty() {
+            return;
+        }
+
+        let cursor = self.rows.get(self.cursor.cursor_idx).copied();
+        for idx in vanished {
+            self.tree.remove(idx);
+        }
+        let selected : Vec<usize> =
+            self.selection.iter().filter_map(|i| self.rows.get(*i).copied()).collect();
+        self.update_rows();
+        self.selection =
+            self.rows.iter().enumerate()
+                .filter(|(_, n)| selected.contains(n))
+                .map(|(i, _)| i)
+                .collect();
+        if let Some(pos) = cursor.and_then(|c| self.rows.iter().position(|r| *r == c)) {
+            self.cursor.cursor_idx = pos;
+        }
+    }
+}
+
+impl FmPage for DiskUsageSheet {
+    fn len(&self) -> usize { self.rows.len() }
+    fn get_scroll_offs(&self) -> usize { self.cursor.scroll_offset }
+    fn is_cursor_idx(&self, idx: usize) -> bool { self.cursor.is_cursor_idx(idx) }
+    fn is_selected(&self, idx: usize) -> bool { self.selection.contains(&idx) }
+    fn is_highlighted(&self, _idx: usize) -> bool { false }
+    fn needs_repage(&self) -> bool { true }
+    fn needs_redraw(&self) -> bool { true }
+
+    fn sort_by_column(&mut self, col_idx: usize) {
+        self.sort_by_name = col_idx == 0;
+        let current = self.current;
+        let cursor  = self.rows.get(self.cursor.cursor_idx).copied();
+        self.show(current, cursor);
+    }
+
+    fn set_render_feedback(&mut self, fb: RenderFeedback) {
+        self.render_feedback = fb;
+    }
+
+    fn is_inside_screen_rect(&self, x: i32, y: i32) -> bool {
+        self.render_feedback.is_inside_screen_rect(x, y)
+    }
+
+    fn cursor_path(&self) -> Option<std::path::PathBuf> {
+        let idx = self.rows.get(self.cursor.cursor_idx)?;
+        Some(self.tree.nodes[*idx].path.clone())
+    }
+
+    fn selected_paths(&self) -> Vec<std::path::PathBuf> {
+        let mut idxs : Vec<usize> = self.selection.iter().copied().collect();
+        idxs.sort();
+        idxs.iter()
+            .filter_map(|i| self.rows.get(*i))
+            .map(|n| self.tree.nodes[*n].path.clone())
+            .collect()
+    }
+
+    fn clear_selection(&mut self) {
+        self.selection.clear();
+    }
+
+    fn is_busy(&self) -> bool { self.job.is_some() }
+
+    fn update(&mut self) -> Option<PageEvent> {
+        let msgs = self.job.as_ref()?.poll();
+        if msgs.is_empty() {
+            return None;
+        }
+
+        let mut log = vec![];
+        for msg in msgs {
+            match msg {
+                DuMsg::Progress(count, bytes) => self.progress = (count, bytes),
+                DuMsg::Done(tree) => {
+                    self.job  = None;
+                    self.tree = tree;
+                    log.extend(self.tree.errors.iter().cloned());
+                    self.show(0, None);
+                },
+            }
+        }
+
+        if log.is_empty() {
+            Some(PageEvent::Redraw)
+        } else {
+            Some(PageEvent::Log(log))
+        }
+    }
+
+    fn do_control(&mut self, ctrl: PageControl) -> Option<PageEvent> {
+        match ctrl {
+            PageControl::Access => {
+                let idx = *self.rows.get(self.cursor.cursor_idx)?;
+                if self.tree.nodes[idx].is_dir {
+                    self.show(idx, None);
+                }
+                None
+            },
+            PageControl::Back => {
+                match self.tree.nodes.get(self.current).and_then(|n| n.parent) {
+                    Some(parent) => {
+                        let current = self.current;
+                        self.show(parent, Some(current));
+                        None
+                    },
+                    None => Some(PageEvent::Close),
+                }
+            },
+            PageControl::ToggleSelect => {
+                let idx = self.cursor.cursor_idx;
+                if idx < self.rows.len() && !self.selection.remove(&idx) {
+                    self.selection.insert(idx);
+                }
+                self.cursor.do_control(self.rows.len(), &self.render_feedback, PageControl::CursorDown);
+                None
+            },
+            PageControl::Refresh => {
+                self.drop_vanished();
+                self.cursor.do_control(self.rows.len(), &self.render_feedback, ctrl);
+                None
+            },
+            _ => {
+                self.cursor.do_control(self.rows.len(), &self.render_feedback, ctrl);
+                None
+            },
+        }
+    }
+
+    fn as_drawable_table(&mut self) -> TableRef {
+        let title =
+            match self.tree.nodes.get(self.current) {
+                _ if self.job.is_some() => {
+                    format!("du {} [scanning... {} entries, {}]",
+                        self.root.to_string_lossy(),
+                        self.progress.0, format_size(self.progress.1).trim())
+                },
+                Some(node) => {
+                    format!("du {} [{}, {} files]",
+                        node.path.to_string_lossy(),
+                        format_size(node.size).trim(), node.files)
+                },
+                None => format!("du {}", self.root.to_string_lossy()),
+            };
+
+        let total = self.tree.nodes.get(self.current).map(|n| n.size).unwrap_or(0);
+        let nodes : Vec<&DuNode> = self.rows.iter().map(|i| &self.tree.nodes[*i]).collect();
+
+        self.rendered =
+            std::rc::Rc::new(std::cell::RefCell::new(Table {
+                title,
+                row_gap: 2,
+                col_gap: 4,
+                columns: vec![
+                    Column {
+                        head: String::from("name"),
+                        size: ColumnSizing::ExpandFract(1),
+                        calc_size: None,
+                        rows: nodes.iter().map(|n| {
+                            let mut name =
+                                n.path.file_name().unwrap_or_default().to_string_lossy().to_string();
+                            if n.is_dir {
+                                name += &std::path::MAIN_SEPARATOR.to_string();
+                            }
+                            StyleString {
+                                text: name,
+                                style: if n.is_dir { Style::Dir } else { Style::File },
+                                highlight: vec![],
+                            }
+                        }).collect(),
+                    },
+                    Column {
+                        head: String::from("size"),
+                        size: ColumnSizing::TextWidth(String::from("MMMMMMMM")),
+                        calc_size: None,
+                        rows: nodes.iter().map(|n| StyleString {
+                            text: format_size(n.size),
+                            style: Style::Default,
+                            highlight: vec![],
+                        }).collect(),
+                    },
+                    Column {
+                        head: String::from("usage"),
+                        size: ColumnSizing::TextWidth(String::from("MMMMMMMMMMMM")),
+                        calc_size: None,
+                        rows: nodes.iter().map(|n| {
+                            let frac = if total > 0 { n.size as f64 / total as f64 } else { 0.0 };
+                            StyleString {
+                                text: format!("{:5.1}%", frac * 100.0),
+                                style: Style::Bar(frac as f32),
+                                highlight: vec![],
+                            }
+                        }).collect(),
+                    },
+                    Column {
+                        head: String::from("files"),
+                        size: ColumnSizing::TextWidth(String::from("MMMMMMM")),
+                        calc_size: None,
+                        rows: nodes.iter().map(|n| StyleString {
+                            text: n.files.to_string(),
+                            style: Style::Default,
+                            highlight: vec![],
+                        }).collect(),
+                    },
+                ],
+            }));
+        self.rendered.clone()
+    }
+}
@@ -0,0 +1,355 @@
+use crate::fm_page::*;
+use crate::cursor::PageCursor;
+use crate::path_sheet::format_size;
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+
+/// The size of a file system in bytes.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FsSpace {
+    pub total: u64,
+    pub free:  u64,
+    /// The free space available to unprivileged users.
+    pub avail: u64,
+}
+
+impl FsSpace {
+    pub fn used(&self) -> u64 { self.total.saturating_sub(self.free) }
+
+    pub fn used_fraction(&self) -> f64 {
+        if self.total == 0 { 0.0 } else { self.used() as f64 / self.total as f64 }
+    }
+}
+
+/// Queries the size of the file system holding `path`.
+#[cfg(unix)]
+pub fn fs_space(path: &std::path::Path) -> std::io::Result<FsSpace> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let cpath =
+        std::ffi::CString::new(path.as_os_str().as_bytes())
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    let mut st : libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(cpath.as_ptr(), &mut st) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    let frsize = st.f_frsize as u64;
+    Ok(FsSpace {
+        total: st.f_blocks as u64 * frsize,
+        free:  st.f_bfree as u64 * frsize,
+        avail: st.f_bavail as u64 * frsize,
+    })
+}
+
+#[cfg(not(unix))]
+pub fn fs_space(_path: &std::path::Path) -> std::io::Result<FsSpace> {
+    Err(std::io::Error::new(std::io::ErrorKind::Other, "not supported on this platform"))
+}
+
+/// Queries the size of a file system on a thread, `statvfs` can hang
+/// on a network file system that went away.
+pub struct FsSpaceJob {
+    rx: Receiver<Option<FsSpace>>,
+}
+
+impl FsSpaceJob {
+    pub fn start(path: &std::path::Path) -> FsSpaceJob {
+        let (tx, rx) = channel();
+        let path = path.to_path_buf();
+        std::thread::spawn(move || {
+            let _ = tx.send(fs_space(&path).ok());
+        });
+        FsSpaceJob { rx }
+    }
+
+    /// Returns the result once the job is done.
+    pub fn poll(&self) -> Option<Option<FsSpace>> {
+        match self.rx.try_recv() {
+            Ok(space) => Some(space),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => Some(None),
+        }
+    }
+}
+
+/// A mounted file system, as listed in `/proc/self/mountinfo`.
+#[derive(Debug, Clone)]
+pub struct Mount {
+    pub mount_point: std::path::PathBuf,
+    pub device:      String,
+    pub fs_type:     String,
+    /// `None` until the size was queried.
+    pub space:       Option<FsSpace>,
+}
+
+impl Mount {
+    /// The size for sorting, a mount still queried counts as empty.
+    fn space_or_empty(&self) -> FsSpace { self.space.unwrap_or_default() }
+}
+
+/// Decodes the octal escapes like `\040` for a space in mountinfo
+/// fields.
+fn unescape_mount_field(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = vec![];
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'\\' && i + 3 < bytes.len() {
+            let code =
+                std::str::from_utf8(&bytes[(i + 1)..(i + 4)]).ok()
+                    .and_then(|o| u8::from_str_radix(o, 8).ok());
+            if let Some(code) = code {
+                out.push(code);
+                i += 4;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).to_string()
+}
+
+/// Parses one line of `/proc/self/mountinfo` into the mount point,
+/// device and file system type.
+pub fn parse_mountinfo_line(line: &str) -> Option<(String, String, String)> {
+    let fields : Vec<&str> = line.split(' ').collect();
+    let mount_point = fields.get(4)?;
+    // The optional fields end with a single "-":
+    let sep = fields.iter().skip(6).position(|f| *f == "-")? + 6;
+    let fs_type = fields.get(sep + 1)?;
+    let device  = fields.get(sep + 2)?;
+    Some((unescape_mount_field(mount_point),
+          unescape_mount_field(device),
+          fs_type.to_string()))
+}
+
+/// Reads the mounted file systems, without their sizes. Reading
+/// `/proc` doesn't block, but querying the sizes may.
+pub fn read_mounts() -> std::io::Result<Vec<Mount>> {
+    let info = std::fs::read_to_string("/proc/self/mountinfo")?;
+    let mut mounts = vec![];
+    for line in info.lines() {
+        let (mount_point, device, fs_type) =
+            match parse_mountinfo_line(line) {
+                Some(m) => m,
+                None    => continue,
+            };
+        mounts.push(Mount {
+            mount_point: std::path::PathBuf::from(mount_point),
+            device,
+            fs_type,
+            space: None,
+        });
+    }
+    Ok(mounts)
+}
+
+/// Lists the mounted file systems with their size and usage. The sizes
+/// are queried with one job per mount and filled in as they arrive, so
+/// a hanging network mount only leaves its own row empty. Pseudo file
+/// systems without any blocks, like `proc` or `cgroup`, are left out
+/// once their size is known.
+pub struct DrivesSheet {
+    pub mounts:          Vec<Mount>,
+    pub error:           Option<String>,
+    /// The size queries still running, by mount point.
+    pub jobs:            Vec<(std::path::PathBuf, FsSpaceJob)>,
+    pub render_feedback: RenderFeedback,
+    pub cursor:          PageCursor,
+    pub rendered:        TableRef,
+}
+
+impl DrivesSheet {
+    pub fn new() -> DrivesSheet {
+        let mut ds = DrivesSheet {
+            mounts:          vec![],
+            error:           None,
+            jobs:            vec![],
+            render_feedback: RenderFeedback::new(),
+            cursor:          PageCursor::new(),
+            rendered:        Table::new_ref(),
+        };
+        ds.reread();
+        ds
+    }
+
+    /// Reads the mounts again and starts querying their sizes. The old
+    /// sizes stay until the new ones arrive, and a mount whose last query
+    /// still hangs isn't queried again.
+    fn reread(&mut self) {
+        let mut mounts =
+            match read_mounts() {
+                Ok(mounts) => mounts,
+                Err(e) => {
+                    self.mounts = vec![];
+                    self.jobs   = vec![];
+                    self.error  = Some(e.to_string());
+                    return;
+                },
+            };
+        self.error = None;
+
+        for m in mounts.iter_mut() {
+            m.space =
+                self.mounts.iter()
+                    .find(|old| old.mount_point == m.mount_point)
+                    .and_then(|old| old.space);
+            if !self.jobs.iter().any(|(path, _)| *path == m.mount_point) {
+                self.jobs.push((m.mount_point.clone(), FsSpaceJob::start(&m.mount_point)));
+            }
+        }
+        self.jobs.retain(|(path, _)| mounts.iter().any(|m| m.mount_point == *path));
+        self.mounts = mounts;
+    }
+}
+
+impl FmPage for DrivesSheet {
+    fn len(&self) -> usize { self.mounts.len() }
+    fn get_scroll_offs(&self) -> usize { self.cursor.scroll_offset }
+    fn is_cursor_idx(&self, idx: usize) -> bool { self.cursor.is_cursor_idx(idx) }
+    fn is_selected(&self, _idx: usize) -> bool { false }
+    fn is_highlighted(&self, _idx: usize) -> bool { false }
+    fn needs_repage(&self) -> bool { true }
+    fn needs_redraw(&self) -> bool { true }
+    fn is_busy(&self) -> bool { !self.jobs.is_empty() }
+
+    fn update(&mut self) -> Option<PageEvent> {
+        let mut done = vec![];
+        self.jobs.retain(|(path, job)| {
+            match job.poll() {
+                Some(space) => { done.push((path.clone(), space)); false },
+                None        => true,
+            }
+        });
+        if done.is_empty() {
+            return None;
+        }
+
+        for (path, space) in done {
+            match space {
+                Some(space) if space.total > 0 => {
+                    if let Some(m) = self.mounts.iter_mut().find(|m| m.mount_point == path) {
+                        m.space = Some(space);
+                    }
+                },
+                _ => self.mounts.retain(|m| m.mount_point != path),
+            }
+        }
+        self.cursor.do_control(self.mounts.len(), &self.render_feedback, PageControl::Refresh);
+        Some(PageEvent::Redraw)
+    }
+
+    fn sort_by_column(&mut self, col_idx: usize) {
+        match col_idx {
+            0 => self.mounts.sort_by(|a, b| a.mount_point.cmp(&b.mount_point)),
+            3 => self.mounts.sort_by(|a, b|
+                    b.space_or_empty().total.cmp(&a.space_or_empty().total)),
+            5 => self.mounts.sort_by(|a, b|
+                    b.space_or_empty().avail.cmp(&a.space_or_empty().avail)),
+            6 => self.mounts.sort_by(|a, b|
+                    b.space_or_empty().used_fraction()
+                        .partial_cmp(&a.space_or_empty().used_fraction()).unwrap()),
+            _ => (),
+        }
+    }
+
+    fn set_render_feedback(&mut self, fb: RenderFeedback) {
+        self.render_feedback = fb;
+    }
+
+    fn is_inside_screen_rect(&self, x: i32, y: i32) -> bool {
+        self.render_feedback.is_inside_screen_rect(x, y)
+    }
+
+    fn cursor_path(&self) -> Option<std::path::PathBuf> {
+        self.mounts.get(self.cursor.cursor_idx).map(|m| m.mount_point.clone())
+    }
+
+    fn do_control(&mut self, ctrl: PageControl) -> Option<PageEvent> {
+        match ctrl {
+            PageControl::Access => Some(PageEvent::OpenDir(self.cursor_path()?)),
+            PageControl::Back => Some(PageEvent::Close),
+            PageControl::Refresh => {
+                self.reread();
+                self.cursor.do_control(self.mounts.len(), &self.render_feedback, ctrl);
+                None
+            },
+            _ => {
+                self.cursor.do_control(self.mounts.len(), &self.render_feedback, ctrl);
+                None
+            },
+        }
+    }
+
+    fn as_drawable_table(&mut self) -> TableRef {
+        let title =
+            match &self.error {
+                Some(err) => format!("drives [{}]", err),
+                None if !self.jobs.is_empty() =>
+                    format!("drives [{} file systems, querying {}...]",
+                        self.mounts.len() - self.jobs.len(), self.jobs.len()),
+                None      => format!("drives [{} file systems]", self.mounts.len()),
+            };
+
+        // Mounts whose size is still queried show "..." for it:
+        let size_col = |f: fn(&FsSpace) -> u64| -> Vec<String> {
+            self.mounts.iter().map(|m| match &m.space {
+                Some(space) => format_size(f(space)),
+                None        => String::from("..."),
+            }).collect()
+        };
+
+        let text_col = |head: &str, width: &str, rows: Vec<String>| Column {
+            head: head.to_string(),
+            size: ColumnSizing::TextWidth(width.to_string()),
+            calc_size: None,
+            rows: rows.into_iter().map(|text| StyleString {
+                text,
+                style: Style::Default,
+                highlight: vec![],
+            }).collect(),
+        };
+
+        self.rendered =
+            std::rc::Rc::new(std::cell::RefCell::new(Table {
+                title,
+                row_gap: 2,
+                col_gap: 4,
+                columns: vec![
+                    Column {
+                        head: String::from("mount point"),
+                        size: ColumnSizing::ExpandFract(1),
+                        calc_size: None,
+                        rows: self.mounts.iter().map(|m| StyleString {
+                            text: m.mount_point.to_string_lossy().to_string(),
+                            style: Style::Dir,
+                            highlight: vec![],
+                        }).collect(),
+                    },
+                    text_col("device", "MMMMMMMMMMMM",
+                        self.mounts.iter().map(|m| m.device.clone()).collect()),
+                    text_col("type", "MMMMMM",
+                        self.mounts.iter().map(|m| m.fs_type.clone()).collect()),
+                    text_col("size", "MMMMMMMM", size_col(|s| s.total)),
+                    text_col("used", "MMMMMMMM", size_col(|s| s.used())),
+                    text_col("free", "MMMMMMMM", size_col(|s| s.avail)),
+                    Column {
+                        head: String::from("usage"),
+                        size: ColumnSizing::TextWidth(String::from("MMMMMMMMMMMM")),
+                        calc_size: None,
+                        rows: self.mounts.iter().map(|m| {
+                            let frac = m.space_or_empty().used_fraction();
+                            StyleString {
+                                text:
+                                    if m.space.is_some() { format!("{:5.1}%", frac * 100.0) }
+                                    else { String::from("...") },
+                                style: Style::Bar(frac as f32),
+                                highlight: vec![],
+                            }
+                        }).collect(),
+                    },
+                ],
+            }));
+        self.rendered.clone()
+    }
+}
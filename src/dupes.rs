@@ -0,0 +1,633 @@
+use chrono::DateTime;
+use chrono::offset::Utc;
+use crate::fm_page::*;
+use crate::cursor::PageCursor;
+use crate::path_sheet::{PathRecord, PathRecordType, format_size, file_mode};
+use crate::file_ops::replace_with_hard_link;
+use std::io::Read;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+
+/// Bytes from the start of a file that go into the partial hash.
+const PARTIAL_HASH_BYTES : usize = 16 * 1024;
+
+/// Files with the same content.
+#[derive(Debug, Clone)]
+pub struct DupeGroup {
+    pub size:  u64,
+    pub files: Vec<PathRecord>,
+}
+
+impl DupeGroup {
+    /// The space that is freed if only one copy is kept.
+    pub fn wasted(&self) -> u64 {
+        self.size * (self.files.len() as u64).saturating_sub(1)
+    }
+}
+
+/// Identifies the file behind a path, so that hard links to the same
+/// file aren't reported as duplicates.
+#[cfg(unix)]
+fn file_id(md: &std::fs::Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((md.dev(), md.ino()))
+}
+
+#[cfg(not(unix))]
+fn file_id(_md: &std::fs::Metadata) -> Option<(u64, u64)> { None }
+
+/// Hashes the first `limit` bytes of a file, or all of it.
+fn hash_file(path: &std::path::Path, limit: Option<usize>, stop: &AtomicBool)
+    -> std::io::Result<Vec<u8>> {
+
+    let mut file  = std::fs::File::open(path)?;
+    let mut state = blake3::Hasher::new();
+    let mut buf   = vec![0; 64 * 1024];
+    let mut left  = limit.unwrap_or(usize::MAX);
+    while left > 0 {
+        if stop.load(Ordering::Relaxed) {
+            return Err(std::io::Error::new(std::io::ErrorKind::Interrupted, "canceled"));
+        }
+        let want = left.min(buf.len());
+        let n = file.read(&mut buf[0..want])?;
+        if n == 0 {
+            break;
+        }
+        state.update(&buf[0..n]);
+        left -= n;
+    }
+    Ok(state.finalize().as_bytes().to_vec())
+}
+
+/// Whether the file still has the size and modification time it had
+/// when it was scanned.
+fn unchanged_since_scan(pr: &PathRecord) -> Result<std::fs::Metadata, String> {
+    let md = pr.path.symlink_metadata().map_err(|e| e.to_string())?;
+    if !md.file_type().is_file() || md.len() != pr.size || md.modified().ok() != Some(pr.mtime) {
+        return Err(String::from("changed since the scan, skipped"));
+    }
+    Ok(md)
+}
+
+#[cfg(unix)]
+fn same_owner_and_mode(a: &std::fs::Metadata, b: &std::fs::Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    a.uid() == b.uid() && a.gid() == b.gid() && a.mode() == b.mode()
+}
+
+#[cfg(not(unix))]
+fn same_owner_and_mode(_a: &std::fs::Metadata, _b: &std::fs::Metadata) -> bool { true }
+
+/// Compares two files byte by byte.
+fn same_content(a: &std::path::Path, b: &std::path::Path) -> std::io::Result<bool> {
+    let mut fa = std::fs::File::open(a)?;
+    let mut fb = std::fs::File::open(b)?;
+    let mut buf_a = vec![0; 64 * 1024];
+    let mut buf_b = vec![0; 64 * 1024];
+    loop {
+        let n = fa.read(&mut buf_a)?;
+        if n == 0 {
+            return Ok(fb.read(&mut buf_b[0..1])? == 0);
+        }
+        if fb.read_exact(&mut buf_b[0..n]).is_err() || buf_a[0..n] != buf_b[0..n] {
+            return Ok(false);
+        }
+    }
+}
+
+/// Checks right before linking that `pr` still is a copy of `target`.
+/// A hard link shares the mode and owner of the target, so files that
+/// differ in those are not linked either.
+fn check_link(target: &PathRecord, target_md: &std::fs::Metadata, pr: &PathRecord)
+    -> Result<(), String> {
+
+    let md = unchanged_since_scan(pr)?;
+    if !same_owner_and_mode(target_md, &md) {
+        return Err(String::from("owner or permissions differ from the kept copy, skipped"));
+    }
+    match same_content(&target.path, &pr.path) {
+        Ok(true)  => Ok(()),
+        Ok(false) => Err(String::from("content differs from the kept copy, skipped")),
+        Err(e)    => Err(e.to_string()),
+    }
+}
+
+pub enum DupeMsg {
+    Progress(String),
+    Error(String),
+    Done(Vec<DupeGroup>),
+    /// The files replaced by hard links, the linking is finished.
+    Linked(Vec<std::path::PathBuf>),
+}
+
+/// Splits every group into groups of files with the same hash. Groups
+/// with a single file are dropped.
+fn split_by_hash(groups: Vec<Vec<PathRecord>>, limit: Option<usize>, phase: &str,
+                 stop: &AtomicBool, tx: &Sender<DupeMsg>) -> Vec<Vec<PathRecord>> {
+
+    let total : usize = groups.iter().map(|g| g.len()).sum();
+    let mut done = 0;
+    let mut out  = vec![];
+    for group in groups {
+        let mut by_hash : std::collections::HashMap<Vec<u8>, Vec<PathRecord>> =
+            std::collections::HashMap::new();
+        for pr in group {
+            if stop.load(Ordering::Relaxed) {
+                return vec![];
+            }
+            match hash_file(&pr.path, limit, stop) {
+                Ok(hash) => by_hash.entry(hash).or_insert_with(Vec::new).push(pr),
+                Err(e) => {
+                    let _ = tx.send(DupeMsg::Error(format!("{}: {}", pr.path.to_string_lossy(), e)));
+                },
+            }
+            done += 1;
+            if done % 100 == 0 {
+                let _ = tx.send(DupeMsg::Progress(format!("{}: {}/{} files", phase, done, total)));
+            }
+        }
+        out.extend(by_hash.into_iter().map(|(_, g)| g).filter(|g| g.len() > 1));
+    }
+    out
+}
+
+fn find_dupes(base: &std::path::Path, stop: &AtomicBool, tx: &Sender<DupeMsg>) -> Vec<DupeGroup> {
+    let mut by_size : std::collections::HashMap<u64, Vec<PathRecord>> =
+        std::collections::HashMap::new();
+    let mut seen  = std::collections::HashSet::new();
+    let mut count = 0;
+
+    let mut dirs = vec![base.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let rd =
+            match std::fs::read_dir(&dir) {
+                Ok(rd) => rd,
+                Err(e) => {
+                    let _ = tx.send(DupeMsg::Error(format!("{}: {}", dir.to_string_lossy(), e)));
+                    continue;
+                },
+            };
+        for entry in rd {
+            if stop.load(Ordering::Relaxed) {
+                return vec![];
+            }
+            let path = match entry { Ok(e) => e.path(), Err(_) => continue };
+            let md   = match path.symlink_metadata() { Ok(md) => md, Err(_) => continue };
+            let ft   = md.file_type();
+            if ft.is_dir() {
+                dirs.push(path);
+                continue;
+            }
+            // Empty files are all the same, and links are skipped:
+            if !ft.is_file() || md.len() == 0 {
+                continue;
+            }
+            if let Some(id) = file_id(&md) {
+                if !seen.insert(id) {
+                    continue;
+                }
+            }
+
+            let pr = PathRecord {
+                path,
+                size:      md.len(),
+                mtime:     md.modified().unwrap_or(std::time::UNIX_EPOCH),
+                path_type: PathRecordType::File,
+                mode:      file_mode(&md),
+            };
+            by_size.entry(pr.size).or_insert_with(Vec::new).push(pr);
+
+            count += 1;
+            if count % 1000 == 0 {
+                let _ = tx.send(DupeMsg::Progress(format!("scanning: {} files", count)));
+            }
+        }
+    }
+
+    let groups : Vec<Vec<PathRecord>> =
+        by_size.into_iter().map(|(_, g)| g).filter(|g| g.len() > 1).collect();
+    let groups = split_by_hash(groups, Some(PARTIAL_HASH_BYTES), "partial hash", stop, tx);
+    let groups = split_by_hash(groups, None, "full hash", stop, tx);
+
+    let mut groups : Vec<DupeGroup> =
+        groups.into_iter().map(|mut files| {
+            files.sort_by(|a, b| a.path.cmp(&b.path));
+            DupeGroup { size: files[0].size, files }
+        }).collect();
+    groups.sort_by(|a, b| b.wasted().cmp(&a.wasted()).then_with(|| a.files[0].path.cmp(&b.files[0].path)));
+    groups
+}
+
+/// Replaces the files of every `(target, files)` pair by hard links to
+/// the target. The scan may be minutes old, so the files are checked
+/// again first. Those that changed are skipped and reported.
+fn link_files(plan: Vec<(PathRecord, Vec<PathRecord>)>, stop: &AtomicBool,
+              tx: &Sender<DupeMsg>) -> Vec<std::path::PathBuf> {
+
+    let total : usize = plan.iter().map(|(_, files)| files.len()).sum();
+    let mut done   = 0;
+    let mut linked = vec![];
+    for (target, files) in plan {
+        let target_md =
+            match unchanged_since_scan(&target) {
+                Ok(md) => md,
+                Err(e) => {
+                    let _ = tx.send(DupeMsg::Error(format!("{}: {}", target.path.to_string_lossy(), e)));
+                    done += files.len();
+                    continue;
+                },
+            };
+
+        for f in files {
+            if stop.load(Ordering::Relaxed) {
+                return linked;
+            }
+            let res =
+                check_link(&target, &target_md, &f)
+                    .and_then(|()| {
+                        replace_with_hard_link(&target.path, &f.path).map_err(|e| e.to_string())
+                    });
+            match res {
+                Ok(())  => linked.push(f.path),
+                Err(e)  => {
+                    let _ = tx.send(DupeMsg::Error(format!("{}: {}", f.path.to_string_lossy(), e)));
+                },
+            }
+            done += 1;
+            let _ = tx.send(DupeMsg::Progress(format!("linking: {}/{} files", done, total)));
+        }
+    }
+    linked
+}
+
+/// Searches duplicate files on a thread. Files are grouped by size, then
+/// by a hash of their start and then by a hash of the whole content.
+/// Also replaces duplicates by hard links. Dropping the job stops it.
+pub struct DupeJob {
+    /// Set for a job linking files, instead of searching them.
+    linking: bool,
+    cancel:  Arc<AtomicBool>,
+    rx:      Receiver<DupeMsg>,
+}
+
+impl DupeJob {
+    pub fn start(base: &std::path::Path) -> DupeJob {
+        let cancel = Arc::new(AtomicBool::new(false));
+        let (tx, rx) = channel();
+
+        let base = base.to_path_buf();
+        let stop = cancel.clone();
+        std::thread::spawn(move || {
+            let groups = find_dupes(&base, &stop, &tx);
+            let _ = tx.send(DupeMsg::Done(groups));
+        });
+
+        DupeJob { linking: false, cancel, rx }
+    }
+
+    pub fn link(plan: Vec<(PathRecord, Vec<PathRecord>)>) -> DupeJob {
+        let cancel = Arc::new(AtomicBool::new(false));
+        let (tx, rx) = channel();
+
+        let stop = cancel.clone();
+        std::thread::spawn(move || {
+            let linked = link_files(plan, &stop, &tx);
+            let _ = tx.send(DupeMsg::Linked(linked));
+        });
+
+        DupeJob { linking: true, cancel, rx }
+    }
+
+    pub fn poll(&self) -> Vec<DupeMsg> {
+        let mut msgs = vec![];
+        loop {
+            match self.rx.try_recv() {
+                Ok(msg @ DupeMsg::Done(_)) | Ok(msg @ DupeMsg::Linked(_)) => {
+                    msgs.push(msg);
+                    break;
+                },
+                Ok(msg) => msgs.push(msg),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    msgs.push(if self.linking { DupeMsg::Linked(vec![]) } else { DupeMsg::Done(vec![]) });
+                    break;
+                },
+            }
+        }
+        msgs
+    }
+}
+
+impl Drop for DupeJob {
+    fn drop(&mut self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Which copy of each group is kept when selecting the others.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum KeepCopy {
+    Newest,
+    Oldest,
+    ShortestPath,
+}
+
+/// Lists groups of duplicate files, each one below a header row. The
+/// selection can be trashed like in other pages, or replaced by hard
+/// links to the copy that isn't selected.
+pub struct DupeSheet {
+    pub base:            std::path::PathBuf,
+    pub groups:          Vec<DupeGroup>,
+    /// `(group, None)` for a header row, `(group, Some(file))` for a
+    /// file.
+    pub rows:            Vec<(usize, Option<usize>)>,
+    pub selection:       std::collections::HashSet<std::path::PathBuf>,
+    pub job:             Option<DupeJob>,
+    pub status:          String,
+    pub render_feedback: RenderFeedback,
+    pub cursor:          PageCursor,
+    pub rendered:        TableRef,
+}
+
+impl DupeSheet {
+    pub fn new(base: &std::path::Path) -> Self {
+        DupeSheet {
+            base:            base.to_path_buf(),
+            groups:          vec![],
+            rows:            vec![],
+            selection:       std::collections::HashSet::new(),
+            job:             Some(DupeJob::start(base)),
+            status:          String::from("scanning..."),
+            render_feedback: RenderFeedback::new(),
+            cursor:          PageCursor::new(),
+            rendered:        Table::new_ref(),
+        }
+    }
+
+    fn update_rows(&mut self) {
+        self.groups.retain(|g| g.files.len() > 1);
+        self.rows.clear();
+        for (gi, group) in self.groups.iter().enumerate() {
+            self.rows.push((gi, None));
+            for fi in 0..group.files.len() {
+                self.rows.push((gi, Some(fi)));
+            }
+        }
+        let groups = &self.groups;
+        self.selection.retain(|p| groups.iter().any(|g| g.files.iter().any(|f| &f.path == p)));
+        self.cursor.do_control(self.rows.len(), &self.render_feedback, PageControl::Refresh);
+    }
+
+    fn row_file(&self, idx: usize) -> Option<&PathRecord> {
+        let (gi, fi) = self.rows.get(idx)?;
+        self.groups[*gi].files.get((*fi)?)
+    }
+
+    /// Selects all files except one copy per group.
+    fn select_all_but(&mut self, keep: KeepCopy) {
+        self.selection.clear();
+        for group in self.groups.iter() {
+            let kept =
+                match keep {
+                    KeepCopy::Newest => group.files.iter().max_by_key(|f| f.mtime),
+                    KeepCopy::Oldest => group.files.iter().min_by_key(|f| f.mtime),
+                    KeepCopy::ShortestPath =>
+                        group.files.iter().min_by_key(|f| f.path.as_os_str().len()),
+                };
+            let kept = match kept { Some(f) => f.path.clone(), None => continue };
+            for f in group.files.iter() {
+                if f.path != kept {
+                    self.selection.insert(f.path.clone());
+                }
+            }
+        }
+    }
+
+    /// Starts replacing the selected files of every group by hard links
+    /// to the first copy that is not selected.
+    fn link_selection(&mut self) -> Vec<String> {
+        if self.job.is_some() {
+            return vec![String::from("link: still busy, try again when done")];
+        }
+
+        let mut log  = vec![];
+        let mut plan = vec![];
+        for group in self.groups.iter() {
+            let selected : Vec<PathRecord> =
+                group.files.iter().filter(|f| self.selection.contains(&f.path)).cloned().collect();
+            if selected.is_empty() {
+                continue;
+            }
+            match group.files.iter().find(|f| !self.selection.contains(&f.path)) {
+                Some(target) => plan.push((target.clone(), selected)),
+                None => {
+                    log.push(format!("{}: all copies selected, none to link to",
+                        group.files[0].path.to_string_lossy()));
+                },
+            }
+        }
+        if !plan.is_empty() {
+            self.job    = Some(DupeJob::link(plan));
+            self.status = String::from("linking...");
+        }
+        log
+    }
+}
+
+impl FmPage for DupeSheet {
+    fn len(&self) -> usize { self.rows.len() }
+    fn get_scroll_offs(&self) -> usize { self.cursor.scroll_offset }
+    fn is_cursor_idx(&self, idx: usize) -> bool { self.cursor.is_cursor_idx(idx) }
+    fn is_selected(&self, idx: usize) -> bool {
+        self.row_file(idx).map(|f| self.selection.contains(&f.path)).unwrap_or(false)
+    }
+    fn is_highlighted(&self, _idx: usize) -> bool { false }
+    fn needs_repage(&self) -> bool { true }
+    fn needs_redraw(&self) -> bool { true }
+    fn sort_by_column(&mut self, _col_idx: usize) { }
+
+    fn set_render_feedback(&mut self, fb: RenderFeedback) {
+        self.render_feedback = fb;
+    }
+
+    fn is_inside_screen_rect(&self, x: i32, y: i32) -> bool {
+        self.render_feedback.is_inside_screen_rect(x, y)
+    }
+
+    fn cursor_path(&self) -> Option<std::path::PathBuf> {
+        self.row_file(self.cursor.cursor_idx).map(|f| f.path.clone())
+    }
+
+    fn selected_paths(&self) -> Vec<std::path::PathBuf> {
+        self.rows.iter().enumerate()
+            .filter(|(i, _)| self.is_selected(*i))
+            .filter_map(|(i, _)| self.row_file(i).map(|f| f.path.clone()))
+            .collect()
+    }
+
+    fn clear_selection(&mut self) {
+        self.selection.clear();
+    }
+
+    fn is_busy(&self) -> bool { self.job.is_some() }
+
+    fn update(&mut self) -> Option<PageEvent> {
+        let msgs = self.job.as_ref()?.poll();
+        if msgs.is_empty() {
+            return None;
+        }
+
+        let mut log = vec![];
+        for msg in msgs {
+            match msg {
+                DupeMsg::Progress(status) => self.status = status,
+                DupeMsg::Error(err)       => log.push(err),
+                DupeMsg::Done(groups) => {
+                    self.job    = None;
+                    self.groups = groups;
+                    self.status = String::new();
+                    self.update_rows();
+                },
+                DupeMsg::Linked(paths) => {
+                    self.job    = None;
+                    self.status = String::new();
+                    log.push(format!("Replaced {} files with hard links", paths.len()));
+                    let linked : std::collections::HashSet<std::path::PathBuf> =
+                        paths.into_iter().collect();
+                    for group in self.groups.iter_mut() {
+                        group.files.retain(|f| !linked.contains(&f.path));
+                    }
+                    self.selection.clear();
+                    self.update_rows();
+                },
+            }
+        }
+
+        if log.is_empty() {
+            Some(PageEvent::Redraw)
+        } else {
+            Some(PageEvent::Log(log))
+        }
+    }
+
+    fn do_control(&mut self, ctrl: PageControl) -> Option<PageEvent> {
+        match ctrl {
+            PageControl::Back => Some(PageEvent::Close),
+            PageControl::Access => {
+                Some(PageEvent::ShowEntry(self.cursor_path()?))
+            },
+            PageControl::ToggleSelect => {
+                if let Some(path) = self.cursor_path() {
+                    if !self.selection.remove(&path) {
+                        self.selection.insert(path);
+                    }
+                }
+                self.cursor.do_control(self.rows.len(), &self.render_feedback, PageControl::CursorDown);
+                None
+            },
+            PageControl::CycleMode => {
+                Some(PageEvent::Prompt(
+                    String::from("select all but the [n]ewest, [o]ldest, [s]hortest path, or [l]ink the selection: "),
+                    String::new()))
+            },
+            PageControl::Edit(choice) => {
+                match choice.trim() {
+                    "n" => self.select_all_but(KeepCopy::Newest),
+                    "o" => self.select_all_but(KeepCopy::Oldest),
+                    "s" => self.select_all_but(KeepCopy::ShortestPath),
+                    "l" => {
+                        let log = self.link_selection();
+                        if !log.is_empty() {
+                            return Some(PageEvent::Log(log));
+                        }
+                    },
+                    _   => return Some(PageEvent::Log(vec![format!("unknown choice '{}'", choice)])),
+                }
+                None
+            },
+            PageControl::Refresh => {
+                // Drop the files that were deleted or moved to the trash:
+                for group in self.groups.iter_mut() {
+                    group.files.retain(|f| f.path.symlink_metadata().is_ok());
+                }
+                self.update_rows();
+                None
+            },
+            _ => {
+                self.cursor.do_control(self.rows.len(), &self.render_feedback, ctrl);
+                None
+            },
+        }
+    }
+
+    fn as_drawable_table(&mut self) -> TableRef {
+        let wasted : u64 = self.groups.iter().map(|g| g.wasted()).sum();
+        let mut title = format!("duplicates in {} [{} groups, {} wasted",
+            self.base.to_string_lossy(), self.groups.len(), format_size(wasted).trim());
+        if !self.status.is_empty() {
+            title += ", ";
+            title += &self.status;
+        }
+        title += "]";
+
+        let base = &self.base;
+        let rows : Vec<(&DupeGroup, Option<&PathRecord>)> =
+            self.rows.iter()
+                .map(|(gi, fi)| (&self.groups[*gi], fi.map(|fi| &self.groups[*gi].files[fi])))
+                .collect();
+
+        let table =
+            Table {
+                title,
+                row_gap: 2,
+                col_gap: 4,
+                columns: vec![
+                    Column {
+                        head: String::from("name"),
+                        size: ColumnSizing::ExpandFract(1),
+                        calc_size: None,
+                        rows: rows.iter().map(|(g, f)| match f {
+                            Some(f) => StyleString {
+                                text: f.path.strip_prefix(base).unwrap_or(&f.path)
+                                        .to_string_lossy().to_string(),
+                                style: Style::File,
+                                highlight: vec![],
+                            },
+                            None => StyleString {
+                                text: format!("── {} copies, {} wasted", g.files.len(),
+                                    format_size(g.wasted()).trim()),
+                                style: Style::Special,
+                                highlight: vec![],
+                            },
+                        }).collect(),
+                    },
+                    Column {
+                        head: String::from("time"),
+                        size: ColumnSizing::TextWidth(String::from("MMMM-MM-MM MM:MM:MM")),
+                        calc_size: None,
+                        rows: rows.iter().map(|(_, f)| StyleString {
+                            text: f.map(|f| {
+                                let dt : DateTime<Utc> = f.mtime.into();
+                                dt.format("%Y-%m-%d %H:%M:%S").to_string()
+                            }).unwrap_or_default(),
+                            style: Style::Default,
+                            highlight: vec![],
+                        }).collect(),
+                    },
+                    Column {
+                        head: String::from("size"),
+                        size: ColumnSizing::TextWidth(String::from("MMMMMMMM")),
+                        calc_size: None,
+                        rows: rows.iter().map(|(g, _)| StyleString {
+                            text: format_size(g.size),
+                            style: Style::Default,
+                            highlight: vec![],
+                        }).collect(),
+                    },
+                ],
+            };
+        self.rendered = std::rc::Rc::new(std::cell::RefCell::new(table));
+        self.rendered.clone()
+    }
+}
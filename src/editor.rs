@@ -0,0 +1,99 @@
+use crate::assoc::split_command;
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+
+/// How files are opened for editing. `%p` in the command is replaced
+/// by the file, otherwise the file is appended. `%l` is replaced by the
+/// line to start at, like in `vim +%l %p`. Terminal editors are started
+/// through the terminal command.
+#[derive(Debug, Clone)]
+pub struct EditorConfig {
+    pub command:     String,
+    pub in_terminal: bool,
+    pub terminal:    String,
+}
+
+impl EditorConfig {
+    /// Uses `$VISUAL` as GUI editor, or `$EDITOR` in `$TERMINAL`.
+    pub fn from_env() -> Self {
+        let terminal =
+            std::env::var("TERMINAL").unwrap_or_else(|_| String::from("xterm -e"));
+        if let Ok(visual) = std::env::var("VISUAL") {
+            return EditorConfig { command: visual, in_terminal: false, terminal };
+        }
+        EditorConfig {
+            command:     std::env::var("EDITOR").unwrap_or_else(|_| String::from("vi")),
+            in_terminal: true,
+            terminal,
+        }
+    }
+
+    pub fn args(&self, path: &std::path::Path, line: Option<usize>) -> Vec<String> {
+        let path = path.to_string_lossy();
+        let line = line.unwrap_or(1).to_string();
+        let mut args = split_command(&self.command);
+        let has_path = args.iter().any(|a| a.contains("%p"));
+        for a in args.iter_mut() {
+            *a = a.replace("%l", &line).replace("%p", &path);
+        }
+        if !has_path {
+            args.push(path.to_string());
+        }
+
+        if self.in_terminal {
+            let mut term = split_command(&self.terminal);
+            term.append(&mut args);
+            term
+        } else {
+            args
+        }
+    }
+}
+
+/// An editor that was started for a file. A thread waits for it to
+/// exit.
+pub struct EditSession {
+    pub path: std::path::PathBuf,
+    rx:       Receiver<String>,
+}
+
+impl EditSession {
+    pub fn start(args: &[String], path: &std::path::Path, cwd: &std::path::Path)
+        -> std::io::Result<EditSession> {
+
+        use std::process::{Command, Stdio};
+
+        if args.is_empty() {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "empty editor command"));
+        }
+
+        let mut child =
+            Command::new(&args[0])
+                .args(&args[1..])
+                .current_dir(cwd)
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .spawn()?;
+
+        let (tx, rx) = channel();
+        std::thread::spawn(move || {
+            let status =
+                match child.wait() {
+                    Ok(st) => st.to_string(),
+                    Err(e) => e.to_string(),
+                };
+            let _ = tx.send(status);
+        });
+
+        Ok(EditSession { path: path.to_path_buf(), rx })
+    }
+
+    /// Returns the exit status once the editor exited.
+    pub fn poll(&self) -> Option<String> {
+        match self.rx.try_recv() {
+            Ok(status)                      => Some(status),
+            Err(TryRecvError::Empty)        => None,
+            Err(TryRecvError::Disconnected) => Some(String::from("unknown exit status")),
+        }
+    }
+}
@@ -0,0 +1,359 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+
+/// Counts the entries a copy or delete finished, reports the count at
+/// most once a second and carries the cancel flag.
+pub struct OpProgress<'a> {
+    pub stop:    &'a AtomicBool,
+    pub done:    usize,
+    last_report: std::time::Instant,
+    report:      Box<dyn FnMut(usize) + 'a>,
+}
+
+impl<'a> OpProgress<'a> {
+    pub fn new(stop: &'a AtomicBool, report: Box<dyn FnMut(usize) + 'a>) -> OpProgress<'a> {
+        OpProgress { stop, done: 0, last_report: std::time::Instant::now(), report }
+    }
+
+    fn canceled(&self) -> bool { self.stop.load(Ordering::Relaxed) }
+
+    fn entry_done(&mut self) {
+        self.done += 1;
+        if self.last_report.elapsed() >= std::time::Duration::from_secs(1) {
+            self.last_report = std::time::Instant::now();
+            (self.report)(self.done);
+        }
+    }
+}
+
+/// Copies a file, symbolic link or directory tree to `dst`. Existing
+/// entries are not overwritten. Failures don't stop the copy, they are
+/// collected as messages in `log`. Returns the number of copied entries.
+pub fn copy_recursive(src: &std::path::Path, dst: &std::path::Path,
+                      progress: &mut OpProgress, log: &mut Vec<String>) -> usize {
+
+    if progress.canceled() {
+        return 0;
+    }
+    let md = match src.symlink_metadata() {
+        Ok(md) => md,
+        Err(e) => {
+            log.push(format!("{}: {}", src.to_string_lossy(), e));
+            return 0;
+        },
+    };
+    if dst.symlink_metadata().is_ok() {
+        log.push(format!("{}: already exists", dst.to_string_lossy()));
+        return 0;
+    }
+
+    let ft = md.file_type();
+    let res =
+        if ft.is_symlink() {
+            copy_symlink(src, dst)
+        } else if ft.is_dir() {
+            std::fs::create_dir(dst)
+        } else if ft.is_file() {
+            std::fs::copy(src, dst).map(|_| ())
+        } else {
+            // std::fs::copy would open a FIFO and wait for a writer:
+            copy_special(dst, &md)
+        };
+    if let Err(e) = res {
+        log.push(format!("{}: {}", dst.to_string_lossy(), e));
+        return 0;
+    }
+    progress.entry_done();
+
+    let mut count = 1;
+    if ft.is_dir() {
+        match std::fs::read_dir(src) {
+            Ok(entries) => {
+                for e in entries {
+                    match e {
+                        Ok(e) => {
+                            count += copy_recursive(
+                                &e.path(), &dst.join(e.file_name()), progress, log);
+                        },
+                        Err(e) => log.push(format!("{}: {}", src.to_string_lossy(), e)),
+                    }
+                }
+            },
+            Err(e) => log.push(format!("{}: {}", src.to_string_lossy(), e)),
+        }
+        // Set the permissions last, they might not allow adding entries:
+        if let Err(e) = std::fs::set_permissions(dst, md.permissions()) {
+            log.push(format!("{}: {}", dst.to_string_lossy(), e));
+        }
+    }
+    count
+}
+
+#[cfg(unix)]
+fn copy_symlink(src: &std::path::Path, dst: &std::path::Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(std::fs::read_link(src)?, dst)
+}
+
+#[cfg(not(unix))]
+fn copy_symlink(src: &std::path::Path, dst: &std::path::Path) -> std::io::Result<()> {
+    std::fs::copy(src, dst).map(|_| ())
+}
+
+/// Creates a FIFO like the one `md` is from. Sockets and devices are not
+/// copied.
+#[cfg(unix)]
+fn copy_special(dst: &std::path::Path, md: &std::fs::Metadata) -> std::io::Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+
+    if !md.file_type().is_fifo() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::Other, "not a regular file, skipped"));
+    }
+    let cpath =
+        std::ffi::CString::new(dst.as_os_str().as_bytes())
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    let mode = md.permissions().mode() & 0o7777;
+    if unsafe { libc::mkfifo(cpath.as_ptr(), mode as libc::mode_t) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn copy_special(_dst: &std::path::Path, _md: &std::fs::Metadata) -> std::io::Result<()> {
+    Err(std::io::Error::new(std::io::ErrorKind::Other, "not a regular file, skipped"))
+}
+
+/// Deletes a file, symbolic link or directory tree. Symbolic links are
+/// removed, not followed. Failures are collected in `log` and don't stop
+/// the deletion of the other entries. Returns the number of deleted
+/// entries.
+pub fn delete_recursive(path: &std::path::Path, progress: &mut OpProgress,
+                        log: &mut Vec<String>) -> usize {
+    if progress.canceled() {
+        return 0;
+    }
+    let is_dir =
+        path.symlink_metadata().map(|md| md.file_type().is_dir()).unwrap_or(false);
+
+    let mut count = 0;
+    let res =
+        if is_dir {
+            match std::fs::read_dir(path) {
+                Ok(entries) => {
+                    for e in entries {
+                        match e {
+                            Ok(e)  => count += delete_recursive(&e.path(), progress, log),
+                            Err(e) => log.push(format!("{}: {}", path.to_string_lossy(), e)),
+                        }
+                    }
+                },
+                Err(e) => log.push(format!("{}: {}", path.to_string_lossy(), e)),
+            }
+            if progress.canceled() {
+                return count;
+            }
+            std::fs::remove_dir(path)
+        } else {
+            std::fs::remove_file(path)
+        };
+
+    match res {
+        Ok(())  => {
+            progress.entry_done();
+            count + 1
+        },
+        Err(e)  => {
+            log.push(format!("{}: {}", path.to_string_lossy(), e));
+            count
+        },
+    }
+}
+
+pub enum FileOpMsg {
+    /// The number of entries done so far.
+    Progress(usize),
+    /// The failures and a summary, the job is finished.
+    Done(Vec<String>),
+}
+
+/// Copies or deletes entries on a thread. Dropping or canceling the job
+/// stops it after the current entry.
+pub struct FileOpJob {
+    /// What the job does, like "copy" or "delete".
+    pub what: String,
+    cancel:   Arc<AtomicBool>,
+    rx:       Receiver<FileOpMsg>,
+}
+
+impl FileOpJob {
+    fn start<F>(what: &str, work: F) -> FileOpJob
+        where F: FnOnce(&mut OpProgress, &mut Vec<String>) -> String + Send + 'static {
+
+        let cancel = Arc::new(AtomicBool::new(false));
+        let (tx, rx) = channel();
+
+        let stop = cancel.clone();
+        std::thread::spawn(move || {
+            let progress_tx : Sender<FileOpMsg> = tx.clone();
+            let mut progress =
+                OpProgress::new(&stop, Box::new(move |n| {
+                    let _ = progress_tx.send(FileOpMsg::Progress(n));
+                }));
+            let mut log = vec![];
+            let summary = work(&mut progress, &mut log);
+            if progress.canceled() {
+                log.push(format!("{} (canceled)", summary));
+            } else {
+                log.push(summary);
+            }
+            let _ = tx.send(FileOpMsg::Done(log));
+        });
+
+        FileOpJob { what: what.to_string(), cancel, rx }
+    }
+
+    /// Copies the entries into `dst_dir`.
+    pub fn copy(paths: Vec<std::path::PathBuf>, dst_dir: std::path::PathBuf) -> FileOpJob {
+        Self::start("copy", move |progress, log| {
+            let mut count = 0;
+            for path in paths.iter() {
+                let name = match path.file_name() {
+                    Some(name) => name,
+                    None       => continue,
+                };
+                if dst_dir.starts_with(path) {
+                    log.push(format!("{}: can't copy a directory into itself",
+                        path.to_string_lossy()));
+                    continue;
+                }
+                count += copy_recursive(path, &dst_dir.join(name), progress, log);
+            }
+            format!("Copied {} entries to {}, {} failed",
+                count, dst_dir.to_string_lossy(), log.len())
+        })
+    }
+
+    pub fn delete(paths: Vec<std::path::PathBuf>) -> FileOpJob {
+        Self::start("delete", move |progress, log| {
+            let mut count = 0;
+            for path in paths.iter() {
+                count += delete_recursive(path, progress, log);
+            }
+            format!("Deleted {} entries, {} failed", count, log.len())
+        })
+    }
+
+    pub fn cancel(&self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+
+    pub fn poll(&self) -> Vec<FileOpMsg> {
+        let mut msgs = vec![];
+        loop {
+            match self.rx.try_recv() {
+                Ok(FileOpMsg::Done(log)) => {
+                    msgs.push(FileOpMsg::Done(log));
+                    break;
+                },
+                Ok(msg) => msgs.push(msg),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    msgs.push(FileOpMsg::Done(vec![format!("{} job stopped", self.what)]));
+                    break;
+                },
+            }
+        }
+        msgs
+    }
+}
+
+impl Drop for FileOpJob {
+    fn drop(&mut self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+}
+
+/// The trash directory of the user, as in the freedesktop.org trash
+/// specification.
+fn trash_dir() -> Option<std::path::PathBuf> {
+    let data =
+        match std::env::var_os("XDG_DATA_HOME") {
+            Some(dir) if !dir.is_empty() => std::path::PathBuf::from(dir),
+            _ => std::path::PathBuf::from(std::env::var_os("HOME")?).join(".local/share"),
+        };
+    Some(data.join("Trash"))
+}
+
+/// Percent encodes a path for the `Path=` line of a `.trashinfo` file.
+fn encode_trash_path(path: &std::path::Path) -> String {
+    let mut s = String::new();
+    for b in path.to_string_lossy().bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9'
+            | b'-' | b'_' | b'.' | b'~' | b'/' => s.push(b as char),
+            _ => s += &format!("%{:02X}", b),
+        }
+    }
+    s
+}
+
+/// Moves an entry to the trash of the user, where desktop file managers
+/// can restore it from. Entries on other file systems than the trash
+/// can't be moved there and return an error. Returns the path in the
+/// trash.
+pub fn move_to_trash(path: &std::path::Path) -> std::io::Result<std::path::PathBuf> {
+    use std::io::Write;
+
+    let trash = trash_dir().ok_or_else(|| {
+        std::io::Error::new(std::io::ErrorKind::NotFound, "no trash directory, $HOME is not set")
+    })?;
+    std::fs::create_dir_all(trash.join("files"))?;
+    std::fs::create_dir_all(trash.join("info"))?;
+
+    let abs  = std::fs::canonicalize(path.parent().unwrap_or(path))?
+                    .join(path.file_name().unwrap_or_default());
+    let name = abs.file_name().unwrap_or_default().to_string_lossy().to_string();
+
+    // The info file is created first, it reserves the name in the trash:
+    let mut n = 1;
+    let (info_path, mut info) =
+        loop {
+            let trash_name = if n == 1 { name.clone() } else { format!("{}.{}", name, n) };
+            let info_path  = trash.join("info").join(trash_name + ".trashinfo");
+            match std::fs::OpenOptions::new().write(true).create_new(true).open(&info_path) {
+                Ok(f) => break (info_path, f),
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => n += 1,
+                Err(e) => return Err(e),
+            }
+        };
+    write!(info, "[Trash Info]\nPath={}\nDeletionDate={}\n",
+        encode_trash_path(&abs),
+        chrono::Local::now().format("%Y-%m-%dT%H:%M:%S"))?;
+
+    let trash_name = info_path.file_stem().unwrap_or_default().to_os_string();
+    let dst = trash.join("files").join(trash_name);
+    if let Err(e) = std::fs::rename(&abs, &dst) {
+        let _ = std::fs::remove_file(&info_path);
+        return Err(e);
+    }
+    Ok(dst)
+}
+
+/// Replaces `path` with a hard link to `target`. The link is created
+/// next to `path` first and renamed over it, so `path` is never
+/// missing.
+pub fn replace_with_hard_link(target: &std::path::Path, path: &std::path::Path)
+    -> std::io::Result<()> {
+
+    let name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+    let tmp  = path.with_file_name(format!(".{}.link-tmp", name));
+    std::fs::hard_link(target, &tmp)?;
+    if let Err(e) = std::fs::rename(&tmp, path) {
+        let _ = std::fs::remove_file(&tmp);
+        return Err(e);
+    }
+    Ok(())
+}
@@ -0,0 +1,536 @@
+use crate::fm_page::*;
+use crate::cursor::PageCursor;
+use crate::path_sheet::*;
+use crate::glob::glob_match_nocase;
+use crate::assoc::split_command;
+use crate::text_view::is_binary_file;
+use regex::Regex;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+use std::time::{Duration, SystemTime};
+
+/// Results are sent to the page in batches of this size.
+const RESULT_BATCH : usize = 64;
+
+pub enum NameMatch {
+    Glob(String),
+    Regex(Regex),
+}
+
+pub enum ContentMatch {
+    Text(String),
+    Regex(Regex),
+}
+
+/// What a find searches for. All given criteria have to match, of the
+/// name patterns any one.
+#[derive(Default)]
+pub struct FindCriteria {
+    pub names:      Vec<NameMatch>,
+    pub min_size:   Option<u64>,
+    pub max_size:   Option<u64>,
+    pub newer_than: Option<SystemTime>,
+    pub older_than: Option<SystemTime>,
+    pub path_type:  Option<PathRecordType>,
+    pub content:    Option<ContentMatch>,
+}
+
+/// Parses `10`, `4k`, `2M` or `1G`.
+fn parse_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let (num, unit) =
+        match s.char_indices().find(|(_, c)| !c.is_ascii_digit()) {
+            Some((i, _)) => (&s[0..i], &s[i..]),
+            None         => (s, ""),
+        };
+    let factor =
+        match unit.to_lowercase().as_str() {
+            ""        => 1,
+            "k" | "kb" => 1024,
+            "m" | "mb" => 1024 * 1024,
+            "g" | "gb" => 1024 * 1024 * 1024,
+            _ => return Err(format!("bad size '{}'", s)),
+        };
+    num.parse::<u64>().map(|n| n * factor).map_err(|_| format!("bad size '{}'", s))
+}
+
+/// Parses `YYYY-MM-DD`, `YYYY-MM-DD HH:MM:SS` or an age like `30m`,
+/// `12h`, `7d` or `2w`, which means that long before now.
+fn parse_point_in_time(s: &str) -> Result<SystemTime, String> {
+    use chrono::{DateTime, NaiveDate};
+    use chrono::offset::Utc;
+
+    let s = s.trim();
+    if let Some(t) = crate::attr_ops::parse_time(s) {
+        return Ok(t);
+    }
+    if let Ok(d) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+        return Ok(DateTime::<Utc>::from_utc(d.and_hms(0, 0, 0), Utc).into());
+    }
+
+    if s.len() > 1 {
+        let (num, unit) = s.split_at(s.len() - 1);
+        if let Ok(n) = num.parse::<u64>() {
+            let secs =
+                match unit {
+                    "s" => Some(n),
+                    "m" => Some(n * 60),
+                    "h" => Some(n * 3600),
+                    "d" => Some(n * 86400),
+                    "w" => Some(n * 7 * 86400),
+                    _   => None,
+                };
+            if let Some(secs) = secs {
+                return Ok(SystemTime::now() - Duration::from_secs(secs));
+            }
+        }
+    }
+
+    Err(format!("bad time '{}'", s))
+}
+
+/// Splits `A..B`, `>A` and `<A` into the lower and upper bound.
+fn parse_range(s: &str) -> (Option<&str>, Option<&str>) {
+    if let Some(i) = s.find("..") {
+        let (a, b) = (&s[0..i], &s[(i + 2)..]);
+        (if a.is_empty() { None } else { Some(a) },
+         if b.is_empty() { None } else { Some(b) })
+    } else if let Some(min) = s.strip_prefix('>') {
+        (Some(min), None)
+    } else if let Some(max) = s.strip_prefix('<') {
+        (None, Some(max))
+    } else {
+        (Some(s), Some(s))
+    }
+}
+
+fn parse_regex(s: &str) -> Result<Regex, String> {
+    Regex::new(s).map_err(|e| e.to_string())
+}
+
+/// Strips the slashes of a `/regex/`.
+fn as_regex(s: &str) -> Option<&str> {
+    if s.len() >= 2 && s.starts_with('/') && s.ends_with('/') {
+        Some(&s[1..(s.len() - 1)])
+    } else {
+        None
+    }
+}
+
+impl FindCriteria {
+    /// Parses the find query of the input line. Words are name globs, a
+    /// word without wildcards matches names containing it, and `/re/` is
+    /// a regex on the name. Further criteria are `size:`, `mtime:`
+    /// (both as `A..B`, `>A` or `<A`), `type:f|d|l` and `content:` with a
+    /// string or a `/re/`.
+    pub fn parse(query: &str) -> Result<FindCriteria, String> {
+        let mut crit = FindCriteria::default();
+
+        for word in split_command(query) {
+            if let Some(range) = word.strip_prefix("size:") {
+                let (min, max) = parse_range(range);
+                if let Some(min) = min { crit.min_size = Some(parse_size(min)?); }
+                if let Some(max) = max { crit.max_size = Some(parse_size(max)?); }
+
+            } else if let Some(range) = word.strip_prefix("mtime:") {
+                let (from, to) = parse_range(range);
+                if let Some(from) = from { crit.newer_than = Some(parse_point_in_time(from)?); }
+                if let Some(to)   = to   { crit.older_than = Some(parse_point_in_time(to)?); }
+
+            } else if let Some(t) = word.strip_prefix("type:") {
+                crit.path_type =
+                    Some(match t {
+                        "f" => PathRecordType::File,
+                        "d" => PathRecordType::Dir,
+                        "l" => PathRecordType::SymLink,
+                        t   => return Err(format!("bad type '{}', expected f, d or l", t)),
+                    });
+
+            } else if let Some(c) = word.strip_prefix("content:") {
+                crit.content =
+                    Some(match as_regex(c) {
+                        Some(re) => ContentMatch::Regex(parse_regex(re)?),
+                        None     => ContentMatch::Text(c.to_string()),
+                    });
+
+            } else if let Some(re) = as_regex(&word) {
+                crit.names.push(NameMatch::Regex(parse_regex(re)?));
+
+            } else if word.contains(|c| c == '*' || c == '?' || c == '[') {
+                crit.names.push(NameMatch::Glob(word));
+
+            } else {
+                crit.names.push(NameMatch::Glob(format!("*{}*", word)));
+            }
+        }
+
+        Ok(crit)
+    }
+
+    fn matches_name(&self, name: &str) -> bool {
+        self.names.is_empty()
+        || self.names.iter().any(|n| match n {
+            NameMatch::Glob(g)  => glob_match_nocase(g, name),
+            NameMatch::Regex(r) => r.is_match(name),
+        })
+    }
+
+    fn matches_content(&self, path: &std::path::Path) -> bool {
+        use std::io::BufRead;
+
+        let content = match &self.content {
+            Some(c) => c,
+            None    => return true,
+        };
+        // FIFOs, sockets and devices are listed as files too, but reading
+        // them could block for good:
+        if !path.symlink_metadata().map(|md| md.is_file()).unwrap_or(false) {
+            return false;
+        }
+        if is_binary_file(path).unwrap_or(true) {
+            return false;
+        }
+        let f = match std::fs::File::open(path) {
+            Ok(f)  => f,
+            Err(_) => return false,
+        };
+
+        let mut rd  = std::io::BufReader::new(f);
+        let mut buf = vec![];
+        loop {
+            buf.clear();
+            match rd.read_until(b'\n', &mut buf) {
+                Ok(0) | Err(_) => return false,
+                Ok(_) => {
+                    let line = String::from_utf8_lossy(&buf);
+                    let found =
+                        match content {
+                            ContentMatch::Text(t)  => line.contains(t.as_str()),
+                            ContentMatch::Regex(r) => r.is_match(&line),
+                        };
+                    if found { return true; }
+                },
+            }
+        }
+    }
+
+    /// Checks everything but the content, which is the expensive part.
+    fn matches_record(&self, rec: &PathRecord) -> bool {
+        let name =
+            rec.path.file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_default();
+
+        if !self.matches_name(&name) { return false; }
+        if let Some(t) = self.path_type {
+            if t != rec.path_type { return false; }
+        }
+        // The size of a directory says nothing about its content:
+        let has_size = self.min_size.is_some() || self.max_size.is_some();
+        if has_size && rec.path_type == PathRecordType::Dir {
+            return false;
+        }
+        if let Some(min) = self.min_size {
+            if rec.size < min { return false; }
+        }
+        if let Some(max) = self.max_size {
+            if rec.size > max { return false; }
+        }
+        if let Some(t) = self.newer_than {
+            if rec.mtime < t { return false; }
+        }
+        if let Some(t) = self.older_than {
+            if rec.mtime > t { return false; }
+        }
+        if self.content.is_some() && rec.path_type != PathRecordType::File {
+            return false;
+        }
+        true
+    }
+
+    pub fn matches(&self, rec: &PathRecord) -> bool {
+        self.matches_record(rec) && self.matches_content(&rec.path)
+    }
+}
+
+pub enum FindMsg {
+    Found(Vec<PathRecord>),
+    Error(String),
+    Done,
+}
+
+/// Walks a directory tree on a thread and sends the matching entries.
+/// Symbolic links are not followed. Dropping the job stops the walk.
+pub struct FindJob {
+    cancel: Arc<AtomicBool>,
+    rx:     Receiver<FindMsg>,
+}
+
+impl FindJob {
+    pub fn start(base: &std::path::Path, criteria: FindCriteria) -> FindJob {
+        let cancel = Arc::new(AtomicBool::new(false));
+        let (tx, rx) = channel();
+
+        let base = base.to_path_buf();
+        let stop = cancel.clone();
+        std::thread::spawn(move || {
+            let mut dirs  = vec![base];
+            let mut batch = vec![];
+
+            while let Some(dir) = dirs.pop() {
+                let records =
+                    match read_path_records(&dir) {
+                        Ok(records) => records,
+                        Err(e) => {
+                            let _ = tx.send(FindMsg::Error(
+                                format!("{}: {:?}", dir.to_string_lossy(), e)));
+                            continue;
+                        },
+                    };
+
+                for rec in records {
+                    if stop.load(Ordering::Relaxed) {
+                        return;
+                    }
+                    if rec.path_type == PathRecordType::Dir {
+                        dirs.push(rec.path.clone());
+                    }
+                    if criteria.matches(&rec) {
+                        batch.push(rec);
+                    }
+                    if batch.len() >= RESULT_BATCH {
+                        let b = std::mem::take(&mut batch);
+                        if tx.send(FindMsg::Found(b)).is_err() {
+                            return;
+                        }
+                    }
+                }
+            }
+
+            if !batch.is_empty() {
+                let _ = tx.send(FindMsg::Found(batch));
+            }
+            let _ = tx.send(FindMsg::Done);
+        });
+
+        FindJob { cancel, rx }
+    }
+
+    pub fn poll(&self) -> Vec<FindMsg> {
+        let mut msgs = vec![];
+        loop {
+            match self.rx.try_recv() {
+                Ok(msg) => msgs.push(msg),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    msgs.push(FindMsg::Done);
+                    break;
+                },
+            }
+        }
+        msgs
+    }
+}
+
+impl Drop for FindJob {
+    fn drop(&mut self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+}
+
+/// The results of a find, with paths relative to the searched
+/// directory. Operations like copy and delete work on the selected
+/// results, or on all of them if nothing is selected.
+pub struct SearchResultSheet {
+    pub base:            std::path::PathBuf,
+    pub query:           String,
+    pub results:         Vec<PathRecord>,
+    pub selection:       std::collections::HashSet<usize>,
+    /// The directories that couldn't be read.
+    pub errors:          Vec<String>,
+    pub job:             Option<FindJob>,
+    pub results_dirty:   bool,
+    pub render_feedback: RenderFeedback,
+    pub cursor:          PageCursor,
+    pub rendered:        TableRef,
+}
+
+impl SearchResultSheet {
+    pub fn new(base: &std::path::Path, query: &str, criteria: FindCriteria) -> Self {
+        SearchResultSheet {
+            base:            base.to_path_buf(),
+            query:           query.to_string(),
+            results:         vec![],
+            selection:       std::collections::HashSet::new(),
+            errors:          vec![],
+            job:             Some(FindJob::start(base, criteria)),
+            results_dirty:   true,
+            render_feedback: RenderFeedback::new(),
+            cursor:          PageCursor::new(),
+            rendered:        Table::new_ref(),
+        }
+    }
+
+    fn relative_name(&self, rec: &PathRecord) -> String {
+        let rel = rec.path.strip_prefix(&self.base).unwrap_or(&rec.path);
+        let mut name = rel.to_string_lossy().to_string();
+        if rec.path_type == PathRecordType::Dir {
+            name += &std::path::MAIN_SEPARATOR.to_string();
+        }
+        name
+    }
+}
+
+impl FmPage for SearchResultSheet {
+    fn len(&self) -> usize { self.results.len() }
+    fn get_scroll_offs(&self) -> usize { self.cursor.scroll_offset }
+    fn is_cursor_idx(&self, idx: usize) -> bool { self.cursor.is_cursor_idx(idx) }
+    fn is_selected(&self, idx: usize) -> bool { self.selection.contains(&idx) }
+    fn is_highlighted(&self, _idx: usize) -> bool { false }
+    fn needs_repage(&self) -> bool { self.results_dirty }
+    fn needs_redraw(&self) -> bool { true }
+    fn sort_by_column(&mut self, _col_idx: usize) { }
+
+    fn set_render_feedback(&mut self, fb: RenderFeedback) {
+        self.render_feedback = fb;
+    }
+
+    fn is_inside_screen_rect(&self, x: i32, y: i32) -> bool {
+        self.render_feedback.is_inside_screen_rect(x, y)
+    }
+
+    fn cursor_path(&self) -> Option<std::path::PathBuf> {
+        self.results.get(self.cursor.cursor_idx).map(|r| r.path.clone())
+    }
+
+    fn selected_paths(&self) -> Vec<std::path::PathBuf> {
+        if self.selection.is_empty() {
+            return self.results.iter().map(|r| r.path.clone()).collect();
+        }
+        let mut idxs : Vec<usize> = self.selection.iter().copied().collect();
+        idxs.sort();
+        idxs.iter().filter_map(|i| self.results.get(*i)).map(|r| r.path.clone()).collect()
+    }
+
+    fn clear_selection(&mut self) {
+        self.selection.clear();
+    }
+
+    fn is_busy(&self) -> bool { self.job.is_some() }
+
+    fn update(&mut self) -> Option<PageEvent> {
+        let msgs = self.job.as_ref()?.poll();
+        if msgs.is_empty() {
+            return None;
+        }
+
+        for msg in msgs {
+            match msg {
+                FindMsg::Found(mut recs) => self.results.append(&mut recs),
+                FindMsg::Error(e)        => self.errors.push(e),
+                FindMsg::Done            => self.job = None,
+            }
+        }
+        self.results_dirty = true;
+        Some(PageEvent::Redraw)
+    }
+
+    fn do_control(&mut self, ctrl: PageControl) -> Option<PageEvent> {
+        match ctrl {
+            PageControl::Back => Some(PageEvent::Close),
+            PageControl::Access => {
+                self.cursor_path().map(PageEvent::ShowEntry)
+            },
+            PageControl::ToggleSelect => {
+                let idx = self.cursor.cursor_idx;
+                if idx < self.results.len() && !self.selection.remove(&idx) {
+                    self.selection.insert(idx);
+                }
+                self.cursor.do_control(self.results.len(), &self.render_feedback, PageControl::CursorDown);
+                None
+            },
+            PageControl::Refresh => {
+                // Drop the results that were deleted or moved away:
+                let selected : Vec<std::path::PathBuf> =
+                    self.selection.iter()
+                        .filter_map(|i| self.results.get(*i))
+                        .map(|r| r.path.clone())
+                        .collect();
+                self.results.retain(|r| r.path.symlink_metadata().is_ok());
+                self.selection =
+                    self.results.iter().enumerate()
+                        .filter(|(_, r)| selected.contains(&r.path))
+                        .map(|(i, _)| i)
+                        .collect();
+                self.results_dirty = true;
+                self.cursor.do_control(self.results.len(), &self.render_feedback, ctrl);
+                None
+            },
+            _ => {
+                self.cursor.do_control(self.results.len(), &self.render_feedback, ctrl);
+                None
+            },
+        }
+    }
+
+    fn as_drawable_table(&mut self) -> TableRef {
+        if !self.results_dirty {
+            return self.rendered.clone();
+        }
+        self.results_dirty = false;
+
+        let mut status = format!("{} found", self.results.len());
+        if !self.errors.is_empty() {
+            status += &format!(", {} unreadable", self.errors.len());
+        }
+        if self.job.is_some() {
+            status += ", searching...";
+        }
+
+        self.rendered =
+            std::rc::Rc::new(std::cell::RefCell::new(Table {
+                title: format!("find '{}' in {} [{}]",
+                    self.query, self.base.to_string_lossy(), status),
+                row_gap: 2,
+                col_gap: 4,
+                columns: vec![
+                    Column {
+                        head: String::from("path"),
+                        size: ColumnSizing::ExpandFract(1),
+                        calc_size: None,
+                        rows: self.results.iter().map(|r| StyleString {
+                            text: self.relative_name(r),
+                            style: match r.path_type {
+                                PathRecordType::File    => Style::File,
+                                PathRecordType::Dir     => Style::Dir,
+                                PathRecordType::SymLink => Style::Special,
+                            },
+                            highlight: vec![],
+                        }).collect(),
+                    },
+                    Column {
+                        head: String::from("time"),
+                        size: ColumnSizing::TextWidth(String::from("MMMM-MM-MM MM:MM:MM")),
+                        calc_size: None,
+                        rows: self.results.iter().map(|r| StyleString {
+                            text: crate::attr_ops::format_time(r.mtime),
+                            style: Style::Default,
+                            highlight: vec![],
+                        }).collect(),
+                    },
+                    Column {
+                        head: String::from("size"),
+                        size: ColumnSizing::TextWidth(String::from("MMMMMMMM")),
+                        calc_size: None,
+                        rows: self.results.iter().map(|r| StyleString {
+                            text: format_size(r.size),
+                            style: Style::Default,
+                            highlight: vec![],
+                        }).collect(),
+                    },
+                ],
+            }));
+        self.rendered.clone()
+    }
+}
@@ -1,18 +1,62 @@
 use std::rc::Rc;
 use std::cell::RefCell;
 
+/// The kind of a directory entry, which picks its color from
+/// `LS_COLORS`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FileKind {
+    File,
+    Dir,
+    Link,
+    /// Symbolic links pointing to nothing.
+    BrokenLink,
+    Executable,
+    Archive,
+    Image,
+    /// Audio and video files.
+    Media,
+    Source,
+    /// Regular files whose name starts with a dot.
+    Hidden,
+    Socket,
+    Fifo,
+    /// Block and character devices.
+    Device,
+    /// Files with the setuid or setgid bit.
+    Setuid,
+    /// Directories with the sticky bit, like `/tmp`.
+    StickyDir,
+}
+
 #[derive(Debug)]
 pub enum Style {
     Default,
     Dir,
     File,
     Special,
+    /// The name of an entry in a directory listing.
+    Entry(FileKind),
+    /// Entries matched by the ignore rules, drawn dimmed.
+    Ignored,
+    /// Lines of a comparison.
+    Added,
+    Removed,
+    Changed,
+    /// Results of a check, like a matching checksum or an error message.
+    Success,
+    Error,
+    /// Draws a bar over the given fraction of the cell width behind the
+    /// text, like for disk usage.
+    Bar(f32),
 }
 
 #[derive(Debug)]
 pub struct StyleString {
     pub text: String,
     pub style: Style,
+    /// Character ranges `(start, end)` of `text` that are drawn highlighted,
+    /// for instance search matches.
+    pub highlight: std::vec::Vec<(usize, usize)>,
 }
 
 #[derive(Debug)]
@@ -54,7 +98,7 @@ impl Table {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 pub enum PageControl {
     Refresh,
     Back,
@@ -63,9 +107,64 @@ pub enum PageControl {
     CursorUp,
     Click((i32, i32)),
     Scroll(i32),
+    /// Search for the given text, starting at the cursor, so a match
+    /// under the cursor is found.
+    Search(String),
+    /// Repeat the last search, starting after the last match.
+    SearchNext,
+    /// Move the cursor to the given position, like a line number or
+    /// a byte offset.
+    Goto(String),
+    /// Switch between the display modes of the page, like line wrapping.
+    CycleMode,
+    /// The text entered for a `PageEvent::Prompt` of the page.
+    Edit(String),
+    /// Select or unselect the cursor entry and move on to the next one.
+    ToggleSelect,
 }
 
-#[derive(Debug)]
+/// Requests a page makes to the `FileManager` in response to a control
+/// or to background work finishing.
+#[derive(Debug, Clone)]
+pub enum PageEvent {
+    /// The page content changed and needs to be drawn again.
+    Redraw,
+    /// Remove the page from the pane.
+    Close,
+    /// Show the directory listing of the path in the pane.
+    OpenDir(std::path::PathBuf),
+    /// Show the directory listing of the path in a new tab of the pane.
+    OpenTab(std::path::PathBuf),
+    /// Go to the entry with the index in the history of the current tab.
+    GotoHistory(usize),
+    /// Show the directory listing containing the path, with the cursor
+    /// on it.
+    ShowEntry(std::path::PathBuf),
+    /// Open the file in the internal text viewer at the line.
+    ViewFile(std::path::PathBuf, usize),
+    /// Open the file with its associated program.
+    OpenFile(std::path::PathBuf),
+    /// Start a program detached, with the arguments and the working
+    /// directory.
+    Launch(std::vec::Vec<String>, std::path::PathBuf),
+    /// Ask the user for a value in the input line, with the prompt and
+    /// the initial text. The answer comes back as `PageControl::Edit`.
+    Prompt(String, String),
+    /// Messages for the log.
+    Log(std::vec::Vec<String>),
+    /// The file system may have changed, read the directory listings
+    /// again.
+    RefreshDirs,
+}
+
+/// How the picture of a page is scaled into the space below its table.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImageScale {
+    Fit,
+    Factor(f32),
+}
+
+#[derive(Debug, Clone)]
 pub struct RenderFeedback {
     pub recent_line_count: usize,
     pub row_offset:        usize,
@@ -104,7 +203,7 @@ pub trait FmPage {
     fn len(&self) -> usize;
     fn as_drawable_table(&mut self) -> Rc<RefCell<Table>>;
     fn get_scroll_offs(&self) -> usize;
-    fn do_control(&mut self, ctrl: PageControl);
+    fn do_control(&mut self, ctrl: PageControl) -> Option<PageEvent>;
     fn is_inside_screen_rect(&self, x: i32, y: i32) -> bool;
     fn is_cursor_idx(&self, idx: usize) -> bool;
     fn is_selected(&self, idx: usize) -> bool;
@@ -115,4 +214,30 @@ pub trait FmPage {
     fn sort_by_column(&mut self, col_idx: usize);
 
     fn set_render_feedback(&mut self, fb: RenderFeedback);
+
+    /// The file system entry under the cursor, if the page lists any.
+    fn cursor_path(&self) -> Option<std::path::PathBuf> { None }
+
+    /// The line number of the cursor, starting at 1, for pages showing
+    /// the lines of a file.
+    fn cursor_line(&self) -> Option<usize> { None }
+
+    /// The selected file system entries, in listing order.
+    fn selected_paths(&self) -> std::vec::Vec<std::path::PathBuf> { vec![] }
+
+    fn clear_selection(&mut self) { }
+
+    /// Polls background work of the page. Called once per event loop
+    /// iteration.
+    fn update(&mut self) -> Option<PageEvent> { None }
+
+    /// Whether the page is still waiting for background work. The event
+    /// loop polls more often while this is true.
+    fn is_busy(&self) -> bool { false }
+
+    /// Stops the background work of the page, like a running command.
+    fn cancel(&mut self) { }
+
+    /// A picture to draw below the table, like the image of an image viewer.
+    fn image(&self) -> Option<(&sdl2::surface::Surface<'static>, ImageScale)> { None }
 }
@@ -0,0 +1,385 @@
+use crate::fm_page::*;
+use crate::cursor::PageCursor;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+
+/// When the ranks of all directories add up to more than this, they
+/// are aged, so old favorites make room for new ones.
+const MAX_TOTAL_RANK : f64 = 10000.0;
+/// Visits are saved at most this often, and on exit.
+const SAVE_INTERVAL_SECS : u64 = 30;
+
+/// The data directory of the file manager, following the XDG base
+/// directory specification.
+pub fn data_dir() -> Option<std::path::PathBuf> {
+    let data =
+        match std::env::var_os("XDG_DATA_HOME") {
+            Some(dir) if !dir.is_empty() => std::path::PathBuf::from(dir),
+            _ => std::path::PathBuf::from(std::env::var_os("HOME")?).join(".local/share"),
+        };
+    Some(data.join("wcdemengine"))
+}
+
+fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[derive(Debug, Clone)]
+pub struct DirVisits {
+    pub path: std::path::PathBuf,
+    /// Grows by one with every visit, until the ranks are aged.
+    pub rank: f64,
+    /// The time of the last visit in seconds since the epoch.
+    pub last: u64,
+}
+
+impl DirVisits {
+    /// The rank weighted by how recently the directory was visited,
+    /// like zoxide does it.
+    pub fn score(&self, now: u64) -> f64 {
+        let age = now.saturating_sub(self.last);
+        let factor =
+            if age < 3600 {
+                4.0
+            } else if age < 24 * 3600 {
+                2.0
+            } else if age < 7 * 24 * 3600 {
+                0.5
+            } else {
+                0.25
+            };
+        self.rank * factor
+    }
+}
+
+/// A visited directory matching a query, with the character ranges of
+/// the path that matched.
+pub type DirMatch<'a> = (&'a DirVisits, Vec<(usize, usize)>);
+
+/// The visited directories, stored one `rank<TAB>last<TAB>path` per
+/// line.
+#[derive(Debug, Clone, Default)]
+pub struct FrecencyDb {
+    pub file:    Option<std::path::PathBuf>,
+    pub dirs:    Vec<DirVisits>,
+    /// The time of the first visit that is not saved yet.
+    pub unsaved: Option<std::time::Instant>,
+}
+
+impl FrecencyDb {
+    /// Loads the database from the data directory. A missing file is an
+    /// empty database.
+    pub fn load() -> std::io::Result<FrecencyDb> {
+        match data_dir() {
+            Some(dir) => Self::load_from(&dir.join("dirs")),
+            None      => Ok(FrecencyDb::default()),
+        }
+    }
+
+    pub fn load_from(file: &std::path::Path) -> std::io::Result<FrecencyDb> {
+        let mut db = FrecencyDb { file: Some(file.to_path_buf()), dirs: vec![], unsaved: None };
+        let text =
+            match std::fs::read_to_string(file) {
+                Ok(text) => text,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(db),
+                Err(e) => return Err(e),
+            };
+        for line in text.lines() {
+            let mut parts = line.splitn(3, '\t');
+            let rank = parts.next().and_then(|r| r.parse::<f64>().ok());
+            let last = parts.next().and_then(|l| l.parse::<u64>().ok());
+            if let (Some(rank), Some(last), Some(path)) = (rank, last, parts.next()) {
+                db.dirs.push(DirVisits { path: std::path::PathBuf::from(path), rank, last });
+            }
+        }
+        Ok(db)
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        let file =
+            match &self.file {
+                Some(file) => file,
+                None => return Ok(()),
+            };
+        if let Some(dir) = file.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let mut text = String::new();
+        for d in self.dirs.iter() {
+            text += &format!("{}\t{}\t{}\n", d.rank, d.last, d.path.to_string_lossy());
+        }
+        // Written next to the database and renamed, so a crash does not
+        // leave half of it:
+        let tmp = file.with_extension("tmp");
+        std::fs::write(&tmp, text)?;
+        std::fs::rename(&tmp, file)
+    }
+
+    /// Saves the visits that are not saved yet, once the first of them
+    /// is `SAVE_INTERVAL_SECS` old, or right away if `now` is set.
+    pub fn save_unsaved(&mut self, now: bool) -> std::io::Result<()> {
+        let due =
+            match self.unsaved {
+                Some(t) => now || t.elapsed().as_secs() >= SAVE_INTERVAL_SECS,
+                None    => false,
+            };
+        if !due {
+            return Ok(());
+        }
+        // Not tried again before the next visit if it fails:
+        self.unsaved = None;
+        self.save()
+    }
+
+    /// Records a visit of the directory.
+    pub fn visit(&mut self, path: &std::path::Path) {
+        self.visit_at(path, unix_now());
+    }
+
+    pub fn visit_at(&mut self, path: &std::path::Path, now: u64) {
+        match self.dirs.iter_mut().find(|d| d.path == path) {
+            Some(d) => {
+                d.rank += 1.0;
+                d.last  = now;
+            },
+            None => {
+                self.dirs.push(DirVisits { path: path.to_path_buf(), rank: 1.0, last: now });
+            },
+        }
+        if self.unsaved.is_none() {
+            self.unsaved = Some(std::time::Instant::now());
+        }
+
+        let total : f64 = self.dirs.iter().map(|d| d.rank).sum();
+        if total > MAX_TOTAL_RANK {
+            for d in self.dirs.iter_mut() {
+                d.rank *= 0.9;
+            }
+            self.dirs.retain(|d| d.rank >= 1.0);
+        }
+    }
+
+    /// The directories matching all terms, best first.
+    pub fn query(&self, terms: &[&str], now: u64) -> Vec<DirMatch<'_>> {
+        let mut matches : Vec<(DirMatch<'_>, f64)> =
+            self.dirs.iter().filter_map(|d| {
+                let ranges = match_terms(&d.path.to_string_lossy(), terms)?;
+                Some(((d, ranges), d.score(now)))
+            }).collect();
+        matches.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+        matches.into_iter().map(|(m, _)| m).collect()
+    }
+}
+
+/// Matches the terms case insensitively and in order against the path.
+/// Like in zoxide the last term has to match in the last component of
+/// the path. Returns the matched character ranges.
+pub fn match_terms(path: &str, terms: &[&str]) -> Option<Vec<(usize, usize)>> {
+    let lower = |c: char| c.to_lowercase().next().unwrap_or(c);
+    let hay : Vec<char> = path.chars().map(lower).collect();
+    let last_comp_start =
+        hay.iter().rposition(|c| *c == std::path::MAIN_SEPARATOR).map(|i| i + 1).unwrap_or(0);
+
+    let mut ranges = vec![];
+    let mut pos = 0;
+    for (i, term) in terms.iter().enumerate() {
+        let needle : Vec<char> = term.chars().map(lower).collect();
+        if needle.is_empty() {
+            continue;
+        }
+        let start = (pos..=hay.len().saturating_sub(needle.len()))
+            .find(|s| hay[*s..].starts_with(&needle))?;
+        let end = start + needle.len();
+        if i == terms.len() - 1 && end <= last_comp_start {
+            // Try to find the last term in the last component:
+            let start = (last_comp_start.max(pos)..=hay.len().saturating_sub(needle.len()))
+                .find(|s| hay[*s..].starts_with(&needle))?;
+            ranges.push((start, start + needle.len()));
+            return Some(ranges);
+        }
+        ranges.push((start, end));
+        pos = end;
+    }
+    Some(ranges)
+}
+
+/// Checks on a thread which of the visited directories are gone, a
+/// stale network mount can hang the check. The gone ones are sent as
+/// they are found. Dropping the job stops it.
+pub struct GoneDirsJob {
+    cancel: Arc<AtomicBool>,
+    rx:     Receiver<std::path::PathBuf>,
+}
+
+impl GoneDirsJob {
+    pub fn start(dirs: Vec<std::path::PathBuf>) -> GoneDirsJob {
+        let cancel = Arc::new(AtomicBool::new(false));
+        let (tx, rx) = channel();
+        let stop = cancel.clone();
+        std::thread::spawn(move || {
+            for dir in dirs {
+                if stop.load(Ordering::Relaxed) {
+                    return;
+                }
+                if !dir.is_dir() && tx.send(dir).is_err() {
+                    return;
+                }
+            }
+        });
+        GoneDirsJob { cancel, rx }
+    }
+
+    /// Returns the gone directories found since the last call, and
+    /// whether the check is done.
+    pub fn poll(&self) -> (Vec<std::path::PathBuf>, bool) {
+        let mut gone = vec![];
+        loop {
+            match self.rx.try_recv() {
+                Ok(dir) => gone.push(dir),
+                Err(TryRecvError::Empty) => return (gone, false),
+                Err(TryRecvError::Disconnected) => return (gone, true),
+            }
+        }
+    }
+}
+
+impl Drop for GoneDirsJob {
+    fn drop(&mut self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+}
+
+/// The matches of the jump query, updated while it is typed. Access
+/// opens the directory under the cursor, the best match by default.
+/// Directories that are gone drop out of the list once the check on
+/// the job finds them.
+pub struct JumpSheet {
+    pub db:              FrecencyDb,
+    /// The directory of the pane, which is left out of the matches.
+    pub current:         std::path::PathBuf,
+    pub query:           String,
+    pub job:             Option<GoneDirsJob>,
+    pub matches:         Vec<(std::path::PathBuf, Vec<(usize, usize)>)>,
+    pub render_feedback: RenderFeedback,
+    pub cursor:          PageCursor,
+    pub rendered:        TableRef,
+}
+
+impl JumpSheet {
+    pub fn new(db: FrecencyDb, current: &std::path::Path) -> JumpSheet {
+        let mut js = JumpSheet {
+            db,
+            current:         current.to_path_buf(),
+            query:           String::new(),
+            job:             None,
+            matches:         vec![],
+            render_feedback: RenderFeedback::new(),
+            cursor:          PageCursor::new(),
+            rendered:        Table::new_ref(),
+        };
+        js.job = Some(GoneDirsJob::start(js.db.dirs.iter().map(|d| d.path.clone()).collect()));
+        js.update_matches();
+        js
+    }
+
+    fn update_matches(&mut self) {
+        let terms : Vec<&str> = self.query.split_whitespace().collect();
+        let now = unix_now();
+        let current = &self.current;
+        self.matches =
+            self.db.query(&terms, now).into_iter()
+                .filter(|(d, _)| d.path != *current)
+                .map(|(d, ranges)| (d.path.clone(), ranges))
+                .collect();
+        self.cursor.cursor_idx    = 0;
+        self.cursor.scroll_offset = 0;
+    }
+}
+
+impl FmPage for JumpSheet {
+    fn len(&self) -> usize { self.matches.len() }
+    fn get_scroll_offs(&self) -> usize { self.cursor.scroll_offset }
+    fn is_cursor_idx(&self, idx: usize) -> bool { self.cursor.is_cursor_idx(idx) }
+    fn is_selected(&self, _idx: usize) -> bool { false }
+    fn is_highlighted(&self, _idx: usize) -> bool { false }
+    fn needs_repage(&self) -> bool { true }
+    fn needs_redraw(&self) -> bool { true }
+    fn sort_by_column(&mut self, _col_idx: usize) { }
+    fn is_busy(&self) -> bool { self.job.is_some() }
+
+    fn update(&mut self) -> Option<PageEvent> {
+        let (gone, done) = self.job.as_ref()?.poll();
+        if done {
+            self.job = None;
+        }
+        if gone.is_empty() {
+            return None;
+        }
+
+        self.db.dirs.retain(|d| !gone.contains(&d.path));
+        self.matches.retain(|(path, _)| !gone.contains(path));
+        self.cursor.do_control(self.matches.len(), &self.render_feedback, PageControl::Refresh);
+        Some(PageEvent::Redraw)
+    }
+
+    fn set_render_feedback(&mut self, fb: RenderFeedback) {
+        self.render_feedback = fb;
+    }
+
+    fn is_inside_screen_rect(&self, x: i32, y: i32) -> bool {
+        self.render_feedback.is_inside_screen_rect(x, y)
+    }
+
+    fn cursor_path(&self) -> Option<std::path::PathBuf> {
+        self.matches.get(self.cursor.cursor_idx).map(|(p, _)| p.clone())
+    }
+
+    fn do_control(&mut self, ctrl: PageControl) -> Option<PageEvent> {
+        match ctrl {
+            PageControl::Search(query) => {
+                self.query = query;
+                self.update_matches();
+                None
+            },
+            PageControl::Access => {
+                match self.cursor_path() {
+                    Some(path) => Some(PageEvent::OpenDir(path)),
+                    None       => Some(PageEvent::Close),
+                }
+            },
+            PageControl::Back => Some(PageEvent::Close),
+            _ => {
+                self.cursor.do_control(self.len(), &self.render_feedback, ctrl);
+                None
+            },
+        }
+    }
+
+    fn as_drawable_table(&mut self) -> TableRef {
+        let title = format!("jump {} [{} matches]", self.query, self.matches.len());
+
+        self.rendered =
+            std::rc::Rc::new(std::cell::RefCell::new(Table {
+                title,
+                row_gap: 2,
+                col_gap: 4,
+                columns: vec![
+                    Column {
+                        head: String::from("directory"),
+                        size: ColumnSizing::ExpandFract(1),
+                        calc_size: None,
+                        rows: self.matches.iter().map(|(path, ranges)| StyleString {
+                            text: path.to_string_lossy().to_string(),
+                            style: Style::Dir,
+                            highlight: ranges.clone(),
+                        }).collect(),
+                    },
+                ],
+            }));
+        self.rendered.clone()
+    }
+}
@@ -0,0 +1,514 @@
+use crate::fm_page::*;
+use crate::cursor::PageCursor;
+use crate::ignore::IgnoreStack;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+
+/// Indexed files are sent to the page in batches of this size.
+const FILE_BATCH : usize = 256;
+/// At most this many files are indexed.
+const MAX_FILES : usize = 200_000;
+/// Only the best matches are listed.
+const MAX_SHOWN : usize = 500;
+/// While indexing, the matches are sent at most this often.
+const SEND_INTERVAL_MS : u64 = 50;
+
+fn lower(c: char) -> char {
+    c.to_lowercase().next().unwrap_or(c)
+}
+
+/// Matches the characters of the pattern in order against the text,
+/// ignoring case and whitespace in the pattern. Returns a score, higher
+/// is better, and the character positions that matched. Consecutive
+/// matches, matches at word starts and in the file name score higher.
+pub fn fuzzy_match(pattern: &str, text: &str) -> Option<(i64, Vec<usize>)> {
+    let pat : Vec<char> = pattern.chars().filter(|c| !c.is_whitespace()).map(lower).collect();
+    if pat.is_empty() {
+        return Some((0, vec![]));
+    }
+    let chars : Vec<char> = text.chars().collect();
+    let lc    : Vec<char> = chars.iter().map(|c| lower(*c)).collect();
+
+    // The first end of a match, then the shortest match ending there:
+    let mut pi  = 0;
+    let mut end = None;
+    for (i, c) in lc.iter().enumerate() {
+        if *c == pat[pi] {
+            pi += 1;
+            if pi == pat.len() {
+                end = Some(i);
+                break;
+            }
+        }
+    }
+    let end = end?;
+    let mut pi    = pat.len();
+    let mut start = 0;
+    for i in (0..=end).rev() {
+        if lc[i] == pat[pi - 1] {
+            pi -= 1;
+            if pi == 0 {
+                start = i;
+                break;
+            }
+        }
+    }
+
+    let mut positions = vec![];
+    let mut pi = 0;
+    for i in start..=end {
+        if pi < pat.len() && lc[i] == pat[pi] {
+            positions.push(i);
+            pi += 1;
+        }
+    }
+
+    let name_start =
+        chars.iter().rposition(|c| *c == std::path::MAIN_SEPARATOR).map(|i| i + 1).unwrap_or(0);
+    let mut score = 0;
+    let mut prev : Option<usize> = None;
+    for p in positions.iter().copied() {
+        score += 16;
+        if let Some(q) = prev {
+            if p == q + 1 {
+                score += 24;
+            } else {
+                score -= 2 * (p - q - 1).min(10) as i64;
+            }
+        }
+        let word_start =
+            p == 0
+            || matches!(chars[p - 1], '_' | '-' | '.' | ' ')
+            || chars[p - 1] == std::path::MAIN_SEPARATOR
+            || (chars[p].is_uppercase() && chars[p - 1].is_lowercase());
+        if word_start {
+            score += 20;
+        }
+        if p >= name_start {
+            score += 8;
+        }
+        prev = Some(p);
+    }
+    // Shorter paths win between otherwise equal matches:
+    score -= chars.len() as i64 / 8;
+    Some((score, positions))
+}
+
+/// Joins matched character positions into the ranges for highlighting.
+fn position_ranges(positions: &[usize]) -> Vec<(usize, usize)> {
+    let mut ranges : Vec<(usize, usize)> = vec![];
+    for p in positions.iter().copied() {
+        match ranges.last_mut() {
+            Some(r) if r.1 == p => r.1 = p + 1,
+            _ => ranges.push((p, p + 1)),
+        }
+    }
+    ranges
+}
+
+/// Checks cheaply if the lower case characters of `pat` occur in order
+/// in `text`, before scoring it.
+fn contains_in_order(pat: &[char], text: &str) -> bool {
+    let mut pi = 0;
+    for c in text.chars() {
+        if pi == pat.len() {
+            break;
+        }
+        if lower(c) == pat[pi] {
+            pi += 1;
+        }
+    }
+    pi == pat.len()
+}
+
+/// A file matching the query, with the highlighted ranges.
+#[derive(Debug, Clone)]
+pub struct FuzzyMatch {
+    pub path:   String,
+    pub ranges: Vec<(usize, usize)>,
+}
+
+/// The best matches of the files scored so far for one query. Only
+/// about `MAX_SHOWN` are kept, so a new batch of files doesn't sort all
+/// matches again.
+struct Ranking {
+    query:  String,
+    pat:    Vec<char>,
+    /// Index into the files, score and matched positions.
+    top:    Vec<(usize, i64, Vec<usize>)>,
+    count:  usize,
+    scored: usize,
+    dirty:  bool,
+}
+
+impl Ranking {
+    fn new(query: String) -> Ranking {
+        let pat = query.chars().filter(|c| !c.is_whitespace()).map(lower).collect();
+        Ranking { query, pat, top: vec![], count: 0, scored: 0, dirty: true }
+    }
+
+    /// Higher scores first, equal scores keep the order of the walk.
+    fn cmp(a: &(usize, i64, Vec<usize>), b: &(usize, i64, Vec<usize>)) -> std::cmp::Ordering {
+        b.1.cmp(&a.1).then(a.0.cmp(&b.0))
+    }
+
+    fn score_next(&mut self, files: &[String]) {
+        let idx  = self.scored;
+        let file = &files[idx];
+        self.scored += 1;
+        if !contains_in_order(&self.pat, file) {
+            return;
+        }
+        if let Some((score, positions)) = fuzzy_match(&self.query, file) {
+            self.top.push((idx, score, positions));
+            self.count += 1;
+            self.dirty = true;
+            if self.top.len() >= 2 * MAX_SHOWN {
+                self.trim();
+            }
+        }
+    }
+
+    fn trim(&mut self) {
+        if self.top.len() > MAX_SHOWN {
+            self.top.select_nth_unstable_by(MAX_SHOWN - 1, Self::cmp);
+            self.top.truncate(MAX_SHOWN);
+        }
+    }
+
+    fn matches(&mut self, files: &[String]) -> Vec<FuzzyMatch> {
+        self.trim();
+        self.top.sort_by(Self::cmp);
+        self.dirty = false;
+        self.top.iter().map(|(idx, _, positions)| FuzzyMatch {
+            path:   files[*idx].clone(),
+            ranges: position_ranges(positions),
+        }).collect()
+    }
+}
+
+/// The latest of the queries sent to the job, if any.
+fn latest_query(queries: &Receiver<String>) -> Option<String> {
+    let mut query = None;
+    while let Ok(q) = queries.try_recv() {
+        query = Some(q);
+    }
+    query
+}
+
+/// Scores the files not scored yet, and starts over when a newer query
+/// arrives meanwhile. Returns false if the job was stopped.
+fn rank_files(ranking: &mut Ranking, files: &[String], queries: &Receiver<String>,
+              stop: &AtomicBool) -> bool {
+    if let Some(query) = latest_query(queries) {
+        *ranking = Ranking::new(query);
+    }
+    while ranking.scored < files.len() {
+        if ranking.scored % 1024 == 0 {
+            if stop.load(Ordering::Relaxed) {
+                return false;
+            }
+            if let Some(query) = latest_query(queries) {
+                *ranking = Ranking::new(query);
+                continue;
+            }
+        }
+        ranking.score_next(files);
+    }
+    true
+}
+
+pub enum IndexMsg {
+    /// The best matches for a query, the number of matching and of
+    /// indexed files.
+    Matches { query: String, matches: Vec<FuzzyMatch>, count: usize, files: usize },
+    /// The index is complete.
+    Done,
+}
+
+/// Collects the paths of the files below a directory on a thread,
+/// relative to it, and ranks them by the query. Everything the
+/// `.gitignore` files exclude is left out, and hidden entries unless
+/// `show_hidden` is set. After the walk the thread waits for new
+/// queries. Dropping the job stops it.
+pub struct IndexJob {
+    cancel:  Arc<AtomicBool>,
+    queries: Sender<String>,
+    rx:      Receiver<IndexMsg>,
+}
+
+impl IndexJob {
+    pub fn start(base: &std::path::Path, show_hidden: bool) -> IndexJob {
+        let cancel = Arc::new(AtomicBool::new(false));
+        let (tx, rx) = channel();
+        let (queries, query_rx) = channel::<String>();
+
+        let base = base.to_path_buf();
+        let stop = cancel.clone();
+        std::thread::spawn(move || {
+            let mut dirs    = vec![(base.clone(), IgnoreStack::for_dir(&base))];
+            let mut files   = vec![];
+            let mut ranking = Ranking::new(String::new());
+            let mut sent    = (String::new(), 0, std::time::Instant::now());
+
+            // Sends the matches when they changed, at most every
+            // SEND_INTERVAL_MS while indexing unless the query changed:
+            let mut send = |ranking: &mut Ranking, files: &[String], force: bool| -> bool {
+                let new_query = ranking.query != sent.0;
+                if !(ranking.dirty || new_query || files.len() != sent.1) {
+                    return true;
+                }
+                if !force && !new_query
+                   && sent.2.elapsed() < std::time::Duration::from_millis(SEND_INTERVAL_MS) {
+                    return true;
+                }
+                sent = (ranking.query.clone(), files.len(), std::time::Instant::now());
+                tx.send(IndexMsg::Matches {
+                    query:   ranking.query.clone(),
+                    matches: ranking.matches(files),
+                    count:   ranking.count,
+                    files:   files.len(),
+                }).is_ok()
+            };
+
+            'walk: while let Some((dir, ignore)) = dirs.pop() {
+                let mut entries : Vec<(std::path::PathBuf, bool)> =
+                    match std::fs::read_dir(&dir) {
+                        Ok(rd) => rd.filter_map(|e| e.ok()).map(|e| {
+                            let is_dir = e.file_type().map(|ft| ft.is_dir()).unwrap_or(false);
+                            (e.path(), is_dir)
+                        }).collect(),
+                        Err(_) => continue,
+                    };
+                entries.sort();
+
+                for (path, is_dir) in entries.into_iter().rev() {
+                    if stop.load(Ordering::Relaxed) {
+                        return;
+                    }
+                    let hidden =
+                        path.file_name().map(|n| n.to_string_lossy().starts_with('.')).unwrap_or(false);
+                    if (hidden && !show_hidden) || ignore.is_ignored(&path, is_dir) {
+                        continue;
+                    }
+                    if is_dir {
+                        let sub = ignore.enter(&path);
+                        dirs.push((path, sub));
+                        continue;
+                    }
+
+                    if let Ok(rel) = path.strip_prefix(&base) {
+                        files.push(rel.to_string_lossy().to_string());
+                    }
+                    if files.len() >= MAX_FILES {
+                        break 'walk;
+                    }
+                    if files.len() % FILE_BATCH == 0
+                       && !(rank_files(&mut ranking, &files, &query_rx, &stop)
+                            && send(&mut ranking, &files, false)) {
+                        return;
+                    }
+                }
+
+                if !(rank_files(&mut ranking, &files, &query_rx, &stop)
+                     && send(&mut ranking, &files, false)) {
+                    return;
+                }
+            }
+
+            if !(rank_files(&mut ranking, &files, &query_rx, &stop)
+                 && send(&mut ranking, &files, true)
+                 && tx.send(IndexMsg::Done).is_ok()) {
+                return;
+            }
+
+            // Until the job is dropped:
+            while let Ok(query) = query_rx.recv() {
+                ranking = Ranking::new(query);
+                if !(rank_files(&mut ranking, &files, &query_rx, &stop)
+                     && send(&mut ranking, &files, true)) {
+                    return;
+                }
+            }
+        });
+
+        IndexJob { cancel, queries, rx }
+    }
+
+    /// Ranks the files by a new query, the result comes as a
+    /// `Matches` message.
+    pub fn set_query(&self, query: &str) {
+        let _ = self.queries.send(query.to_string());
+    }
+
+    pub fn poll(&self) -> Vec<IndexMsg> {
+        let mut msgs = vec![];
+        loop {
+            match self.rx.try_recv() {
+                Ok(msg) => msgs.push(msg),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    msgs.push(IndexMsg::Done);
+                    break;
+                },
+            }
+        }
+        msgs
+    }
+}
+
+impl Drop for IndexJob {
+    fn drop(&mut self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+}
+
+/// The files below a directory, ranked by how well they match the
+/// query typed in the input line. Access shows the file in its
+/// directory.
+pub struct FuzzySheet {
+    pub base:            std::path::PathBuf,
+    pub query:           String,
+    /// The query `matches` belong to, behind `query` while it is ranked.
+    pub matched_query:   String,
+    /// The best `MAX_SHOWN` matches.
+    pub matches:         Vec<FuzzyMatch>,
+    pub match_count:     usize,
+    pub file_count:      usize,
+    pub indexing:        bool,
+    pub matches_dirty:   bool,
+    pub job:             IndexJob,
+    pub render_feedback: RenderFeedback,
+    pub cursor:          PageCursor,
+    pub rendered:        TableRef,
+}
+
+impl FuzzySheet {
+    pub fn new(base: &std::path::Path, show_hidden: bool) -> FuzzySheet {
+        FuzzySheet {
+            base:            base.to_path_buf(),
+            query:           String::new(),
+            matched_query:   String::new(),
+            matches:         vec![],
+            match_count:     0,
+            file_count:      0,
+            indexing:        true,
+            matches_dirty:   true,
+            job:             IndexJob::start(base, show_hidden),
+            render_feedback: RenderFeedback::new(),
+            cursor:          PageCursor::new(),
+            rendered:        Table::new_ref(),
+        }
+    }
+
+    fn set_query(&mut self, query: String) {
+        if query != self.query {
+            self.job.set_query(&query);
+            self.query = query;
+            self.matches_dirty = true;
+        }
+    }
+}
+
+impl FmPage for FuzzySheet {
+    fn len(&self) -> usize { self.matches.len() }
+    fn get_scroll_offs(&self) -> usize { self.cursor.scroll_offset }
+    fn is_cursor_idx(&self, idx: usize) -> bool { self.cursor.is_cursor_idx(idx) }
+    fn is_selected(&self, _idx: usize) -> bool { false }
+    fn is_highlighted(&self, _idx: usize) -> bool { false }
+    fn needs_repage(&self) -> bool { self.matches_dirty }
+    fn needs_redraw(&self) -> bool { true }
+    fn sort_by_column(&mut self, _col_idx: usize) { }
+
+    fn set_render_feedback(&mut self, fb: RenderFeedback) {
+        self.render_feedback = fb;
+    }
+
+    fn is_inside_screen_rect(&self, x: i32, y: i32) -> bool {
+        self.render_feedback.is_inside_screen_rect(x, y)
+    }
+
+    fn cursor_path(&self) -> Option<std::path::PathBuf> {
+        let m = self.matches.get(self.cursor.cursor_idx)?;
+        Some(self.base.join(&m.path))
+    }
+
+    fn is_busy(&self) -> bool { self.indexing || self.matched_query != self.query }
+
+    fn update(&mut self) -> Option<PageEvent> {
+        let msgs = self.job.poll();
+        if msgs.is_empty() {
+            return None;
+        }
+
+        for msg in msgs {
+            match msg {
+                IndexMsg::Matches { query, matches, count, files } => {
+                    if query != self.matched_query {
+                        self.cursor.cursor_idx    = 0;
+                        self.cursor.scroll_offset = 0;
+                    }
+                    self.matched_query = query;
+                    self.matches       = matches;
+                    self.match_count   = count;
+                    self.file_count    = files;
+                },
+                IndexMsg::Done => self.indexing = false,
+            }
+        }
+        self.cursor.do_control(self.matches.len(), &self.render_feedback, PageControl::Refresh);
+        self.matches_dirty = true;
+        Some(PageEvent::Redraw)
+    }
+
+    fn do_control(&mut self, ctrl: PageControl) -> Option<PageEvent> {
+        match ctrl {
+            PageControl::Search(query) => {
+                self.set_query(query);
+                None
+            },
+            PageControl::Access => Some(PageEvent::ShowEntry(self.cursor_path()?)),
+            PageControl::Back => Some(PageEvent::Close),
+            _ => {
+                self.cursor.do_control(self.matches.len(), &self.render_feedback, ctrl);
+                None
+            },
+        }
+    }
+
+    fn as_drawable_table(&mut self) -> TableRef {
+        if !self.matches_dirty {
+            return self.rendered.clone();
+        }
+        self.matches_dirty = false;
+
+        let mut status = format!("{}/{} files", self.match_count, self.file_count);
+        if self.indexing {
+            status += ", indexing...";
+        } else if self.matched_query != self.query {
+            status += ", ranking...";
+        }
+
+        self.rendered =
+            std::rc::Rc::new(std::cell::RefCell::new(Table {
+                title: format!("find '{}' in {} [{}]",
+                    self.query, self.base.to_string_lossy(), status),
+                row_gap: 2,
+                col_gap: 4,
+                columns: vec![
+                    Column {
+                        head: String::from("file"),
+                        size: ColumnSizing::ExpandFract(1),
+                        calc_size: None,
+                        rows: self.matches.iter().map(|m| StyleString {
+                            text: m.path.clone(),
+                            style: Style::File,
+                            highlight: m.ranges.clone(),
+                        }).collect(),
+                    },
+                ],
+            }));
+        self.rendered.clone()
+    }
+}
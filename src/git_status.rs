@@ -0,0 +1,317 @@
+use crate::fm_page::*;
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+
+/// The files of a directory whose modification times are watched, more
+/// would make the check too slow.
+const MAX_WATCHED_FILES : usize = 1000;
+
+/// The git state of a file, or of the contents of a directory.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct GitFlags {
+    pub conflicted: bool,
+    pub staged:     bool,
+    pub modified:   bool,
+    pub untracked:  bool,
+    pub ignored:    bool,
+}
+
+impl GitFlags {
+    fn from_xy(x: char, y: char) -> GitFlags {
+        let mut flags = GitFlags::default();
+        match (x, y) {
+            ('?', '?') => flags.untracked = true,
+            ('!', '!') => flags.ignored = true,
+            ('D', 'D') | ('A', 'A') | ('U', _) | (_, 'U') => flags.conflicted = true,
+            _ => {
+                flags.staged   = x != ' ';
+                flags.modified = y != ' ';
+            },
+        }
+        flags
+    }
+
+    fn merge(&mut self, other: GitFlags) {
+        self.conflicted |= other.conflicted;
+        self.staged     |= other.staged;
+        self.modified   |= other.modified;
+        self.untracked  |= other.untracked;
+        self.ignored    |= other.ignored;
+    }
+
+    /// A short label like `SM` for a staged file with further
+    /// modifications.
+    pub fn label(&self) -> String {
+        let mut s = String::new();
+        if self.conflicted { s.push('U'); }
+        if self.staged     { s.push('S'); }
+        if self.modified   { s.push('M'); }
+        if self.untracked  { s.push('?'); }
+        if self.ignored    { s.push('!'); }
+        s
+    }
+
+    pub fn style(&self) -> Style {
+        if self.conflicted {
+            Style::Special
+        } else if self.staged {
+            Style::Dir
+        } else if self.modified {
+            Style::File
+        } else {
+            Style::Default
+        }
+    }
+}
+
+/// The status of the work tree below a directory, as reported by
+/// `git status`.
+#[derive(Debug, Clone, Default)]
+pub struct GitStatus {
+    pub git_dir: std::path::PathBuf,
+    pub branch:  String,
+    pub ahead:   usize,
+    pub behind:  usize,
+    /// Applies to all entries, if the directory itself is untracked or
+    /// ignored.
+    pub all:     GitFlags,
+    /// The flags by entry name in the directory. Directories summarize
+    /// their contents, their ignored contents are left out.
+    pub entries: std::collections::HashMap<std::ffi::OsString, GitFlags>,
+}
+
+impl GitStatus {
+    pub fn flags(&self, name: &std::ffi::OsStr) -> GitFlags {
+        let mut flags = self.all;
+        if let Some(f) = self.entries.get(name) {
+            flags.merge(*f);
+        }
+        flags
+    }
+
+    /// Describes the branch for the table title, like
+    /// `main, ahead 1, behind 2`.
+    pub fn branch_info(&self) -> String {
+        let mut s = self.branch.clone();
+        if self.ahead > 0  { s += &format!(", ahead {}", self.ahead); }
+        if self.behind > 0 { s += &format!(", behind {}", self.behind); }
+        s
+    }
+}
+
+/// Parses the `## ...` line of `git status --branch`.
+fn parse_branch_line(line: &str) -> (String, usize, usize) {
+    let line = line.trim_start_matches("## ");
+    if let Some(branch) = line.strip_prefix("No commits yet on ") {
+        return (branch.to_string(), 0, 0);
+    }
+    if line.starts_with("HEAD (no branch)") {
+        return (String::from("detached HEAD"), 0, 0);
+    }
+
+    let (refs, counts) =
+        match line.find(" [") {
+            Some(i) => (&line[..i], line[(i + 2)..].trim_end_matches(']')),
+            None    => (line, ""),
+        };
+    let branch = refs.split("...").next().unwrap_or("").to_string();
+
+    let (mut ahead, mut behind) = (0, 0);
+    for count in counts.split(", ") {
+        if let Some(n) = count.strip_prefix("ahead ") {
+            ahead = n.parse().unwrap_or(0);
+        } else if let Some(n) = count.strip_prefix("behind ") {
+            behind = n.parse().unwrap_or(0);
+        }
+    }
+    (branch, ahead, behind)
+}
+
+/// Parses the output of `git status --porcelain -z --branch --ignored`.
+/// `prefix` is the path of the listed directory relative to the top of
+/// the work tree, the entries are collected relative to it.
+pub fn parse_porcelain(output: &str, prefix: &std::path::Path) -> GitStatus {
+    let mut status = GitStatus::default();
+    let mut records = output.split('\0');
+    while let Some(rec) = records.next() {
+        if rec.starts_with("## ") {
+            let (branch, ahead, behind) = parse_branch_line(rec);
+            status.branch = branch;
+            status.ahead  = ahead;
+            status.behind = behind;
+            continue;
+        }
+        if rec.len() < 4 {
+            continue;
+        }
+
+        let mut xy = rec.chars();
+        let x = xy.next().unwrap_or(' ');
+        let y = xy.next().unwrap_or(' ');
+        if x == 'R' || x == 'C' {
+            // The original path of a rename or copy follows:
+            records.next();
+        }
+
+        let flags = GitFlags::from_xy(x, y);
+        let path  = std::path::Path::new(rec[3..].trim_end_matches('/'));
+        match path.strip_prefix(prefix) {
+            Ok(rel) => {
+                let mut comps = rel.components();
+                let name =
+                    match comps.next() {
+                        Some(name) => name.as_os_str().to_os_string(),
+                        None => {
+                            status.all.merge(flags);
+                            continue;
+                        },
+                    };
+                let mut flags = flags;
+                if comps.next().is_some() {
+                    flags.ignored = false;
+                }
+                status.entries.entry(name).or_insert_with(GitFlags::default).merge(flags);
+            },
+            Err(_) => {
+                // An untracked or ignored parent directory:
+                if prefix.starts_with(path) {
+                    status.all.merge(flags);
+                }
+            },
+        }
+    }
+    status
+}
+
+/// Runs git and returns its output. Git is killed when `stop` is set.
+fn git_output(dir: &std::path::Path, args: &[&str], stop: &AtomicBool) -> Option<String> {
+    use std::io::Read;
+
+    let mut child =
+        Command::new("git")
+            .arg("-C").arg(dir)
+            .args(args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn().ok()?;
+    let mut stdout = child.stdout.take()?;
+    let reader = std::thread::spawn(move || {
+        let mut out = vec![];
+        let _ = stdout.read_to_end(&mut out);
+        out
+    });
+
+    loop {
+        if stop.load(Ordering::Relaxed) {
+            let _ = child.kill();
+            let _ = child.wait();
+            return None;
+        }
+        match child.try_wait() {
+            Ok(Some(st)) => {
+                let out = reader.join().ok()?;
+                if !st.success() {
+                    return None;
+                }
+                return Some(String::from_utf8_lossy(&out).to_string());
+            },
+            Ok(None) => std::thread::sleep(std::time::Duration::from_millis(10)),
+            Err(_)   => return None,
+        }
+    }
+}
+
+/// Runs `git status` for a directory, limited to the files below it.
+/// Returns `None` if it is not inside a git work tree, git is not
+/// available or `stop` was set.
+pub fn read_git_status(dir: &std::path::Path, stop: &AtomicBool) -> Option<GitStatus> {
+    let info = git_output(dir, &["rev-parse", "--show-prefix", "--absolute-git-dir"], stop)?;
+    let mut lines = info.lines();
+    let prefix  = lines.next()?.to_string();
+    let git_dir = lines.next()?.to_string();
+
+    let output = git_output(dir, &[
+        "status", "--porcelain", "-z", "--branch", "--ignored=matching",
+        "--untracked-files=normal", "--", "."], stop)?;
+    let mut status = parse_porcelain(&output, std::path::Path::new(&prefix));
+    status.git_dir = std::path::PathBuf::from(git_dir);
+    Some(status)
+}
+
+/// Runs `git status` in the background. Dropping the job kills git.
+pub struct GitStatusJob {
+    cancel: Arc<AtomicBool>,
+    rx:     Receiver<Option<GitStatus>>,
+}
+
+impl GitStatusJob {
+    pub fn start(dir: &std::path::Path) -> GitStatusJob {
+        let cancel = Arc::new(AtomicBool::new(false));
+        let (tx, rx) = channel();
+        let dir  = dir.to_path_buf();
+        let stop = cancel.clone();
+        std::thread::spawn(move || {
+            let _ = tx.send(read_git_status(&dir, &stop));
+        });
+        GitStatusJob { cancel, rx }
+    }
+
+    /// Returns the result once the job is done.
+    pub fn poll(&self) -> Option<Option<GitStatus>> {
+        match self.rx.try_recv() {
+            Ok(status) => Some(status),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => Some(None),
+        }
+    }
+}
+
+impl Drop for GitStatusJob {
+    fn drop(&mut self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Watches the modification times of the git index, `HEAD`, a directory
+/// and its files, to notice when the status has to be read again. Files
+/// edited in place don't change the time of their directory.
+pub struct GitWatch {
+    paths:      Vec<std::path::PathBuf>,
+    stamps:     Vec<Option<std::time::SystemTime>>,
+    last_check: std::time::Instant,
+}
+
+impl GitWatch {
+    pub fn new(git_dir: &std::path::Path, dir: &std::path::Path, files: &[std::path::PathBuf])
+        -> GitWatch {
+
+        let mut paths = vec![git_dir.join("index"), git_dir.join("HEAD"), dir.to_path_buf()];
+        paths.extend(files.iter().take(MAX_WATCHED_FILES).cloned());
+        let stamps = paths.iter().map(|p| Self::stamp(p)).collect();
+        GitWatch { paths, stamps, last_check: std::time::Instant::now() }
+    }
+
+    fn stamp(path: &std::path::Path) -> Option<std::time::SystemTime> {
+        path.metadata().and_then(|md| md.modified()).ok()
+    }
+
+    /// Checks at most once a second whether one of the watched paths
+    /// changed.
+    pub fn changed(&mut self) -> bool {
+        if self.last_check.elapsed() < std::time::Duration::from_secs(1) {
+            return false;
+        }
+        self.last_check = std::time::Instant::now();
+
+        let stamps : Vec<Option<std::time::SystemTime>> =
+            self.paths.iter().map(|p| Self::stamp(p)).collect();
+        if stamps != self.stamps {
+            self.stamps = stamps;
+            return true;
+        }
+        false
+    }
+}
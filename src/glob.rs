@@ -0,0 +1,114 @@
+/// Matches a shell style glob pattern against a string. Supports `*`,
+/// `?` and character classes like `[abc]`, `[a-z]` and `[!0-9]`.
+/// A leading `*` doesn't match a leading `.`, like in the shell.
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let p : Vec<char> = pattern.chars().collect();
+    let t : Vec<char> = text.chars().collect();
+
+    if t.first() == Some(&'.') && p.first() != Some(&'.') && p.first() != Some(&'[') {
+        return false;
+    }
+
+    match_from(&p, &t)
+}
+
+/// Like `glob_match`, but a leading `*` also matches a leading `.`,
+/// like in `.gitignore` files.
+pub fn glob_match_dotfiles(pattern: &str, text: &str) -> bool {
+    let p : Vec<char> = pattern.chars().collect();
+    let t : Vec<char> = text.chars().collect();
+    match_from(&p, &t)
+}
+
+/// Like `glob_match`, but ignores upper and lower case.
+pub fn glob_match_nocase(pattern: &str, text: &str) -> bool {
+    glob_match(&pattern.to_lowercase(), &text.to_lowercase())
+}
+
+fn match_from(p: &[char], t: &[char]) -> bool {
+    let (mut pi, mut ti) = (0, 0);
+    // Position after the last `*` and the text position it was tried at,
+    // for backtracking:
+    let mut star : Option<(usize, usize)> = None;
+
+    while ti < t.len() {
+        if pi < p.len() {
+            match p[pi] {
+                '*' => {
+                    star = Some((pi + 1, ti));
+                    pi += 1;
+                    continue;
+                },
+                '?' => {
+                    pi += 1;
+                    ti += 1;
+                    continue;
+                },
+                '[' => {
+                    if let Some((matched, len)) = match_class(&p[pi..], t[ti]) {
+                        if matched {
+                            pi += len;
+                            ti += 1;
+                            continue;
+                        }
+                    } else if t[ti] == '[' {
+                        // Unterminated class, match it literally:
+                        pi += 1;
+                        ti += 1;
+                        continue;
+                    }
+                },
+                c => {
+                    if c == t[ti] {
+                        pi += 1;
+                        ti += 1;
+                        continue;
+                    }
+                },
+            }
+        }
+
+        match star {
+            Some((spi, sti)) => {
+                pi = spi;
+                ti = sti + 1;
+                star = Some((spi, sti + 1));
+            },
+            None => return false,
+        }
+    }
+
+    p[pi..].iter().all(|c| *c == '*')
+}
+
+/// Matches `c` against the character class at the start of `p`.
+/// Returns whether it matched and the length of the class pattern, or
+/// `None` if the class isn't terminated.
+fn match_class(p: &[char], c: char) -> Option<(bool, usize)> {
+    let mut i = 1;
+    let negate = p.get(i) == Some(&'!') || p.get(i) == Some(&'^');
+    if negate { i += 1; }
+
+    let mut matched = false;
+    let mut first = true;
+    while i < p.len() {
+        if p[i] == ']' && !first {
+            return Some((matched != negate, i + 1));
+        }
+        first = false;
+
+        if i + 2 < p.len() && p[i + 1] == '-' && p[i + 2] != ']' {
+            if p[i] <= c && c <= p[i + 2] {
+                matched = true;
+            }
+            i += 3;
+        } else {
+            if p[i] == c {
+                matched = true;
+            }
+            i += 1;
+        }
+    }
+
+    None
+}
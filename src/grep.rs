@@ -0,0 +1,355 @@
+use crate::fm_page::*;
+use crate::cursor::PageCursor;
+use crate::ignore::IgnoreStack;
+use crate::text_view::{TextFile, is_binary_file};
+use regex::{Regex, RegexBuilder};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+
+/// Matching lines are sent to the page in batches of this size.
+const HIT_BATCH : usize = 64;
+/// Longer lines are cut off in the page.
+const MAX_LINE_CHARS : usize = 400;
+
+/// Builds the regex for a grep pattern. Patterns that are no valid
+/// regex are searched literally. Like the viewer search, the case is
+/// ignored unless the pattern contains upper case letters.
+pub fn grep_regex(pattern: &str) -> Result<Regex, String> {
+    let ignore_case = !pattern.chars().any(|c| c.is_uppercase());
+    RegexBuilder::new(pattern)
+        .case_insensitive(ignore_case)
+        .build()
+        .or_else(|_| {
+            RegexBuilder::new(&regex::escape(pattern))
+                .case_insensitive(ignore_case)
+                .build()
+        })
+        .map_err(|e| e.to_string())
+}
+
+/// One matching line.
+#[derive(Debug, Clone)]
+pub struct GrepHit {
+    pub path:   std::path::PathBuf,
+    /// Line number, starting at 1.
+    pub line:   usize,
+    pub text:   String,
+    /// Character ranges of the matches in `text`.
+    pub ranges: Vec<(usize, usize)>,
+}
+
+/// Converts the byte ranges of the matches to character ranges, and
+/// cuts the line to `MAX_LINE_CHARS`.
+fn make_hit(path: &std::path::Path, line: usize, text: &str, re: &Regex) -> GrepHit {
+    let mut ranges = vec![];
+    for m in re.find_iter(text) {
+        let start = text[0..m.start()].chars().count();
+        let len   = text[m.start()..m.end()].chars().count();
+        if start >= MAX_LINE_CHARS {
+            break;
+        }
+        ranges.push((start, (start + len).min(MAX_LINE_CHARS)));
+    }
+    GrepHit {
+        path: path.to_path_buf(),
+        line,
+        text: text.chars().take(MAX_LINE_CHARS).collect(),
+        ranges,
+    }
+}
+
+fn grep_file<F>(path: &std::path::Path, re: &Regex, stop: &AtomicBool, emit: &mut F)
+    where F: FnMut(GrepHit) {
+
+    match is_binary_file(path) {
+        Ok(false) => (),
+        _         => return,
+    }
+    let tf = match TextFile::open(path) {
+        Ok(tf) => tf,
+        Err(_) => return,
+    };
+    let mut rd = match tf.reader_at(tf.data_start) {
+        Ok(rd) => rd,
+        Err(_) => return,
+    };
+
+    let mut buf  = vec![];
+    let mut line = 0;
+    while let Ok(true) = tf.read_line_bytes(&mut rd, &mut buf) {
+        line += 1;
+        if line % 1024 == 0 && stop.load(Ordering::Relaxed) {
+            return;
+        }
+        let text = tf.encoding.decode(&buf);
+        if re.is_match(&text) {
+            emit(make_hit(path, line, &text, re));
+        }
+    }
+}
+
+pub enum GrepMsg {
+    Hits(Vec<GrepHit>),
+    Done,
+}
+
+/// Greps the text files below a directory on a thread, skipping
+/// binary files and everything the `.gitignore` files exclude.
+/// Dropping the job stops it.
+pub struct GrepJob {
+    cancel: Arc<AtomicBool>,
+    rx:     Receiver<GrepMsg>,
+}
+
+impl GrepJob {
+    pub fn start(base: &std::path::Path, re: Regex) -> GrepJob {
+        let cancel = Arc::new(AtomicBool::new(false));
+        let (tx, rx) = channel();
+
+        let base = base.to_path_buf();
+        let stop = cancel.clone();
+        std::thread::spawn(move || {
+            let mut dirs  = vec![(base.clone(), IgnoreStack::for_dir(&base))];
+            let mut batch = vec![];
+
+            while let Some((dir, ignore)) = dirs.pop() {
+                // Only directories and regular files, a FIFO, socket or
+                // device could block the read:
+                let mut entries : Vec<(std::path::PathBuf, bool)> =
+                    match std::fs::read_dir(&dir) {
+                        Ok(rd) => rd.filter_map(|e| e.ok()).filter_map(|e| {
+                            let ft = e.file_type().ok()?;
+                            if ft.is_dir() || ft.is_file() {
+                                Some((e.path(), ft.is_dir()))
+                            } else {
+                                None
+                            }
+                        }).collect(),
+                        Err(_) => continue,
+                    };
+                entries.sort();
+
+                for (path, is_dir) in entries.into_iter().rev() {
+                    if stop.load(Ordering::Relaxed) {
+                        return;
+                    }
+                    if ignore.is_ignored(&path, is_dir) {
+                        continue;
+                    }
+                    if is_dir {
+                        let sub = ignore.enter(&path);
+                        dirs.push((path, sub));
+                        continue;
+                    }
+
+                    grep_file(&path, &re, &stop, &mut |hit| batch.push(hit));
+                    if batch.len() >= HIT_BATCH {
+                        let b = std::mem::take(&mut batch);
+                        if tx.send(GrepMsg::Hits(b)).is_err() {
+                            return;
+                        }
+                    }
+                }
+
+                // Files of one directory at a time, so results show up
+                // early in big trees:
+                if !batch.is_empty() {
+                    let b = std::mem::take(&mut batch);
+                    if tx.send(GrepMsg::Hits(b)).is_err() {
+                        return;
+                    }
+                }
+            }
+            let _ = tx.send(GrepMsg::Done);
+        });
+
+        GrepJob { cancel, rx }
+    }
+
+    pub fn poll(&self) -> Vec<GrepMsg> {
+        let mut msgs = vec![];
+        loop {
+            match self.rx.try_recv() {
+                Ok(msg) => msgs.push(msg),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    msgs.push(GrepMsg::Done);
+                    break;
+                },
+            }
+        }
+        msgs
+    }
+}
+
+impl Drop for GrepJob {
+    fn drop(&mut self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Lists the lines matching a pattern in the files below a directory.
+/// Access opens the viewer at the line.
+pub struct GrepSheet {
+    pub base:            std::path::PathBuf,
+    pub pattern:         String,
+    pub hits:            Vec<GrepHit>,
+    pub job:             Option<GrepJob>,
+    pub hits_dirty:      bool,
+    pub render_feedback: RenderFeedback,
+    pub cursor:          PageCursor,
+    pub rendered:        TableRef,
+}
+
+impl GrepSheet {
+    pub fn new(base: &std::path::Path, pattern: &str, re: Regex) -> Self {
+        GrepSheet {
+            base:            base.to_path_buf(),
+            pattern:         pattern.to_string(),
+            hits:            vec![],
+            job:             Some(GrepJob::start(base, re)),
+            hits_dirty:      true,
+            render_feedback: RenderFeedback::new(),
+            cursor:          PageCursor::new(),
+            rendered:        Table::new_ref(),
+        }
+    }
+
+    fn file_count(&self) -> usize {
+        let mut count = 0;
+        let mut last : Option<&std::path::Path> = None;
+        for hit in self.hits.iter() {
+            if last != Some(hit.path.as_path()) {
+                count += 1;
+                last = Some(&hit.path);
+            }
+        }
+        count
+    }
+}
+
+impl FmPage for GrepSheet {
+    fn len(&self) -> usize { self.hits.len() }
+    fn get_scroll_offs(&self) -> usize { self.cursor.scroll_offset }
+    fn is_cursor_idx(&self, idx: usize) -> bool { self.cursor.is_cursor_idx(idx) }
+    fn is_selected(&self, _idx: usize) -> bool { false }
+    fn is_highlighted(&self, _idx: usize) -> bool { false }
+    fn needs_repage(&self) -> bool { self.hits_dirty }
+    fn needs_redraw(&self) -> bool { true }
+    fn sort_by_column(&mut self, _col_idx: usize) { }
+
+    fn set_render_feedback(&mut self, fb: RenderFeedback) {
+        self.render_feedback = fb;
+    }
+
+    fn is_inside_screen_rect(&self, x: i32, y: i32) -> bool {
+        self.render_feedback.is_inside_screen_rect(x, y)
+    }
+
+    fn cursor_path(&self) -> Option<std::path::PathBuf> {
+        self.hits.get(self.cursor.cursor_idx).map(|h| h.path.clone())
+    }
+
+    /// The files with matches, to work on them as a batch.
+    fn selected_paths(&self) -> Vec<std::path::PathBuf> {
+        let mut paths : Vec<std::path::PathBuf> = vec![];
+        for hit in self.hits.iter() {
+            if paths.last() != Some(&hit.path) {
+                paths.push(hit.path.clone());
+            }
+        }
+        paths
+    }
+
+    fn cursor_line(&self) -> Option<usize> {
+        self.hits.get(self.cursor.cursor_idx).map(|h| h.line)
+    }
+
+    fn is_busy(&self) -> bool { self.job.is_some() }
+
+    fn update(&mut self) -> Option<PageEvent> {
+        let msgs = self.job.as_ref()?.poll();
+        if msgs.is_empty() {
+            return None;
+        }
+
+        for msg in msgs {
+            match msg {
+                GrepMsg::Hits(mut hits) => self.hits.append(&mut hits),
+                GrepMsg::Done           => self.job = None,
+            }
+        }
+        self.hits_dirty = true;
+        Some(PageEvent::Redraw)
+    }
+
+    fn do_control(&mut self, ctrl: PageControl) -> Option<PageEvent> {
+        match ctrl {
+            PageControl::Back => Some(PageEvent::Close),
+            PageControl::Access => {
+                let hit = self.hits.get(self.cursor.cursor_idx)?;
+                Some(PageEvent::ViewFile(hit.path.clone(), hit.line))
+            },
+            _ => {
+                self.cursor.do_control(self.hits.len(), &self.render_feedback, ctrl);
+                None
+            },
+        }
+    }
+
+    fn as_drawable_table(&mut self) -> TableRef {
+        if !self.hits_dirty {
+            return self.rendered.clone();
+        }
+        self.hits_dirty = false;
+
+        let mut status = format!("{} lines in {} files", self.hits.len(), self.file_count());
+        if self.job.is_some() {
+            status += ", searching...";
+        }
+
+        let base = self.base.clone();
+        self.rendered =
+            std::rc::Rc::new(std::cell::RefCell::new(Table {
+                title: format!("grep '{}' in {} [{}]",
+                    self.pattern, self.base.to_string_lossy(), status),
+                row_gap: 2,
+                col_gap: 4,
+                columns: vec![
+                    Column {
+                        head: String::from("file"),
+                        size: ColumnSizing::ExpandFract(1),
+                        calc_size: None,
+                        rows: self.hits.iter().map(|h| StyleString {
+                            text: h.path.strip_prefix(&base).unwrap_or(&h.path)
+                                    .to_string_lossy().to_string(),
+                            style: Style::File,
+                            highlight: vec![],
+                        }).collect(),
+                    },
+                    Column {
+                        head: String::from("line"),
+                        size: ColumnSizing::TextWidth(String::from("MMMMMM")),
+                        calc_size: None,
+                        rows: self.hits.iter().map(|h| StyleString {
+                            text: h.line.to_string(),
+                            style: Style::Default,
+                            highlight: vec![],
+                        }).collect(),
+                    },
+                    Column {
+                        head: String::from("text"),
+                        size: ColumnSizing::ExpandFract(2),
+                        calc_size: None,
+                        rows: self.hits.iter().map(|h| StyleString {
+                            text: h.text.clone(),
+                            style: Style::Default,
+                            highlight: h.ranges.clone(),
+                        }).collect(),
+                    },
+                ],
+            }));
+        self.rendered.clone()
+    }
+}
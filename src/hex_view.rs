@@ -0,0 +1,367 @@
+use crate::fm_page::*;
+use std::io::{Read, Seek, SeekFrom};
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+
+/// Row widths `PageControl::CycleMode` switches through.
+const BYTES_PER_ROW : [usize; 3] = [16, 8, 32];
+/// Rows built for the first page, before the render feedback is known.
+const DEFAULT_ROWS  : usize = 50;
+/// Size of the chunks the pattern search reads.
+const SEARCH_CHUNK  : usize = 256 * 1024;
+
+/// Parses a search pattern. A pattern starting with `#` is a sequence of
+/// hex bytes, like `#de ad be ef` or `#deadbeef`. Everything else is
+/// searched for as UTF-8 text.
+pub fn parse_byte_pattern(pat: &str) -> Option<Vec<u8>> {
+    if !pat.starts_with('#') {
+        if pat.is_empty() { return None; }
+        return Some(pat.as_bytes().to_vec());
+    }
+
+    let digits : Vec<char> =
+        pat[1..].chars().filter(|c| !c.is_whitespace()).collect();
+    if digits.is_empty() || digits.len() % 2 != 0 {
+        return None;
+    }
+
+    digits.chunks(2).map(|d| {
+        let s : String = d.iter().collect();
+        u8::from_str_radix(&s, 16).ok()
+    }).collect()
+}
+
+/// Parses an offset given as decimal number or with `0x` prefix as hex.
+pub fn parse_offset(s: &str) -> Option<u64> {
+    let s = s.trim();
+    if s.starts_with("0x") || s.starts_with("0X") {
+        u64::from_str_radix(&s[2..], 16).ok()
+    } else {
+        s.parse::<u64>().ok()
+    }
+}
+
+fn read_at(path: &std::path::Path, offs: u64, len: usize) -> std::io::Result<Vec<u8>> {
+    let mut f = std::fs::File::open(path)?;
+    f.seek(SeekFrom::Start(offs))?;
+    let mut buf = Vec::with_capacity(len);
+    f.take(len as u64).read_to_end(&mut buf)?;
+    Ok(buf)
+}
+
+/// Returns the offset of the first occurence of `pattern` at or after
+/// `start`. Chunks overlap by the pattern length so matches crossing a
+/// chunk border are found.
+fn search_bytes(path: &std::path::Path, start: u64, pattern: &[u8]) -> Option<u64> {
+    let mut f = std::fs::File::open(path).ok()?;
+    f.seek(SeekFrom::Start(start)).ok()?;
+
+    let mut buf_offs = start;
+    let mut buf : Vec<u8> = vec![];
+    let mut chunk = vec![0; SEARCH_CHUNK];
+    loop {
+        let n = f.read(&mut chunk).ok()?;
+        if n == 0 { return None; }
+        buf.extend_from_slice(&chunk[0..n]);
+
+        if let Some(pos) = buf.windows(pattern.len()).position(|w| w == pattern) {
+            return Some(buf_offs + pos as u64);
+        }
+
+        let keep = (pattern.len() - 1).min(buf.len());
+        let drop = buf.len() - keep;
+        buf.drain(0..drop);
+        buf_offs += drop as u64;
+    }
+}
+
+pub struct HexViewSheet {
+    pub path:            std::path::PathBuf,
+    pub file_len:        u64,
+    pub row_width_idx:   usize,
+    pub top_row:         u64,
+    pub cursor_row:      u64,
+    pub pattern:         Option<Vec<u8>>,
+    pub match_offs:      Option<u64>,
+    pub status:          String,
+    searching:           Option<Receiver<Option<u64>>>,
+    pub render_feedback: RenderFeedback,
+    pub rendered:        TableRef,
+}
+
+impl HexViewSheet {
+    pub fn open(path: &std::path::Path) -> std::io::Result<HexViewSheet> {
+        let md = std::fs::metadata(path)?;
+        Ok(HexViewSheet {
+            path:            path.to_path_buf(),
+            file_len:        md.len(),
+            row_width_idx:   0,
+            top_row:         0,
+            cursor_row:      0,
+            pattern:         None,
+            match_offs:      None,
+            status:          String::from(""),
+            searching:       None,
+            render_feedback: RenderFeedback::new(),
+            rendered:        Table::new_ref(),
+        })
+    }
+
+    fn bytes_per_row(&self) -> usize { BYTES_PER_ROW[self.row_width_idx] }
+
+    fn row_count(&self) -> u64 {
+        let bpr = self.bytes_per_row() as u64;
+        (self.file_len + bpr - 1) / bpr
+    }
+
+    fn visible_rows(&self) -> usize {
+        if self.render_feedback.recent_line_count == 0 {
+            DEFAULT_ROWS
+        } else {
+            self.render_feedback.recent_line_count
+        }
+    }
+
+    fn cursor_offset(&self) -> u64 {
+        self.cursor_row * self.bytes_per_row() as u64
+    }
+
+    fn goto_offset(&mut self, offs: u64) {
+        let offs = offs.min(self.file_len.saturating_sub(1));
+        self.cursor_row = offs / self.bytes_per_row() as u64;
+        let half = self.visible_rows() as u64 / 2;
+        self.top_row = self.cursor_row.saturating_sub(half);
+    }
+
+    fn ensure_cursor_visible(&mut self) {
+        let rows = self.row_count();
+        if rows > 0 && self.cursor_row >= rows {
+            self.cursor_row = rows - 1;
+        }
+
+        let visible = self.visible_rows() as u64;
+        if self.cursor_row < self.top_row {
+            self.top_row = self.cursor_row;
+        } else if self.cursor_row >= self.top_row + visible {
+            self.top_row = self.cursor_row + 1 - visible;
+        }
+    }
+
+    fn start_search(&mut self, start: u64) {
+        let pattern = match &self.pattern {
+            Some(p) => p.clone(),
+            None    => return,
+        };
+
+        let (tx, rx) = channel();
+        let path = self.path.clone();
+        std::thread::spawn(move || {
+            let _ = tx.send(search_bytes(&path, start, &pattern));
+        });
+        self.status = String::from("searching...");
+        self.searching = Some(rx);
+    }
+
+    /// Character ranges of the current match inside the row starting at
+    /// `row_offs`, for the hex column (3 chars per byte) and the ASCII
+    /// column (1 char per byte).
+    fn match_ranges(&self, row_offs: u64, row_len: usize, chars_per_byte: usize)
+        -> Vec<(usize, usize)> {
+
+        let (m_start, m_len) = match (&self.match_offs, &self.pattern) {
+            (Some(offs), Some(pat)) => (*offs, pat.len() as u64),
+            _ => return vec![],
+        };
+        let m_end   = m_start + m_len;
+        let row_end = row_offs + row_len as u64;
+        if m_end <= row_offs || m_start >= row_end {
+            return vec![];
+        }
+
+        let s = (m_start.max(row_offs) - row_offs) as usize;
+        let e = (m_end.min(row_end) - row_offs) as usize;
+        // Leave out the space after the last hex byte:
+        let pad = if chars_per_byte > 1 { 1 } else { 0 };
+        vec![(s * chars_per_byte, e * chars_per_byte - pad)]
+    }
+}
+
+impl FmPage for HexViewSheet {
+    fn len(&self) -> usize { self.row_count() as usize }
+    fn get_scroll_offs(&self) -> usize { 0 }
+    fn is_cursor_idx(&self, idx: usize) -> bool {
+        self.top_row + idx as u64 == self.cursor_row
+    }
+    fn is_selected(&self, _idx: usize) -> bool { false }
+    fn is_highlighted(&self, _idx: usize) -> bool { false }
+    fn needs_repage(&self) -> bool { true }
+    fn needs_redraw(&self) -> bool { true }
+    fn sort_by_column(&mut self, _col_idx: usize) { }
+
+    fn set_render_feedback(&mut self, fb: RenderFeedback) {
+        self.render_feedback = fb;
+    }
+
+    fn is_inside_screen_rect(&self, x: i32, y: i32) -> bool {
+        self.render_feedback.is_inside_screen_rect(x, y)
+    }
+
+    fn is_busy(&self) -> bool { self.searching.is_some() }
+
+    fn update(&mut self) -> Option<PageEvent> {
+        let found =
+            match &self.searching {
+                Some(rx) => match rx.try_recv() {
+                    Ok(found)                       => found,
+                    Err(TryRecvError::Empty)        => return None,
+                    Err(TryRecvError::Disconnected) => None,
+                },
+                None => return None,
+            };
+
+        self.searching = None;
+        self.match_offs = found;
+        if let Some(offs) = found {
+            self.status = format!("found at 0x{:x}", offs);
+            self.goto_offset(offs);
+        } else {
+            self.status = String::from("pattern not found");
+        }
+        Some(PageEvent::Redraw)
+    }
+
+    fn do_control(&mut self, ctrl: PageControl) -> Option<PageEvent> {
+        match ctrl {
+            PageControl::Back => { return Some(PageEvent::Close); },
+            PageControl::CursorDown => {
+                self.cursor_row += 1;
+                self.ensure_cursor_visible();
+            },
+            PageControl::CursorUp => {
+                if self.cursor_row > 0 {
+                    self.cursor_row -= 1;
+                }
+                self.ensure_cursor_visible();
+            },
+            PageControl::Scroll(amount) => {
+                let amount = amount as i64 * 5;
+                if amount < 0 {
+                    self.top_row = self.top_row.saturating_sub((-amount) as u64);
+                } else {
+                    self.top_row += amount as u64;
+                }
+                if self.top_row >= self.row_count() {
+                    self.top_row = self.row_count().saturating_sub(1);
+                }
+            },
+            PageControl::Click((x, y)) => {
+                let fb = &self.render_feedback;
+                if x < fb.start_rows.0 || x > fb.end_rows.0
+                   || y < fb.start_rows.1 || y > fb.end_rows.1
+                   || fb.row_height <= 0 {
+                    return None;
+                }
+                self.cursor_row =
+                    self.top_row + ((y - fb.start_rows.1) / fb.row_height) as u64;
+                self.ensure_cursor_visible();
+            },
+            PageControl::Goto(offs) => {
+                match parse_offset(&offs) {
+                    Some(offs) => { self.status = String::from(""); self.goto_offset(offs); },
+                    None       => { self.status = format!("bad offset '{}'", offs); },
+                }
+            },
+            PageControl::Search(pat) => {
+                self.match_offs = None;
+                self.pattern = parse_byte_pattern(&pat);
+                if self.pattern.is_none() {
+                    self.status = format!("bad pattern '{}'", pat);
+                }
+                let start = self.cursor_offset();
+                self.start_search(start);
+            },
+            PageControl::SearchNext => {
+                let start =
+                    match self.match_offs {
+                        Some(offs) => offs + 1,
+                        None       => self.cursor_offset(),
+                    };
+                self.start_search(start);
+            },
+            PageControl::CycleMode => {
+                let offs = self.cursor_offset();
+                self.row_width_idx = (self.row_width_idx + 1) % BYTES_PER_ROW.len();
+                self.cursor_row = offs / self.bytes_per_row() as u64;
+                self.ensure_cursor_visible();
+            },
+            _ => (),
+        }
+        None
+    }
+
+    fn as_drawable_table(&mut self) -> TableRef {
+        let bpr   = self.bytes_per_row();
+        let rows  = self.visible_rows();
+        let start = self.top_row * bpr as u64;
+        let data  = read_at(&self.path, start, rows * bpr).unwrap_or_default();
+
+        let mut offs_col  = vec![];
+        let mut hex_col   = vec![];
+        let mut ascii_col = vec![];
+
+        for (i, chunk) in data.chunks(bpr).enumerate() {
+            let row_offs = start + (i * bpr) as u64;
+
+            offs_col.push(StyleString {
+                text: format!("{:08x}", row_offs),
+                style: Style::Special,
+                highlight: vec![],
+            });
+            hex_col.push(StyleString {
+                text: chunk.iter().map(|b| format!("{:02x} ", b)).collect(),
+                style: Style::Default,
+                highlight: self.match_ranges(row_offs, chunk.len(), 3),
+            });
+            ascii_col.push(StyleString {
+                text: chunk.iter().map(|b|
+                    if *b >= 0x20 && *b < 0x7f { *b as char } else { '.' }).collect(),
+                style: Style::Default,
+                highlight: self.match_ranges(row_offs, chunk.len(), 1),
+            });
+        }
+
+        let mut title = format!("{} [{} bytes, {}/row]",
+            self.path.to_string_lossy(), self.file_len, bpr);
+        if !self.status.is_empty() {
+            title += " ";
+            title += &self.status;
+        }
+
+        self.rendered =
+            std::rc::Rc::new(std::cell::RefCell::new(Table {
+                title,
+                row_gap: 2,
+                col_gap: 4,
+                columns: vec![
+                    Column {
+                        head: String::from("offset"),
+                        size: ColumnSizing::TextWidth(String::from("MMMMMMMM")),
+                        calc_size: None,
+                        rows: offs_col,
+                    },
+                    Column {
+                        head: String::from("hex"),
+                        size: ColumnSizing::TextWidth("MMM".repeat(bpr)),
+                        calc_size: None,
+                        rows: hex_col,
+                    },
+                    Column {
+                        head: String::from("ascii"),
+                        size: ColumnSizing::ExpandFract(1),
+                        calc_size: None,
+                        rows: ascii_col,
+                    },
+                ],
+            }));
+        self.rendered.clone()
+    }
+}
@@ -0,0 +1,172 @@
+use crate::fm_page::*;
+use crate::cursor::PageCursor;
+
+/// The most directories a tab remembers.
+const MAX_HISTORY : usize = 100;
+
+/// A visited directory with the place in its listing when it was left.
+#[derive(Debug, Clone)]
+pub struct HistoryEntry {
+    pub dir:           std::path::PathBuf,
+    pub cursor:        Option<std::path::PathBuf>,
+    pub scroll_offset: usize,
+}
+
+/// The back and forward history of a tab, like in a web browser.
+#[derive(Debug, Clone, Default)]
+pub struct NavHistory {
+    pub entries: Vec<HistoryEntry>,
+    /// The index of the current directory in `entries`.
+    pub pos:     usize,
+}
+
+impl NavHistory {
+    /// Remembers the place in the current directory before it is left.
+    pub fn leave(&mut self, cursor: Option<std::path::PathBuf>, scroll_offset: usize) {
+        if let Some(entry) = self.entries.get_mut(self.pos) {
+            entry.cursor        = cursor;
+            entry.scroll_offset = scroll_offset;
+        }
+    }
+
+    /// Records a newly visited directory. The forward history is
+    /// dropped, like in a browser.
+    pub fn visit(&mut self, dir: &std::path::Path) {
+        if self.entries.get(self.pos).map(|e| e.dir == dir).unwrap_or(false) {
+            return;
+        }
+        self.entries.truncate(self.pos + 1);
+        self.entries.push(HistoryEntry {
+            dir:           dir.to_path_buf(),
+            cursor:        None,
+            scroll_offset: 0,
+        });
+        if self.entries.len() > MAX_HISTORY {
+            self.entries.remove(0);
+        }
+        self.pos = self.entries.len() - 1;
+    }
+
+    /// The index `offs` steps back (negative) or forward from the
+    /// current directory, if there is one.
+    pub fn step(&self, offs: isize) -> Option<usize> {
+        let idx = self.pos as isize + offs;
+        if idx < 0 || idx as usize >= self.entries.len() {
+            return None;
+        }
+        Some(idx as usize)
+    }
+}
+
+/// Lists the visited directories of a tab, Access jumps to one.
+pub struct HistorySheet {
+    pub history:         NavHistory,
+    pub render_feedback: RenderFeedback,
+    pub cursor:          PageCursor,
+    pub rendered:        TableRef,
+}
+
+impl HistorySheet {
+    pub fn new(history: NavHistory) -> HistorySheet {
+        let mut cursor = PageCursor::new();
+        cursor.cursor_idx = history.pos;
+        HistorySheet {
+            history,
+            render_feedback: RenderFeedback::new(),
+            cursor,
+            rendered:        Table::new_ref(),
+        }
+    }
+}
+
+impl FmPage for HistorySheet {
+    fn len(&self) -> usize { self.history.entries.len() }
+    fn get_scroll_offs(&self) -> usize { self.cursor.scroll_offset }
+    fn is_cursor_idx(&self, idx: usize) -> bool { self.cursor.is_cursor_idx(idx) }
+    fn is_selected(&self, _idx: usize) -> bool { false }
+    fn needs_repage(&self) -> bool { true }
+    fn needs_redraw(&self) -> bool { true }
+    fn sort_by_column(&mut self, _col_idx: usize) { }
+
+    fn is_highlighted(&self, idx: usize) -> bool {
+        self.history.entries.get(idx).map(|e| !e.dir.is_dir()).unwrap_or(false)
+    }
+
+    fn set_render_feedback(&mut self, fb: RenderFeedback) {
+        self.render_feedback = fb;
+    }
+
+    fn is_inside_screen_rect(&self, x: i32, y: i32) -> bool {
+        self.render_feedback.is_inside_screen_rect(x, y)
+    }
+
+    fn cursor_path(&self) -> Option<std::path::PathBuf> {
+        self.history.entries.get(self.cursor.cursor_idx).map(|e| e.dir.clone())
+    }
+
+    fn do_control(&mut self, ctrl: PageControl) -> Option<PageEvent> {
+        match ctrl {
+            PageControl::Access => {
+                let idx = self.cursor.cursor_idx;
+                if idx < self.history.entries.len() {
+                    Some(PageEvent::GotoHistory(idx))
+                } else {
+                    None
+                }
+            },
+            PageControl::Back => Some(PageEvent::Close),
+            _ => {
+                self.cursor.do_control(self.len(), &self.render_feedback, ctrl);
+                None
+            },
+        }
+    }
+
+    fn as_drawable_table(&mut self) -> TableRef {
+        let title = format!("history [{}/{}]", self.history.pos + 1, self.history.entries.len());
+        let pos = self.history.pos;
+
+        self.rendered =
+            std::rc::Rc::new(std::cell::RefCell::new(Table {
+                title,
+                row_gap: 2,
+                col_gap: 4,
+                columns: vec![
+                    Column {
+                        head: String::from("#"),
+                        size: ColumnSizing::TextWidth(String::from("MMMMM")),
+                        calc_size: None,
+                        rows: (0..self.history.entries.len()).map(|i| StyleString {
+                            text: if i == pos { format!("> {}", i + 1) } else { (i + 1).to_string() },
+                            style: if i == pos { Style::Special } else { Style::Default },
+                            highlight: vec![],
+                        }).collect(),
+                    },
+                    Column {
+                        head: String::from("directory"),
+                        size: ColumnSizing::ExpandFract(2),
+                        calc_size: None,
+                        rows: self.history.entries.iter().map(|e| StyleString {
+                            text: e.dir.to_string_lossy().to_string(),
+                            style: Style::Dir,
+                            highlight: vec![],
+                        }).collect(),
+                    },
+                    Column {
+                        head: String::from("cursor"),
+                        size: ColumnSizing::ExpandFract(1),
+                        calc_size: None,
+                        rows: self.history.entries.iter().map(|e| StyleString {
+                            text: e.cursor.as_ref()
+                                    .and_then(|c| c.file_name())
+                                    .map(|n| n.to_string_lossy().to_string())
+                                    .unwrap_or_default(),
+                            style: Style::Default,
+                            highlight: vec![],
+                        }).collect(),
+                    },
+                ],
+            }));
+        self.rendered.clone()
+    }
+}
@@ -0,0 +1,211 @@
+use crate::glob::glob_match_dotfiles;
+
+/// One line of a `.gitignore` file.
+#[derive(Debug, Clone)]
+struct IgnoreRule {
+    pattern:  String,
+    /// `!pattern` re-includes what an earlier rule excluded.
+    negate:   bool,
+    /// `pattern/` only matches directories.
+    dir_only: bool,
+    /// Patterns with a `/` are matched against the path relative to the
+    /// `.gitignore`, others against the name only.
+    anchored: bool,
+}
+
+/// The rules of one `.gitignore` file.
+#[derive(Debug, Clone)]
+pub struct GitIgnore {
+    pub base: std::path::PathBuf,
+    rules:    Vec<IgnoreRule>,
+}
+
+impl GitIgnore {
+    pub fn parse(base: &std::path::Path, content: &str) -> GitIgnore {
+        let mut rules = vec![];
+        for line in content.lines() {
+            let line = line.trim_end();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (negate, line) =
+                match line.strip_prefix('!') { Some(l) => (true, l), None => (false, line) };
+            let line = line.strip_prefix('\\').unwrap_or(line);
+            let (dir_only, line) =
+                match line.strip_suffix('/') { Some(l) => (true, l), None => (false, line) };
+            let anchored = line.contains('/');
+            let line     = line.trim_start_matches('/');
+            if line.is_empty() {
+                continue;
+            }
+
+            rules.push(IgnoreRule {
+                pattern: line.to_string(),
+                negate,
+                dir_only,
+                anchored,
+            });
+        }
+        GitIgnore { base: base.to_path_buf(), rules }
+    }
+
+    /// Reads the `.gitignore` of a directory, if it has one.
+    pub fn load(dir: &std::path::Path) -> Option<GitIgnore> {
+        let content = std::fs::read_to_string(dir.join(".gitignore")).ok()?;
+        Some(Self::parse(dir, &content))
+    }
+
+    /// Returns `Some(true)` if the path is ignored, `Some(false)` if a
+    /// negated rule includes it again and `None` if no rule matches.
+    /// The last matching rule decides.
+    pub fn matched(&self, path: &std::path::Path, is_dir: bool) -> Option<bool> {
+        let rel = path.strip_prefix(&self.base).ok()?;
+        let rel = rel.to_string_lossy().replace('\\', "/");
+        let name =
+            path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+
+        for rule in self.rules.iter().rev() {
+            if rule.dir_only && !is_dir {
+                continue;
+            }
+            let hit =
+                if rule.anchored {
+                    let pattern : Vec<&str> = rule.pattern.split('/').collect();
+                    let parts   : Vec<&str> = rel.split('/').collect();
+                    match_components(&pattern, &parts)
+                } else {
+                    glob_match_dotfiles(&rule.pattern, &name)
+                };
+            if hit {
+                return Some(!rule.negate);
+            }
+        }
+        None
+    }
+}
+
+/// Matches the components of an anchored pattern against the components
+/// of a path. `**` matches zero or more whole components, a trailing
+/// `**` at least one. The other components are globs, so `*` never
+/// matches a `/`.
+fn match_components(pattern: &[&str], parts: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => parts.is_empty(),
+        Some((&"**", [])) => !parts.is_empty(),
+        Some((&"**", rest)) => (0..=parts.len()).any(|i| match_components(rest, &parts[i..])),
+        Some((glob, rest)) => {
+            match parts.split_first() {
+                Some((part, parts)) =>
+                    glob_match_dotfiles(glob, part) && match_components(rest, parts),
+                None => false,
+            }
+        },
+    }
+}
+
+/// The `.gitignore` files from the top of a directory walk down to the
+/// current directory. Deeper files take precedence.
+#[derive(Debug, Clone, Default)]
+pub struct IgnoreStack {
+    stack: Vec<GitIgnore>,
+}
+
+impl IgnoreStack {
+    /// Collects the `.gitignore` files of `dir` and its parents, up to
+    /// the root of the git repository it is in.
+    pub fn for_dir(dir: &std::path::Path) -> Self {
+        let mut stack = vec![];
+        let mut cur = Some(dir);
+        while let Some(d) = cur {
+            if let Some(gi) = GitIgnore::load(d) {
+                stack.push(gi);
+            }
+            if d.join(".git").exists() {
+                break;
+            }
+            cur = d.parent();
+        }
+        stack.reverse();
+        IgnoreStack { stack }
+    }
+
+    /// Returns the stack for a subdirectory, with its `.gitignore`
+    /// added.
+    pub fn enter(&self, dir: &std::path::Path) -> Self {
+        let mut stack = self.stack.clone();
+        if let Some(gi) = GitIgnore::load(dir) {
+            stack.push(gi);
+        }
+        IgnoreStack { stack }
+    }
+
+    /// Whether the path is ignored. The `.git` directory always is.
+    pub fn is_ignored(&self, path: &std::path::Path, is_dir: bool) -> bool {
+        if is_dir && path.file_name().map(|n| n == ".git").unwrap_or(false) {
+            return true;
+        }
+        for gi in self.stack.iter().rev() {
+            if let Some(ignored) = gi.matched(path, is_dir) {
+                return ignored;
+            }
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parts(path: &str) -> Vec<&str> { path.split('/').collect() }
+
+    #[test]
+    fn double_star_matches_whole_components() {
+        let pattern = parts("a/**/b");
+        assert!(match_components(&pattern, &parts("a/b")));
+        assert!(match_components(&pattern, &parts("a/x/b")));
+        assert!(match_components(&pattern, &parts("a/x/y/b")));
+        assert!(!match_components(&pattern, &parts("a/xb")));
+        assert!(!match_components(&pattern, &parts("ax/b")));
+
+        let pattern = parts("**/foo");
+        assert!(match_components(&pattern, &parts("foo")));
+        assert!(match_components(&pattern, &parts("x/y/foo")));
+
+        let pattern = parts("logs/**");
+        assert!(match_components(&pattern, &parts("logs/a")));
+        assert!(match_components(&pattern, &parts("logs/a/b")));
+        assert!(!match_components(&pattern, &parts("logs")));
+    }
+
+    #[test]
+    fn star_stays_in_one_component() {
+        let pattern = parts("doc/*.txt");
+        assert!(match_components(&pattern, &parts("doc/a.txt")));
+        assert!(!match_components(&pattern, &parts("doc/sub/a.txt")));
+    }
+
+    #[test]
+    fn rules() {
+        let base = std::path::Path::new("/r");
+        let gi = GitIgnore::parse(base, "target/\n*.log\n!keep.log\n/src/gen\nsrc/*.o\n");
+
+        // dir/ only matches directories:
+        assert_eq!(gi.matched(&base.join("target"), true), Some(true));
+        assert_eq!(gi.matched(&base.join("target"), false), None);
+
+        // !neg includes a file again, the last matching rule decides:
+        assert_eq!(gi.matched(&base.join("x.log"), false), Some(true));
+        assert_eq!(gi.matched(&base.join("keep.log"), false), Some(false));
+
+        // Name-only patterns match at any depth:
+        assert_eq!(gi.matched(&base.join("a/b/x.log"), false), Some(true));
+
+        // Anchored patterns match relative to the .gitignore:
+        assert_eq!(gi.matched(&base.join("src/gen"), true), Some(true));
+        assert_eq!(gi.matched(&base.join("a/src/gen"), true), None);
+        assert_eq!(gi.matched(&base.join("src/x.o"), false), Some(true));
+        assert_eq!(gi.matched(&base.join("src/a/x.o"), false), None);
+    }
+}
@@ -0,0 +1,223 @@
+use crate::fm_page::*;
+use sdl2::surface::Surface;
+use sdl2::rwops::RWops;
+
+/// Zoom factors the mouse wheel steps through.
+const ZOOM_STEPS : [f32; 9] = [0.125, 0.25, 0.5, 0.75, 1.0, 1.5, 2.0, 4.0, 8.0];
+
+/// Identifies an image format by its magic bytes. Returns `None` for
+/// formats that can't be decoded by this build. BMP is always supported
+/// by SDL itself, everything else needs the `image` feature.
+pub fn detect_image_format(sample: &[u8]) -> Option<&'static str> {
+    let format =
+        if sample.starts_with(b"BM") {
+            "BMP"
+        } else if sample.starts_with(&[0x89, b'P', b'N', b'G']) {
+            "PNG"
+        } else if sample.starts_with(&[0xFF, 0xD8, 0xFF]) {
+            "JPEG"
+        } else if sample.starts_with(b"GIF8") {
+            "GIF"
+        } else if sample.starts_with(b"II*\0") || sample.starts_with(b"MM\0*") {
+            "TIFF"
+        } else if sample.len() >= 12 && &sample[0..4] == b"RIFF" && &sample[8..12] == b"WEBP" {
+            "WEBP"
+        } else {
+            return None;
+        };
+
+    if format == "BMP" || cfg!(feature = "image") {
+        Some(format)
+    } else {
+        None
+    }
+}
+
+pub fn is_image_file(path: &std::path::Path) -> bool {
+    use std::io::Read;
+    let mut sample = [0u8; 16];
+    let len =
+        match std::fs::File::open(path).and_then(|mut f| f.read(&mut sample)) {
+            Ok(len) => len,
+            Err(_)  => return false,
+        };
+    detect_image_format(&sample[0..len]).is_some()
+}
+
+#[cfg(feature = "image")]
+fn decode_image(bytes: &[u8]) -> Result<Surface<'static>, String> {
+    use sdl2::image::ImageRWops;
+    let rw = RWops::from_bytes(bytes)?;
+    let sf = rw.load()?;
+    // SDL2_image decodes into pixels of its own, but the binding ties the
+    // lifetime of the surface to the RWops. A converted copy is 'static:
+    sf.convert(&sf.pixel_format())
+}
+
+#[cfg(not(feature = "image"))]
+fn decode_image(bytes: &[u8]) -> Result<Surface<'static>, String> {
+    let mut rw = RWops::from_bytes(bytes)?;
+    Surface::load_bmp_rw(&mut rw)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ZoomMode {
+    Fit,
+    Original,
+    Zoom(usize),
+}
+
+pub struct ImageViewSheet {
+    pub path:            std::path::PathBuf,
+    pub format:          &'static str,
+    pub surface:         Surface<'static>,
+    pub zoom:            ZoomMode,
+    pub siblings:        Option<Vec<std::path::PathBuf>>,
+    pub render_feedback: RenderFeedback,
+    pub rendered:        TableRef,
+}
+
+impl ImageViewSheet {
+    pub fn open(path: &std::path::Path) -> Result<ImageViewSheet, String> {
+        let bytes = std::fs::read(path).map_err(|e| e.to_string())?;
+        Self::from_bytes(path, &bytes)
+    }
+
+    /// Decodes an image that was already read, for instance by a worker
+    /// thread.
+    pub fn from_bytes(path: &std::path::Path, bytes: &[u8]) -> Result<ImageViewSheet, String> {
+        let format =
+            detect_image_format(bytes)
+            .ok_or_else(|| String::from("unsupported image format"))?;
+        Ok(ImageViewSheet {
+            path:            path.to_path_buf(),
+            format,
+            surface:         decode_image(bytes)?,
+            zoom:            ZoomMode::Fit,
+            siblings:        None,
+            render_feedback: RenderFeedback::new(),
+            rendered:        Table::new_ref(),
+        })
+    }
+
+    /// Switches to the next (or previous) image file in the directory.
+    fn step(&mut self, forward: bool) -> Option<PageEvent> {
+        if self.siblings.is_none() {
+            let dir = self.path.parent()?;
+            let mut images : Vec<std::path::PathBuf> =
+                std::fs::read_dir(dir).ok()?
+                    .filter_map(|e| e.ok().map(|e| e.path()))
+                    .filter(|p| p.is_file() && is_image_file(p))
+                    .collect();
+            images.sort();
+            self.siblings = Some(images);
+        }
+
+        let siblings = self.siblings.as_ref().unwrap();
+        let idx = siblings.iter().position(|p| *p == self.path)?;
+        let next =
+            if forward {
+                siblings.get(idx + 1)?
+            } else {
+                siblings.get(idx.checked_sub(1)?)?
+            };
+
+        let zoom = self.zoom;
+        match Self::open(next) {
+            Ok(mut img) => {
+                img.zoom     = zoom;
+                img.siblings = self.siblings.take();
+                *self = img;
+            },
+            Err(_) => {
+                // Leave the broken image out of the stepping:
+                let next = next.clone();
+                if let Some(s) = self.siblings.as_mut() { s.retain(|p| *p != next); }
+            },
+        }
+        Some(PageEvent::Redraw)
+    }
+}
+
+impl FmPage for ImageViewSheet {
+    fn len(&self) -> usize { 0 }
+    fn get_scroll_offs(&self) -> usize { 0 }
+    fn is_cursor_idx(&self, _idx: usize) -> bool { false }
+    fn is_selected(&self, _idx: usize) -> bool { false }
+    fn is_highlighted(&self, _idx: usize) -> bool { false }
+    fn needs_repage(&self) -> bool { true }
+    fn needs_redraw(&self) -> bool { true }
+    fn sort_by_column(&mut self, _col_idx: usize) { }
+
+    fn set_render_feedback(&mut self, fb: RenderFeedback) {
+        self.render_feedback = fb;
+    }
+
+    fn is_inside_screen_rect(&self, x: i32, y: i32) -> bool {
+        self.render_feedback.is_inside_screen_rect(x, y)
+    }
+
+    fn cursor_path(&self) -> Option<std::path::PathBuf> {
+        Some(self.path.clone())
+    }
+
+    fn image(&self) -> Option<(&Surface<'static>, ImageScale)> {
+        let scale =
+            match self.zoom {
+                ZoomMode::Fit      => ImageScale::Fit,
+                ZoomMode::Original => ImageScale::Factor(1.0),
+                ZoomMode::Zoom(i)  => ImageScale::Factor(ZOOM_STEPS[i]),
+            };
+        Some((&self.surface, scale))
+    }
+
+    fn do_control(&mut self, ctrl: PageControl) -> Option<PageEvent> {
+        match ctrl {
+            PageControl::Back       => Some(PageEvent::Close),
+            PageControl::CursorDown => self.step(true),
+            PageControl::CursorUp   => self.step(false),
+            PageControl::CycleMode  => {
+                self.zoom =
+                    match self.zoom {
+                        ZoomMode::Fit      => ZoomMode::Original,
+                        ZoomMode::Original => ZoomMode::Fit,
+                        ZoomMode::Zoom(_)  => ZoomMode::Fit,
+                    };
+                None
+            },
+            PageControl::Scroll(amount) => {
+                let one = ZOOM_STEPS.iter().position(|z| *z == 1.0).unwrap();
+                let idx =
+                    match self.zoom {
+                        ZoomMode::Zoom(i) => i as i32,
+                        _                 => one as i32,
+                    };
+                // Scrolling up (negative amount) zooms in:
+                let idx = (idx - amount).max(0).min(ZOOM_STEPS.len() as i32 - 1);
+                self.zoom = ZoomMode::Zoom(idx as usize);
+                None
+            },
+            _ => None,
+        }
+    }
+
+    fn as_drawable_table(&mut self) -> TableRef {
+        let (w, h) = self.surface.size();
+        let zoom =
+            match self.zoom {
+                ZoomMode::Fit      => String::from("fit"),
+                ZoomMode::Original => String::from("1:1"),
+                ZoomMode::Zoom(i)  => format!("{}%", (ZOOM_STEPS[i] * 100.0) as u32),
+            };
+
+        self.rendered =
+            std::rc::Rc::new(std::cell::RefCell::new(Table {
+                title: format!("{} [{}x{} {}, {}]",
+                    self.path.to_string_lossy(), w, h, self.format, zoom),
+                row_gap: 2,
+                col_gap: 0,
+                columns: vec![],
+            }));
+        self.rendered.clone()
+    }
+}
@@ -13,7 +13,7 @@ pub struct LogSheet {
     pub rendered:       TableRef,
 }
 
-fn append_msg_rows(rows: &mut Vec<String>, msg: &str, chars_per_row: usize) {
+pub fn append_msg_rows(rows: &mut Vec<String>, msg: &str, chars_per_row: usize) {
     let mut row = String::new();
     let mut nothing_pushed = true;
     for c in msg.chars() {
@@ -87,8 +87,9 @@ impl FmPage for LogSheet {
         self.render_feedback.is_inside_screen_rect(x, y)
     }
 
-    fn do_control(&mut self, ctrl: PageControl) {
+    fn do_control(&mut self, ctrl: PageControl) -> Option<PageEvent> {
         self.cursor.do_control(self.len(), &self.render_feedback, ctrl);
+        None
     }
 
     fn as_drawable_table(&mut self) -> TableRef {
@@ -109,6 +110,7 @@ impl FmPage for LogSheet {
                             StyleString {
                                 text: r.to_string(),
                                 style: Style::Default,
+                                highlight: vec![],
                             }
                         }).collect(),
                     },
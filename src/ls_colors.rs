@@ -0,0 +1,291 @@
+use sdl2::pixels::Color;
+use crate::defs::*;
+use crate::fm_page::FileKind;
+use crate::glob::glob_match_dotfiles;
+use crate::path_sheet::{PathRecord, PathRecordType};
+
+const ARCHIVE_EXTS : [&str; 16] = [
+    "tar", "tgz", "gz", "bz2", "xz", "zst", "lz", "lzma", "z", "zip",
+    "7z", "rar", "jar", "deb", "rpm", "cpio",
+];
+
+const IMAGE_EXTS : [&str; 13] = [
+    "png", "jpg", "jpeg", "gif", "bmp", "tif", "tiff", "webp", "svg",
+    "ico", "tga", "xpm", "pcx",
+];
+
+const MEDIA_EXTS : [&str; 17] = [
+    "mp3", "ogg", "oga", "flac", "wav", "opus", "m4a", "aac", "mid",
+    "mp4", "mkv", "avi", "webm", "mov", "mpg", "mpeg", "ogv",
+];
+
+const SOURCE_EXTS : [&str; 22] = [
+    "rs", "c", "h", "cc", "cpp", "hpp", "py", "sh", "wl", "js", "ts",
+    "java", "go", "rb", "pl", "lua", "hs", "ml", "el", "toml", "json",
+    "md",
+];
+
+/// The kind of a directory entry, by its type, permission bits and
+/// name.
+pub fn file_kind(pr: &PathRecord) -> FileKind {
+    match pr.path_type {
+        PathRecordType::SymLink => {
+            // `exists` follows the link:
+            return if pr.path.exists() { FileKind::Link } else { FileKind::BrokenLink };
+        },
+        PathRecordType::Dir => {
+            return if pr.mode & 0o1000 != 0 { FileKind::StickyDir } else { FileKind::Dir };
+        },
+        PathRecordType::File => (),
+    }
+
+    match pr.mode & 0o170000 {
+        0o140000 => return FileKind::Socket,
+        0o010000 => return FileKind::Fifo,
+        0o060000 | 0o020000 => return FileKind::Device,
+        _ => (),
+    }
+    if pr.mode & 0o6000 != 0 {
+        return FileKind::Setuid;
+    }
+    if pr.mode & 0o111 != 0 {
+        return FileKind::Executable;
+    }
+
+    let name = pr.path.file_name().unwrap_or_default().to_string_lossy();
+    if name.starts_with('.') {
+        return FileKind::Hidden;
+    }
+    let ext =
+        match pr.path.extension() {
+            Some(ext) => ext.to_string_lossy().to_lowercase(),
+            None      => return FileKind::File,
+        };
+    let ext = ext.as_str();
+    if ARCHIVE_EXTS.contains(&ext) {
+        FileKind::Archive
+    } else if IMAGE_EXTS.contains(&ext) {
+        FileKind::Image
+    } else if MEDIA_EXTS.contains(&ext) {
+        FileKind::Media
+    } else if SOURCE_EXTS.contains(&ext) {
+        FileKind::Source
+    } else {
+        FileKind::File
+    }
+}
+
+/// How the text of a table cell is drawn.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct TextAttrs {
+    pub fg:        Option<Color>,
+    pub bold:      bool,
+    pub italic:    bool,
+    pub underline: bool,
+}
+
+impl TextAttrs {
+    fn color(fg: Color) -> TextAttrs {
+        TextAttrs { fg: Some(fg), ..TextAttrs::default() }
+    }
+
+    /// Parses the SGR codes of an `LS_COLORS` entry, like `01;34` or
+    /// `38;5;208`. Background colors and blinking are left out.
+    pub fn parse_sgr(codes: &str) -> TextAttrs {
+        let mut attrs = TextAttrs::default();
+        let codes : Vec<u32> =
+            codes.split(';').map(|c| c.trim().parse::<u32>().unwrap_or(0)).collect();
+
+        let mut i = 0;
+        while i < codes.len() {
+            match codes[i] {
+                0  => attrs = TextAttrs::default(),
+                1  => attrs.bold      = true,
+                3  => attrs.italic    = true,
+                4  => attrs.underline = true,
+                22 => attrs.bold      = false,
+                23 => attrs.italic    = false,
+                24 => attrs.underline = false,
+                39 => attrs.fg        = None,
+                c @ 30..=37 => attrs.fg = Some(xterm_color(c - 30)),
+                c @ 90..=97 => attrs.fg = Some(xterm_color(c - 90 + 8)),
+                38 => {
+                    match codes.get(i + 1) {
+                        Some(5) => {
+                            if let Some(n) = codes.get(i + 2) {
+                                attrs.fg = Some(xterm_color(*n));
+                            }
+                            i += 2;
+                        },
+                        Some(2) => {
+                            if let Some(rgb) = codes.get(i + 2..i + 5) {
+                                attrs.fg = Some(Color::RGB(
+                                    rgb[0].min(255) as u8,
+                                    rgb[1].min(255) as u8,
+                                    rgb[2].min(255) as u8));
+                            }
+                            i += 4;
+                        },
+                        _ => (),
+                    }
+                },
+                // The same for the background, which is skipped:
+                48 => {
+                    match codes.get(i + 1) {
+                        Some(5) => i += 2,
+                        Some(2) => i += 4,
+                        _ => (),
+                    }
+                },
+                _ => (),
+            }
+            i += 1;
+        }
+        attrs
+    }
+}
+
+/// The color of an entry of the xterm 256 color palette. The 16 basic
+/// colors are a bit lighter than in a terminal, so that they stay
+/// readable on the dark background.
+pub fn xterm_color(n: u32) -> Color {
+    const BASIC : [(u8, u8, u8); 16] = [
+        ( 96,  96,  96), (224,  64,  64), ( 64, 208,  64), (208, 208,  64),
+        ( 96, 144, 255), (208,  96, 208), ( 64, 208, 208), (229, 229, 229),
+        (144, 144, 144), (255,  96,  96), ( 96, 255,  96), (255, 255,  96),
+        (128, 176, 255), (255, 128, 255), ( 96, 255, 255), (255, 255, 255),
+    ];
+    match n {
+        0..=15 => {
+            let (r, g, b) = BASIC[n as usize];
+            Color::RGB(r, g, b)
+        },
+        16..=231 => {
+            let level = |v: u32| if v == 0 { 0 } else { (55 + v * 40) as u8 };
+            let n = n - 16;
+            Color::RGB(level(n / 36), level((n / 6) % 6), level(n % 6))
+        },
+        232..=255 => {
+            let v = (8 + (n - 232) * 10) as u8;
+            Color::RGB(v, v, v)
+        },
+        _ => NORM_FG_COLOR,
+    }
+}
+
+/// The colors of the directory listings, from the `LS_COLORS`
+/// environment variable and the globs set from WLambda.
+#[derive(Debug, Clone, Default)]
+pub struct LsColors {
+    /// The file type entries, like `di` for directories.
+    pub kinds:     std::collections::HashMap<String, TextAttrs>,
+    /// The file name globs, like `*.tar`.
+    pub globs:     Vec<(String, TextAttrs)>,
+    /// Globs set from WLambda, they come before everything else.
+    pub overrides: Vec<(String, TextAttrs)>,
+}
+
+impl LsColors {
+    pub fn from_env() -> LsColors {
+        Self::parse(&std::env::var("LS_COLORS").unwrap_or_default())
+    }
+
+    /// Parses the `key=codes` entries of `LS_COLORS`, separated by `:`.
+    pub fn parse(spec: &str) -> LsColors {
+        let mut lc = LsColors::default();
+        for entry in spec.split(':') {
+            let mut parts = entry.splitn(2, '=');
+            let (key, codes) =
+                match (parts.next(), parts.next()) {
+                    (Some(key), Some(codes)) if !key.is_empty() => (key, codes),
+                    _ => continue,
+                };
+            let attrs = TextAttrs::parse_sgr(codes);
+            if key.contains('*') {
+                lc.globs.push((key.to_lowercase(), attrs));
+            } else {
+                lc.kinds.insert(key.to_string(), attrs);
+            }
+        }
+        lc
+    }
+
+    /// Sets the attributes for the names matching the glob, replacing
+    /// an earlier setting of the same glob.
+    pub fn set_override(&mut self, glob: &str, attrs: TextAttrs) {
+        match self.overrides.iter_mut().find(|(g, _)| g == glob) {
+            Some(o) => o.1 = attrs,
+            None    => self.overrides.push((glob.to_string(), attrs)),
+        }
+    }
+
+    /// The attributes of an entry name of the kind, as displayed in a
+    /// listing. The name is matched against the globs.
+    pub fn attrs(&self, kind: FileKind, text: &str) -> TextAttrs {
+        let code =
+            match kind {
+                FileKind::Dir        => "di",
+                FileKind::Link       => "ln",
+                FileKind::BrokenLink => "or",
+                FileKind::Executable => "ex",
+                FileKind::Socket     => "so",
+                FileKind::Fifo       => "pi",
+                FileKind::Device     => "bd",
+                FileKind::Setuid     => "su",
+                FileKind::StickyDir  => "st",
+                FileKind::File | FileKind::Archive | FileKind::Image
+                | FileKind::Media | FileKind::Source | FileKind::Hidden => "fi",
+            };
+
+        let name = text.trim_end_matches(std::path::MAIN_SEPARATOR);
+        let name = name.rsplit(std::path::MAIN_SEPARATOR).next().unwrap_or(name);
+        if let Some((_, attrs)) = self.overrides.iter().find(|(g, _)| glob_match_dotfiles(g, name)) {
+            return *attrs;
+        }
+
+        // Like ls, the globs are only for regular files:
+        if code == "fi" {
+            let lname = name.to_lowercase();
+            if let Some((_, attrs)) = self.globs.iter().find(|(g, _)| glob_match_dotfiles(g, &lname)) {
+                return *attrs;
+            }
+            if kind == FileKind::File {
+                if let Some(attrs) = self.kinds.get("fi") {
+                    return *attrs;
+                }
+            }
+            return default_attrs(kind);
+        }
+
+        let attrs =
+            match code {
+                "or" => self.kinds.get("or").or_else(|| self.kinds.get("ln")),
+                "st" => self.kinds.get("st").or_else(|| self.kinds.get("tw")),
+                "bd" => self.kinds.get("bd").or_else(|| self.kinds.get("cd")),
+                "su" => self.kinds.get("su").or_else(|| self.kinds.get("sg")),
+                _    => self.kinds.get(code),
+            };
+        attrs.copied().unwrap_or_else(|| default_attrs(kind))
+    }
+}
+
+/// The attributes of the entry kinds without `LS_COLORS`.
+pub fn default_attrs(kind: FileKind) -> TextAttrs {
+    match kind {
+        FileKind::File       => TextAttrs::default(),
+        FileKind::Dir        => TextAttrs::color(DIR_FG_COLOR),
+        FileKind::Link       => TextAttrs::color(LNK_FG_COLOR),
+        FileKind::Executable => TextAttrs { bold: true, ..TextAttrs::color(EXE_FG_COLOR) },
+        FileKind::Archive    => TextAttrs::color(ARC_FG_COLOR),
+        FileKind::Image      => TextAttrs::color(IMG_FG_COLOR),
+        FileKind::Media      => TextAttrs::color(MED_FG_COLOR),
+        FileKind::Source     => TextAttrs::color(SRC_FG_COLOR),
+        FileKind::Hidden     => TextAttrs::color(HID_FG_COLOR),
+        FileKind::BrokenLink => TextAttrs { bold: true, ..TextAttrs::color(ORPH_FG_COLOR) },
+        FileKind::Socket
+        | FileKind::Fifo
+        | FileKind::Device   => TextAttrs::color(SPCL_FG_COLOR),
+        FileKind::Setuid     => TextAttrs { bold: true, ..TextAttrs::color(SUID_FG_COLOR) },
+        FileKind::StickyDir  => TextAttrs::color(STKY_FG_COLOR),
+    }
+}
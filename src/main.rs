@@ -16,12 +16,65 @@ mod fm_page;
 mod path_sheet;
 mod text_line;
 mod log_sheet;
+mod text_view;
+mod hex_view;
+mod quick_view;
+mod image_view;
+mod users;
+mod mime;
+mod attr_ops;
+mod properties_sheet;
+mod glob;
+mod assoc;
+mod open_with;
+mod shell;
+mod editor;
+mod find;
+mod file_ops;
+mod ignore;
+mod grep;
+mod tree_sheet;
+mod disk_usage;
+mod dupes;
+mod checksum;
+mod git_status;
+mod drives;
+mod bookmarks;
+mod history;
+mod frecency;
+mod fuzzy;
+mod diff_view;
+mod ls_colors;
 
 use log_sheet::*;
 use path_sheet::*;
 use fm_page::*;
 use defs::*;
 use text_line::*;
+use text_view::*;
+use hex_view::*;
+use quick_view::*;
+use image_view::*;
+use properties_sheet::*;
+use attr_ops::*;
+use assoc::*;
+use open_with::*;
+use shell::*;
+use editor::*;
+use find::*;
+use file_ops::*;
+use grep::*;
+use tree_sheet::*;
+use disk_usage::*;
+use dupes::*;
+use checksum::*;
+use diff_view::*;
+use drives::*;
+use bookmarks::*;
+use history::*;
+use frecency::*;
+use fuzzy::*;
+use ls_colors::*;
 
 use wlambda;
 use wlambda::{VVal, GlobalEnv, EvalContext};
@@ -31,7 +84,7 @@ struct Page {
     cache:      Option<Table>,
 }
 
-fn draw_fm_page(fm_page: &mut dyn FmPage, gp: &mut GUIPainter, x: i32, y: i32, w: u32, h: u32, is_active: bool) {
+fn draw_fm_page(fm_page: &mut dyn FmPage, gp: &mut GUIPainter, colors: &LsColors, x: i32, y: i32, w: u32, h: u32, is_active: bool) {
     gp.canvas.set_draw_color(NORM_BG_COLOR);
     gp.canvas.fill_rect(Rect::new(x, y, w, h))
         .expect("filling rectangle");
@@ -44,7 +97,14 @@ fn draw_fm_page(fm_page: &mut dyn FmPage, gp: &mut GUIPainter, x: i32, y: i32, w
 
     let render_feedback =
         gp.draw_table(
-            fm_page, x + 2, y, w as i32 - 2, h as i32, has_focus, is_active);
+            fm_page, colors, x + 2, y, w as i32 - 2, h as i32, has_focus, is_active);
+
+    if let Some((surface, scale)) = fm_page.image() {
+        let img_y = render_feedback.start_rows.1;
+        gp.draw_image(surface, scale,
+            Rect::new(x + 2, img_y, w - 2, (y + h as i32 - img_y).max(1) as u32));
+    }
+
     fm_page.set_render_feedback(render_feedback);
 }
 
@@ -54,19 +114,145 @@ pub enum FileManagerSide {
     Right,
 }
 
+/// One side of the file manager. The directory listings are the tabs,
+/// other pages like file viewers are pushed on top of them.
+pub struct Pane {
+    tabs:               std::vec::Vec<PathSheet>,
+    pages:              std::vec::Vec<Box<dyn FmPage>>,
+    /// Quick view preview of the other pane's cursor entry, shown
+    /// instead of the pages.
+    preview:            Option<Box<dyn FmPage>>,
+    /// Which entries the directory listings of the pane show.
+    filter:             ListingFilter,
+}
+
+impl Pane {
+    fn new() -> Self {
+        Pane {
+            tabs:    Vec::new(),
+            pages:   Vec::new(),
+            filter:  ListingFilter::default(),
+            preview: None,
+        }
+    }
+
+    /// Brings the next tab to the front, the current one goes last.
+    fn cycle_tabs(&mut self) {
+        if self.tabs.len() > 1 {
+            self.tabs.rotate_left(1);
+            self.pages.clear();
+        }
+    }
+
+    /// Closes the current tab, unless it is the last one.
+    fn close_tab(&mut self) {
+        if self.tabs.len() > 1 {
+            self.tabs.remove(0);
+            self.pages.clear();
+        }
+    }
+
+    fn active_page(&mut self) -> Option<&mut dyn FmPage> {
+        if let Some(page) = self.preview.as_mut() {
+            return Some(page.as_mut());
+        }
+        if let Some(page) = self.pages.last_mut() {
+            return Some(page.as_mut());
+        }
+        self.tabs.get_mut(0).map(|t| t as &mut dyn FmPage)
+    }
+
+    fn update(&mut self) -> std::vec::Vec<PageEvent> {
+        let mut events = vec![];
+        for tab in self.tabs.iter_mut() {
+            if let Some(ev) = tab.update() { events.push(ev); }
+        }
+        for page in self.pages.iter_mut() {
+            if let Some(ev) = page.update() { events.push(ev); }
+        }
+        if let Some(page) = self.preview.as_mut() {
+            if let Some(ev) = page.update() { events.push(ev); }
+        }
+        events
+    }
+
+    fn is_busy(&self) -> bool {
+        self.tabs.iter().any(|t| t.is_busy())
+        || self.pages.iter().any(|p| p.is_busy())
+        || self.preview.as_ref().map(|p| p.is_busy()).unwrap_or(false)
+    }
+}
+
 pub struct FileManager {
-    left:               std::vec::Vec<PathSheet>,
-    right:              std::vec::Vec<PathSheet>,
+    left:               Pane,
+    right:              Pane,
     log:                LogSheet,
     active_side:        FileManagerSide,
     input_line:         TextInputLine,
     prompt:             String,
     show_input_line:    bool,
+    query:              Option<QueryKind>,
+    query_skip_text:    bool,
+    saved_prompt:       (String, bool),
+    quick_view:         Option<QuickView>,
+    assoc:              Associations,
+    /// Shell commands whose output goes to the log.
+    jobs:               std::vec::Vec<ShellJob>,
+    /// Running copies and deletions, with the side they were started
+    /// from.
+    file_ops:           std::vec::Vec<(FileManagerSide, FileOpJob)>,
+    editor:             EditorConfig,
+    edits:              std::vec::Vec<(FileManagerSide, EditSession)>,
+    /// The visited directories for the jump query.
+    frecency:           FrecencyDb,
+    /// The colors of the directory listings.
+    ls_colors:          LsColors,
 }
 
 enum FileManagerAction {
     SetPrompt(String, bool),
     TextInput(TextInputAction),
+    AddAssociation(AssocMatch, String, String),
+    SetEditor(String, bool),
+    SetTerminal(String),
+    Panelize(String, Vec<String>),
+    SetBookmark(String, String),
+    RemoveBookmark(String),
+    OpenBookmark(String),
+    SetShowHidden(bool),
+    SetShowGitStatus(bool),
+    SetIgnoredMode(String),
+    SetGitignore(bool),
+    IgnoreGlob(String),
+    SetColor(String, String),
+}
+
+/// What the text typed into the input line is used for, while the
+/// file manager has taken over the input line.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum QueryKind {
+    Search,
+    Goto,
+    /// Answer to a `PageEvent::Prompt` of the active page.
+    PageEdit,
+    Chmod,
+    Chown,
+    Touch,
+    Shell,
+    NewFile,
+    Find,
+    ConfirmDelete,
+    ConfirmTrash,
+    Grep,
+    Panelize,
+    Checksum,
+    /// A single letter, completes the query as soon as it is typed.
+    SetMark,
+    JumpMark,
+    /// Filters the jump page while it is typed.
+    Jump,
+    /// Ranks the files of the fuzzy finder page while it is typed.
+    Fuzzy,
 }
 
 enum PanePos {
@@ -81,79 +267,1393 @@ struct WLCallbacks {
     ctx:        Rc<RefCell<EvalContext>>,
     cb_input:   VVal,
     cb_text:    VVal,
+    cb_init:    Option<VVal>,
     fm_api:     VVal,
 }
 
 impl WLCallbacks {
+    /// Calls the optional `on_init` callback of main.wl, which sets up
+    /// things like file associations.
+    pub fn on_init(&mut self) {
+        if let Some(cb_init) = self.cb_init.as_ref() {
+            self.ctx.borrow_mut()
+                .call(cb_init, &[self.fm_api.clone()])
+                .expect("No error in 'on_init' callback");
+        }
+    }
+
     pub fn on_input(&mut self, keybind: String) {
         self.ctx.borrow_mut()
             .call(&self.cb_input, &[self.fm_api.clone(), VVal::new_str_mv(keybind)])
             .expect("No error in 'on_input' callback");
     }
 
-    pub fn on_text(&mut self, txt: String) {
-        self.ctx.borrow_mut()
-            .call(&self.cb_text, &[self.fm_api.clone(), VVal::new_str_mv(txt)])
-            .expect("No error in 'on_text' callback");
+    pub fn on_text(&mut self, txt: String) {
+        self.ctx.borrow_mut()
+            .call(&self.cb_text, &[self.fm_api.clone(), VVal::new_str_mv(txt)])
+            .expect("No error in 'on_text' callback");
+    }
+
+    pub fn set_fm_api(&mut self, fm: VVal) {
+        self.fm_api = fm;
+    }
+}
+
+fn init_wlambda() -> WLCallbacks {
+    let genv = GlobalEnv::new_default();
+
+//    wlambda_bind(&mut genv.borrow_mut(), fm.clone());
+
+//    let lfmr =
+//        std::rc::Rc::new(std::cell::RefCell::new(
+//            wlambda::compiler::LocalFileModuleResolver::new()));
+//    genv.borrow_mut().set_resolver(lfmr);
+    let mut wl_eval_ctx = EvalContext::new_default();
+
+    match wl_eval_ctx.eval_file("main.wl") {
+        Ok(v) => {
+            if v.is_err() {
+                panic!(format!("'main.wl' SCRIPT ERROR: {}", v.s()));
+            }
+
+            let cb_input =
+                    wl_eval_ctx
+                    .get_global_var("on_input")
+                    .expect("'on_input' global callback in main.wl");
+            let cb_text =
+                    wl_eval_ctx
+                    .get_global_var("on_text")
+                    .expect("'on_text' global callback in main.wl");
+            let cb_init = wl_eval_ctx.get_global_var("on_init");
+            WLCallbacks {
+                ctx:        Rc::new(RefCell::new(wl_eval_ctx)),
+                fm_api:     VVal::None,
+                cb_input,
+                cb_text,
+                cb_init,
+            }
+        },
+        Err(e) => { panic!(format!("'main.wl' SCRIPT ERROR: {}", e)); }
+    }
+}
+
+impl FileManager {
+    /// Opens the directory in a new tab, in front of the other tabs of
+    /// the pane.
+    fn open_path_in(&mut self, path: &std::path::Path, pos: PanePos) {
+        let side =
+            match pos {
+                PanePos::LeftTab  => FileManagerSide::Left,
+                PanePos::RightTab => FileManagerSide::Right,
+            };
+        let mut ps = match self.read_listing(side, path) {
+            Ok(ps) => ps,
+            Err(e) => {
+                self.log.append_msg(
+                    format!("Can't open {}: {:?}", path.to_string_lossy(), e));
+                return;
+            },
+        };
+        ps.history.visit(path);
+        let pane = self.pane_mut(side);
+        pane.pages.clear();
+        pane.tabs.insert(0, ps);
+        self.record_visit(path);
+    }
+
+    fn pane_mut(&mut self, side: FileManagerSide) -> &mut Pane {
+        match side {
+            FileManagerSide::Left  => &mut self.left,
+            FileManagerSide::Right => &mut self.right,
+        }
+    }
+
+    /// Reads the directory listing with the filter of the pane on the
+    /// given side, sorted by name.
+    fn read_listing(&mut self, side: FileManagerSide, path: &std::path::Path)
+        -> Result<PathSheet, FMError> {
+
+        let mut ps = PathSheet::read(path)?;
+        ps.apply_filter(&self.pane_mut(side).filter);
+        ps.sort_by_column(0);
+        Ok(ps)
+    }
+
+    /// Replaces the directory listing of the current tab on the given
+    /// side and closes all pages on top of it.
+    fn navigate_to(&mut self, side: FileManagerSide, path: &std::path::Path) {
+        let mut ps = match self.read_listing(side, path) {
+            Ok(ps) => ps,
+            Err(e) => {
+                self.log.append_msg(
+                    format!("Can't open {}: {:?}", path.to_string_lossy(), e));
+                return;
+            },
+        };
+
+        let pane = self.pane_mut(side);
+        pane.pages.clear();
+        if let Some(old) = pane.tabs.get_mut(0) {
+            ps.set_render_feedback(old.render_feedback.clone());
+            // Coming back up from a subdirectory keeps it under the cursor:
+            let came_from = old.base.clone();
+            ps.set_cursor_path(&came_from);
+
+            ps.history = std::mem::take(&mut old.history);
+            ps.history.leave(old.cursor_path(), old.cursor.scroll_offset);
+        }
+        ps.history.visit(path);
+
+        if pane.tabs.is_empty() {
+            pane.tabs.push(ps);
+        } else {
+            pane.tabs[0] = ps;
+        }
+        self.record_visit(path);
+    }
+
+    /// Records a directory visit for the jump query. The visits are
+    /// saved by `save_visits` later.
+    fn record_visit(&mut self, dir: &std::path::Path) {
+        self.frecency.visit(dir);
+    }
+
+    /// Saves the directory visits once they are due, or right away
+    /// if `now` is set, like on exit.
+    fn save_visits(&mut self, now: bool) {
+        if let Err(e) = self.frecency.save_unsaved(now) {
+            self.log.append_msg(format!("Can't save the directory visits: {}", e));
+        }
+    }
+
+    /// Indexes the files below the directory of the active pane and
+    /// ranks them by the fuzzy query while it is typed.
+    fn start_fuzzy(&mut self) {
+        let side = self.active_side;
+        let base =
+            match self.pane_mut(side).tabs.get(0) {
+                Some(tab) => tab.base.clone(),
+                None      => return,
+            };
+        let show_hidden = self.pane_mut(side).filter.show_hidden;
+        self.push_page(side, Box::new(FuzzySheet::new(&base, show_hidden)));
+        // Ctrl+P does not produce text input, so nothing is skipped:
+        self.start_query(QueryKind::Fuzzy, "find file: ", "", false);
+    }
+
+    /// Opens the file under the cursor of the fuzzy finder page with its
+    /// associated program, instead of showing it in its directory.
+    fn open_fuzzy_match(&mut self) {
+        self.end_query();
+        let side = self.active_side;
+        let path = self.pane_mut(side).active_page().and_then(|p| p.cursor_path());
+        self.process_page_control(PageControl::Back, None);
+        if let Some(path) = path {
+            self.handle_page_event(side, PageEvent::OpenFile(path));
+        }
+    }
+
+    /// Shows the visited directories ranked by frecency, filtered by
+    /// the jump query while it is typed.
+    fn start_jump(&mut self) {
+        let side = self.active_side;
+        let base =
+            match self.pane_mut(side).tabs.get(0) {
+                Some(tab) => tab.base.clone(),
+                None      => return,
+            };
+        let page = JumpSheet::new(self.frecency.clone(), &base);
+        self.push_page(side, Box::new(page));
+        self.start_query(QueryKind::Jump, "jump: ", "", true);
+    }
+
+    /// Goes to a directory in the history of the current tab, back to
+    /// the cursor entry and scroll offset it was left with.
+    fn goto_history(&mut self, side: FileManagerSide, idx: usize) {
+        let entry =
+            match self.pane_mut(side).tabs.get(0).and_then(|t| t.history.entries.get(idx)) {
+                Some(entry) => entry.clone(),
+                None        => return,
+            };
+        let mut ps = match self.read_listing(side, &entry.dir) {
+            Ok(ps) => ps,
+            Err(e) => {
+                self.log.append_msg(
+                    format!("Can't open {}: {:?}", entry.dir.to_string_lossy(), e));
+                return;
+            },
+        };
+
+        let pane = self.pane_mut(side);
+        pane.pages.clear();
+        if let Some(old) = pane.tabs.get_mut(0) {
+            ps.set_render_feedback(old.render_feedback.clone());
+            ps.history = std::mem::take(&mut old.history);
+            ps.history.leave(old.cursor_path(), old.cursor.scroll_offset);
+        }
+        ps.history.pos = idx;
+        ps.cursor.scroll_offset = entry.scroll_offset;
+        if let Some(cursor) = &entry.cursor {
+            ps.set_cursor_path(cursor);
+        }
+        pane.tabs[0] = ps;
+        self.record_visit(&entry.dir);
+    }
+
+    /// Goes back (negative) or forward in the history of the current
+    /// tab of the active pane.
+    fn step_history(&mut self, offs: isize) {
+        let side = self.active_side;
+        let idx = self.pane_mut(side).tabs.get(0).and_then(|t| t.history.step(offs));
+        match idx {
+            Some(idx) => self.goto_history(side, idx),
+            None => {
+                self.log.append_msg(String::from(
+                    if offs < 0 { "history: no previous directory" }
+                    else        { "history: no next directory" }));
+            },
+        }
+    }
+
+    /// Lists the visited directories of the current tab.
+    fn show_history(&mut self) {
+        let side = self.active_side;
+        let history =
+            match self.pane_mut(side).tabs.get(0) {
+                Some(tab) => tab.history.clone(),
+                None      => return,
+            };
+        self.push_page(side, Box::new(HistorySheet::new(history)));
+    }
+
+    fn push_page(&mut self, side: FileManagerSide, page: Box<dyn FmPage>) {
+        self.pane_mut(side).pages.push(page);
+    }
+
+    fn handle_page_event(&mut self, side: FileManagerSide, ev: PageEvent) {
+        match ev {
+            PageEvent::Redraw => (),
+            PageEvent::Close => {
+                self.pane_mut(side).pages.pop();
+            },
+            PageEvent::OpenDir(path) => {
+                self.navigate_to(side, &path);
+            },
+            PageEvent::GotoHistory(idx) => {
+                self.goto_history(side, idx);
+            },
+            PageEvent::OpenTab(path) => {
+                let pos =
+                    match side {
+                        FileManagerSide::Left  => PanePos::LeftTab,
+                        FileManagerSide::Right => PanePos::RightTab,
+                    };
+                self.open_path_in(&path, pos);
+            },
+            PageEvent::ShowEntry(path) => {
+                if let Some(dir) = path.parent() {
+                    self.navigate_to(side, dir);
+                    if let Some(tab) = self.pane_mut(side).tabs.get_mut(0) {
+                        tab.set_cursor_path(&path);
+                    }
+                }
+            },
+            PageEvent::ViewFile(path, line) => {
+                match TextViewSheet::open(&path) {
+                    Ok(mut page) => {
+                        page.do_control(PageControl::Goto(line.to_string()));
+                        self.push_page(side, Box::new(page));
+                    },
+                    Err(e) => {
+                        self.log.append_msg(
+                            format!("Can't view {}: {}", path.to_string_lossy(), e));
+                    },
+                }
+            },
+            PageEvent::OpenFile(path) => {
+                match HashAlgo::for_checksum_file(&path) {
+                    Some(algo) => self.verify_checksums(side, &path, algo),
+                    None       => self.open_file(side, &path),
+                }
+            },
+            PageEvent::Launch(args, cwd) => {
+                self.launch(&args, &cwd);
+            },
+            PageEvent::Prompt(prompt, text) => {
+                self.start_query(QueryKind::PageEdit, &prompt, &text, false);
+            },
+            PageEvent::Log(msgs) => {
+                for msg in msgs {
+                    self.log.append_msg(msg);
+                }
+            },
+            PageEvent::RefreshDirs => {
+                self.refresh_tabs();
+            },
+        }
+    }
+
+    /// Collects what the placeholders of a command expand to, for the
+    /// given entry in the pane on `side`.
+    fn command_context(&mut self, side: FileManagerSide, path: &std::path::Path) -> CommandContext {
+        let other = if side == FileManagerSide::Left { FileManagerSide::Right } else { FileManagerSide::Left };
+        let other_dir = self.pane_mut(other).tabs.get(0).map(|t| t.base.clone());
+
+        let pane = self.pane_mut(side);
+        let dir =
+            pane.tabs.get(0)
+                .map(|t| t.base.clone())
+                .or_else(|| path.parent().map(|p| p.to_path_buf()))
+                .unwrap_or_default();
+        let mut selection =
+            pane.active_page().map(|p| p.selected_paths()).unwrap_or_default();
+        if selection.is_empty() {
+            selection.push(path.to_path_buf());
+        }
+
+        CommandContext {
+            path: path.to_path_buf(),
+            dir,
+            selection,
+            other_dir,
+        }
+    }
+
+    /// Opens a file with the default handler from the association table.
+    fn open_file(&mut self, side: FileManagerSide, path: &std::path::Path) {
+        let ctx     = self.command_context(side, path);
+        let command = self.assoc.default_command(path);
+        self.launch(&ctx.expand_args(&command), &ctx.dir);
+    }
+
+    fn launch(&mut self, args: &[String], cwd: &std::path::Path) {
+        match spawn_detached(args, cwd) {
+            Ok(())  => self.log.append_msg(format!("Started: {}", args.join(" "))),
+            Err(e)  => self.log.append_msg(format!("Can't start '{}': {}", args.join(" "), e)),
+        }
+    }
+
+    /// Lists the programs the cursor entry of the active pane can be
+    /// opened with.
+    fn show_open_with(&mut self) {
+        let side = self.active_side;
+        let path =
+            match self.pane_mut(side).active_page().and_then(|p| p.cursor_path()) {
+                Some(path) if !path.is_dir() => path,
+                _ => return,
+            };
+        let ctx  = self.command_context(side, &path);
+        let page = OpenWithSheet::new(&self.assoc, &ctx);
+        self.push_page(side, Box::new(page));
+    }
+
+    /// Shows the directory tree below the directory of the active pane.
+    fn show_tree(&mut self) {
+        let side = self.active_side;
+        let base =
+            match self.pane_mut(side).tabs.get(0) {
+                Some(tab) => tab.base.clone(),
+                None      => return,
+            };
+        self.push_page(side, Box::new(TreeSheet::new(&base)));
+    }
+
+    /// Scans the disk usage below the directory of the active pane.
+    fn show_disk_usage(&mut self) {
+        let side = self.active_side;
+        let base =
+            match self.pane_mut(side).tabs.get(0) {
+                Some(tab) => tab.base.clone(),
+                None      => return,
+            };
+        self.push_page(side, Box::new(DiskUsageSheet::new(&base)));
+    }
+
+    /// Lists the bookmarks in the active pane.
+    fn show_bookmarks(&mut self) {
+        let side = self.active_side;
+        let base =
+            match self.pane_mut(side).tabs.get(0) {
+                Some(tab) => tab.base.clone(),
+                None      => return,
+            };
+        self.push_page(side, Box::new(BookmarkSheet::new(&base)));
+    }
+
+    fn set_bookmark(&mut self, name: &str, path: &std::path::Path) {
+        let res =
+            Bookmarks::load().and_then(|mut bm| {
+                bm.set(name, path);
+                bm.save()
+            });
+        match res {
+            Ok(()) => {
+                self.log.append_msg(
+                    format!("bookmark '{}': {}", name, path.to_string_lossy()));
+            },
+            Err(e) => self.log.append_msg(format!("Can't save bookmarks: {}", e)),
+        }
+    }
+
+    fn remove_bookmark(&mut self, name: &str) {
+        let res =
+            Bookmarks::load().and_then(|mut bm| {
+                if bm.remove(name) { bm.save() } else { Ok(()) }
+            });
+        if let Err(e) = res {
+            self.log.append_msg(format!("Can't save bookmarks: {}", e));
+        }
+    }
+
+    /// Marks the directory of the active pane with a letter, like `m`
+    /// in vim.
+    fn set_quick_mark(&mut self, letter: &str) {
+        if letter.chars().count() != 1 || !letter.chars().all(|c| c.is_alphanumeric()) {
+            self.log.append_msg(format!("mark: '{}' is not a letter", letter));
+            return;
+        }
+        let side = self.active_side;
+        let base =
+            match self.pane_mut(side).tabs.get(0) {
+                Some(tab) => tab.base.clone(),
+                None      => return,
+            };
+        self.set_bookmark(letter, &base);
+    }
+
+    /// Opens the directory of a bookmark or quick mark in the active
+    /// pane.
+    fn open_bookmark(&mut self, name: &str) {
+        let path =
+            match Bookmarks::load() {
+                Ok(bm) => bm.get(name).map(|p| p.to_path_buf()),
+                Err(e) => {
+                    self.log.append_msg(format!("Can't read bookmarks: {}", e));
+                    return;
+                },
+            };
+        match path {
+            Some(path) if path.is_dir() => {
+                let side = self.active_side;
+                self.navigate_to(side, &path);
+            },
+            Some(path) => {
+                self.log.append_msg(
+                    format!("bookmark '{}' is missing: {}", name, path.to_string_lossy()));
+            },
+            None => self.log.append_msg(format!("no bookmark '{}'", name)),
+        }
+    }
+
+    /// Lists the mounted file systems in the active pane.
+    fn show_drives(&mut self) {
+        let side = self.active_side;
+        self.push_page(side, Box::new(DrivesSheet::new()));
+    }
+
+    /// Computes the checksums of the selected files, directories are
+    /// walked recursively.
+    fn start_checksums(&mut self, algo: &str) {
+        let algo =
+            match HashAlgo::from_name(algo) {
+                Some(algo) => algo,
+                None => {
+                    self.log.append_msg(format!("checksum: unknown algorithm '{}'", algo.trim()));
+                    return;
+                },
+            };
+        let paths = self.batch_paths();
+        if paths.is_empty() {
+            return;
+        }
+        let side = self.active_side;
+        let base =
+            match self.pane_mut(side).tabs.get(0) {
+                Some(tab) => tab.base.clone(),
+                None      => return,
+            };
+        self.push_page(side, Box::new(ChecksumSheet::compute(&base, paths, algo)));
+    }
+
+    fn verify_checksums(&mut self, side: FileManagerSide, file: &std::path::Path, algo: HashAlgo) {
+        match ChecksumSheet::verify(file, algo) {
+            Ok(page) => self.push_page(side, Box::new(page)),
+            Err(e) => {
+                self.log.append_msg(format!("Can't read {}: {}", file.to_string_lossy(), e));
+            },
+        }
+    }
+
+    /// Searches duplicate files below the directory of the active pane.
+    fn show_dupes(&mut self) {
+        let side = self.active_side;
+        let base =
+            match self.pane_mut(side).tabs.get(0) {
+                Some(tab) => tab.base.clone(),
+                None      => return,
+            };
+        self.push_page(side, Box::new(DupeSheet::new(&base)));
+    }
+
+    /// Compares the cursor files of both panes on a thread. Text files
+    /// are shown side by side in the active pane, for binary files the
+    /// first differing byte is reported.
+    fn compare_files(&mut self) {
+        let side  = self.active_side;
+        let other = self.other_side();
+        let left  = self.pane_mut(side).active_page().and_then(|p| p.cursor_path());
+        let right = self.pane_mut(other).active_page().and_then(|p| p.cursor_path());
+        let (left, right) =
+            match (left, right) {
+                (Some(l), Some(r)) if l.is_file() && r.is_file() => (l, r),
+                _ => {
+                    self.log.append_msg(String::from("compare: the cursor entries of both panes must be files"));
+                    return;
+                },
+            };
+
+        self.push_page(side, Box::new(DiffSheet::compare(&left, &right)));
+    }
+
+    /// Shows the cursor entry of the active page in the other pane: a
+    /// directory is opened, other entries are shown in their directory.
+    fn sync_other_pane(&mut self) {
+        let side = self.active_side;
+        let path =
+            match self.pane_mut(side).active_page().and_then(|p| p.cursor_path()) {
+                Some(path) => path,
+                None       => return,
+            };
+        let other = self.other_side();
+        if path.is_dir() {
+            self.handle_page_event(other, PageEvent::OpenDir(path));
+        } else {
+            self.handle_page_event(other, PageEvent::ShowEntry(path));
+        }
+    }
+
+    /// Shows all metadata of the cursor entry of the active pane.
+    fn show_properties(&mut self) {
+        let side = self.active_side;
+        if let Some(path) = self.pane_mut(side).active_page().and_then(|p| p.cursor_path()) {
+            self.push_page(side, Box::new(PropertiesSheet::new(&path)));
+        }
+    }
+
+    /// Opens the file under the cursor of the active pane in the
+    /// internal viewer. Images are shown in the image viewer and other
+    /// binary files in the hex viewer.
+    fn view_cursor_entry(&mut self) {
+        let side = self.active_side;
+        let path =
+            match self.pane_mut(side).active_page().and_then(|p| p.cursor_path()) {
+                Some(path) => path,
+                None       => return,
+            };
+        if path.is_dir() {
+            return;
+        }
+        // Opening a FIFO would block until something writes to it:
+        if !path.metadata().map(|md| md.is_file()).unwrap_or(false) {
+            self.log.append_msg(
+                format!("Can't view {}: not a regular file", path.to_string_lossy()));
+            return;
+        }
+
+        if is_image_file(&path) {
+            match ImageViewSheet::open(&path) {
+                Ok(page) => { self.push_page(side, Box::new(page)); return; },
+                Err(e) => {
+                    self.log.append_msg(
+                        format!("Can't show image {}: {}", path.to_string_lossy(), e));
+                },
+            }
+        }
+
+        let page : std::io::Result<Box<dyn FmPage>> =
+            match is_binary_file(&path) {
+                Ok(true)  => HexViewSheet::open(&path).map(|p| Box::new(p) as Box<dyn FmPage>),
+                Ok(false) => TextViewSheet::open(&path).map(|p| Box::new(p) as Box<dyn FmPage>),
+                Err(e)    => Err(e),
+            };
+
+        match page {
+            Ok(page) => self.push_page(side, page),
+            Err(e) => {
+                self.log.append_msg(
+                    format!("Can't view {}: {}", path.to_string_lossy(), e));
+            },
+        }
+    }
+
+    /// Polls the background work of all pages. Returns true if anything
+    /// needs to be redrawn.
+    fn update(&mut self) -> bool {
+        let mut changed = self.update_quick_view();
+        changed = self.update_jobs() || changed;
+        changed = self.update_file_ops() || changed;
+        changed = self.update_edits() || changed;
+        self.save_visits(false);
+        for side in [FileManagerSide::Left, FileManagerSide::Right].iter() {
+            for ev in self.pane_mut(*side).update() {
+                self.handle_page_event(*side, ev);
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    fn is_busy(&self) -> bool {
+        self.left.is_busy() || self.right.is_busy()
+        || !self.jobs.is_empty()
+        || !self.file_ops.is_empty()
+        || !self.edits.is_empty()
+        || self.quick_view.as_ref().map(|qv| qv.is_busy()).unwrap_or(false)
+    }
+
+    /// Takes over the input line for a query, starting with `text`. If
+    /// the query was started by a key that also produces text input,
+    /// that text is skipped.
+    fn start_query(&mut self, kind: QueryKind, prompt: &str, text: &str, from_text_key: bool) {
+        if self.query.is_none() {
+            self.saved_prompt =
+                (std::mem::replace(&mut self.prompt, prompt.to_string()),
+                 self.show_input_line);
+        } else {
+            self.prompt = prompt.to_string();
+        }
+        self.show_input_line = true;
+        self.query           = Some(kind);
+        self.query_skip_text = from_text_key;
+        self.input_line.handle_input(TextInputAction::Replace(text.to_string()));
+        self.input_line.handle_input(TextInputAction::CursorEnd);
+    }
+
+    fn end_query(&mut self) -> Option<(QueryKind, String)> {
+        let kind = self.query.take()?;
+        let (_, _, text) = self.input_line.get_line_info();
+        let text = text.to_string();
+        self.input_line.handle_input(TextInputAction::Clear);
+        self.prompt          = self.saved_prompt.0.clone();
+        self.show_input_line = self.saved_prompt.1;
+        Some((kind, text))
+    }
+
+    fn submit_query(&mut self) {
+        let (kind, text) = match self.end_query() {
+            Some(q) => q,
+            None    => return,
+        };
+
+        match kind {
+            QueryKind::Search => {
+                self.process_page_control(PageControl::Search(text), None);
+            },
+            QueryKind::Goto => {
+                self.process_page_control(PageControl::Goto(text), None);
+            },
+            QueryKind::PageEdit => {
+                self.process_page_control(PageControl::Edit(text), None);
+            },
+            QueryKind::Chmod | QueryKind::Chown | QueryKind::Touch => {
+                self.change_attributes(kind, &text);
+            },
+            QueryKind::Shell => {
+                self.run_shell_command(&text);
+            },
+            QueryKind::NewFile => {
+                self.edit_new_file(&text);
+            },
+            QueryKind::Find => {
+                self.start_find(&text);
+            },
+            QueryKind::Grep => {
+                self.start_grep(&text);
+            },
+            QueryKind::Panelize => {
+                self.panelize_command(&text);
+            },
+            QueryKind::Checksum => {
+                self.start_checksums(&text);
+            },
+            QueryKind::SetMark => {
+                self.set_quick_mark(&text);
+            },
+            QueryKind::JumpMark => {
+                self.open_bookmark(&text);
+            },
+            QueryKind::Jump | QueryKind::Fuzzy => {
+                self.process_page_control(PageControl::Access, None);
+            },
+            QueryKind::ConfirmDelete => {
+                if text.trim().eq_ignore_ascii_case("y") {
+                    self.delete_batch();
+                }
+            },
+            QueryKind::ConfirmTrash => {
+                if text.trim().eq_ignore_ascii_case("y") {
+                    self.trash_batch();
+                }
+            },
+        }
+    }
+
+    /// Searches the directory tree of the active pane and shows the
+    /// results in a new page.
+    fn start_find(&mut self, query: &str) {
+        let criteria = match FindCriteria::parse(query) {
+            Ok(c)  => c,
+            Err(e) => {
+                self.log.append_msg(format!("find: {}", e));
+                return;
+            },
+        };
+        let side = self.active_side;
+        let base =
+            match self.pane_mut(side).tabs.get(0) {
+                Some(tab) => tab.base.clone(),
+                None      => return,
+            };
+        self.push_page(side, Box::new(SearchResultSheet::new(&base, query, criteria)));
+    }
+
+    /// Searches the lines of the files below the directory of the active
+    /// pane and shows them in a new page.
+    fn start_grep(&mut self, pattern: &str) {
+        if pattern.is_empty() {
+            return;
+        }
+        let re = match grep_regex(pattern) {
+            Ok(re) => re,
+            Err(e) => {
+                self.log.append_msg(format!("grep: {}", e));
+                return;
+            },
+        };
+        let side = self.active_side;
+        let base =
+            match self.pane_mut(side).tabs.get(0) {
+                Some(tab) => tab.base.clone(),
+                None      => return,
+            };
+        self.push_page(side, Box::new(GrepSheet::new(&base, pattern, re)));
+    }
+
+    /// Shows a list of paths as a listing in the active pane. Relative
+    /// paths are resolved against the directory of the pane.
+    fn panelize(&mut self, title: &str, paths: &[std::path::PathBuf]) {
+        let side = self.active_side;
+        let base =
+            match self.pane_mut(side).tabs.get(0) {
+                Some(tab) => tab.base.clone(),
+                None      => return,
+            };
+        let (records, errors) = read_path_list(&base, paths);
+        for msg in errors {
+            self.log.append_msg(msg);
+        }
+        self.push_page(side, Box::new(PathSheet::panelize(&base, title, records)));
+    }
+
+    /// Panelizes the selected entries of the active page, or all
+    /// results of a search page.
+    fn panelize_page(&mut self) {
+        let side = self.active_side;
+        let paths =
+            match self.pane_mut(side).active_page() {
+                Some(page) => page.selected_paths(),
+                None       => return,
+            };
+        if paths.is_empty() {
+            return;
+        }
+        self.panelize("panel", &paths);
+    }
+
+    /// Runs a shell command in the directory of the active pane and
+    /// panelizes the paths it prints. The command is expanded like the
+    /// ones run by `run_shell_command`.
+    fn panelize_command(&mut self, command: &str) {
+        let command = command.trim();
+        if command.is_empty() {
+            return;
+        }
+        let side = self.active_side;
+        let dir =
+            match self.pane_mut(side).tabs.get(0) {
+                Some(tab) => tab.base.clone(),
+                None      => return,
+            };
+        let cursor =
+            self.pane_mut(side).active_page()
+                .and_then(|p| p.cursor_path())
+                .unwrap_or_else(|| dir.clone());
+        let command = self.command_context(side, &cursor).expand_shell(command);
+
+        match ShellJob::start(&command, &dir) {
+            Ok(job) => self.push_page(side, Box::new(PathSheet::panelize_command(&dir, job))),
+            Err(e)  => self.log.append_msg(format!("Can't run '{}': {}", command, e)),
+        }
+    }
+
+    /// Lets the page on the side the files were changed from drop the
+    /// entries that vanished, and reads the directory listings again.
+    fn refresh_after_ops(&mut self, side: FileManagerSide) {
+        if let Some(page) = self.pane_mut(side).active_page() {
+            page.clear_selection();
+            page.do_control(PageControl::Refresh);
+        }
+        self.refresh_tabs();
+    }
+
+    /// Copies the selected entries into the directory of the other pane,
+    /// on a thread.
+    fn copy_batch(&mut self) {
+        let paths = self.batch_paths();
+        let other = self.other_side();
+        let dst_dir =
+            match self.pane_mut(other).tabs.get(0) {
+                Some(tab) => tab.base.clone(),
+                None      => return,
+            };
+        self.log.append_msg(format!("Copying {} entries to {}, Escape cancels",
+            paths.len(), dst_dir.to_string_lossy()));
+        self.file_ops.push((self.active_side, FileOpJob::copy(paths, dst_dir)));
+    }
+
+    fn start_delete(&mut self) {
+        let count = self.batch_paths().len();
+        if count == 0 {
+            return;
+        }
+        self.start_query(QueryKind::ConfirmDelete,
+            &format!("delete {} entries? [y/N]: ", count), "", true);
+    }
+
+    /// Deletes the selected entries on a thread.
+    fn delete_batch(&mut self) {
+        let paths = self.batch_paths();
+        self.log.append_msg(format!("Deleting {} entries, Escape cancels", paths.len()));
+        self.file_ops.push((self.active_side, FileOpJob::delete(paths)));
+    }
+
+    /// Logs the progress of the copies and deletions, and refreshes the
+    /// panes when one is done. Returns true if anything was logged.
+    fn update_file_ops(&mut self) -> bool {
+        let mut changed  = false;
+        let mut finished = vec![];
+        for (i, (_, job)) in self.file_ops.iter().enumerate() {
+            for msg in job.poll() {
+                changed = true;
+                match msg {
+                    FileOpMsg::Progress(n) => {
+                        self.log.append_msg(format!("{}: {} entries done...", job.what, n));
+                    },
+                    FileOpMsg::Done(log) => {
+                        for line in log {
+                            self.log.append_msg(line);
+                        }
+                        finished.push(i);
+                    },
+                }
+            }
+        }
+
+        if !finished.is_empty() {
+            for i in finished.into_iter().rev() {
+                let (side, _) = self.file_ops.remove(i);
+                self.refresh_after_ops(side);
+            }
+        }
+        changed
+    }
+
+    /// Stops the copies, deletions and shell commands running for the
+    /// log, and the background work of the active page.
+    fn cancel_jobs(&mut self) {
+        for (_, job) in self.file_ops.iter() {
+            job.cancel();
+        }
+        for job in self.jobs.iter() {
+            job.cancel();
+        }
+        let side = self.active_side;
+        if let Some(page) = self.pane_mut(side).active_page() {
+            page.cancel();
+        }
+    }
+
+    /// Asks before moving the selected entries to the trash.
+    fn start_trash(&mut self) {
+        let count = self.batch_paths().len();
+        if count == 0 {
+            return;
+        }
+        self.start_query(QueryKind::ConfirmTrash,
+            &format!("move {} entries to the trash? [y/N]: ", count), "", true);
+    }
+
+    /// Moves the selected entries to the trash.
+    fn trash_batch(&mut self) {
+        let mut count = 0;
+        let mut failed = 0;
+        for path in self.batch_paths().iter() {
+            match move_to_trash(path) {
+                Ok(_)  => count += 1,
+                Err(e) => {
+                    failed += 1;
+                    self.log.append_msg(format!("{}: {}", path.to_string_lossy(), e));
+                },
+            }
+        }
+        self.log.append_msg(format!("Moved {} entries to the trash, {} failed", count, failed));
+        self.refresh_after_ops(self.active_side);
+    }
+
+    fn edit_file(&mut self, side: FileManagerSide, path: &std::path::Path, line: Option<usize>) {
+        let args = self.editor.args(path, line);
+        let cwd  = path.parent().unwrap_or(path).to_path_buf();
+        match EditSession::start(&args, path, &cwd) {
+            Ok(session) => {
+                self.log.append_msg(format!("Editing: {}", args.join(" ")));
+                self.edits.push((side, session));
+            },
+            Err(e) => {
+                self.log.append_msg(format!("Can't start editor '{}': {}", args.join(" "), e));
+            },
+        }
+    }
+
+    fn edit_cursor_entry(&mut self) {
+        let side = self.active_side;
+        let (path, line) =
+            match self.pane_mut(side).active_page() {
+                Some(page) => (page.cursor_path(), page.cursor_line()),
+                None       => return,
+            };
+        match path {
+            Some(path) if !path.is_dir() => self.edit_file(side, &path, line),
+            _ => (),
+        }
+    }
+
+    /// Edits a file that may not exist yet, relative to the directory of
+    /// the active pane.
+    fn edit_new_file(&mut self, name: &str) {
+        let name = name.trim();
+        if name.is_empty() {
+            return;
+        }
+        let side = self.active_side;
+        let path =
+            match self.pane_mut(side).tabs.get(0) {
+                Some(tab) => tab.base.join(name),
+                None      => return,
+            };
+        self.edit_file(side, &path, None);
+    }
+
+    /// Refreshes the panes when an editor exited and puts the cursor on
+    /// the edited file. Returns true if any editor exited.
+    fn update_edits(&mut self) -> bool {
+        let mut finished = vec![];
+        let mut i = 0;
+        while i < self.edits.len() {
+            if let Some(status) = self.edits[i].1.poll() {
+                let (side, session) = self.edits.remove(i);
+                finished.push((side, session.path, status));
+            } else {
+                i += 1;
+            }
+        }
+        if finished.is_empty() {
+            return false;
+        }
+
+        self.refresh_tabs();
+        for (side, path, status) in finished {
+            self.log.append_msg(format!("Editor for {} exited [{}]", path.to_string_lossy(), status));
+            if let Some(tab) = self.pane_mut(side).tabs.get_mut(0) {
+                if Some(tab.base.as_path()) == path.parent() {
+                    tab.set_cursor_path(&path);
+                }
+            }
+        }
+        true
+    }
+
+    /// Runs a shell command in the directory of the active pane. Its
+    /// output is shown in an output page, or in the log if the command
+    /// starts with `!`.
+    fn run_shell_command(&mut self, command: &str) {
+        let (to_log, command) =
+            match command.strip_prefix('!') {
+                Some(command) => (true, command.trim()),
+                None          => (false, command.trim()),
+            };
+        if command.is_empty() {
+            return;
+        }
+
+        let side = self.active_side;
+        let dir =
+            match self.pane_mut(side).tabs.get(0) {
+                Some(tab) => tab.base.clone(),
+                None      => return,
+            };
+        let cursor =
+            self.pane_mut(side).active_page()
+                .and_then(|p| p.cursor_path())
+                .unwrap_or_else(|| dir.clone());
+        let command = self.command_context(side, &cursor).expand_shell(command);
+
+        match ShellJob::start(&command, &dir) {
+            Ok(job) => {
+                if to_log {
+                    self.log.append_msg(format!("$ {}", command));
+                    self.jobs.push(job);
+                } else {
+                    self.push_page(side, Box::new(OutputSheet::new(job)));
+                }
+            },
+            Err(e) => {
+                self.log.append_msg(format!("Can't run '{}': {}", command, e));
+            },
+        }
+    }
+
+    /// Moves the output of the shell commands running for the log into
+    /// the log. Returns true if there was any.
+    fn update_jobs(&mut self) -> bool {
+        let mut changed  = false;
+        let mut finished = false;
+        for job in self.jobs.iter_mut() {
+            for out in job.poll() {
+                changed = true;
+                match out {
+                    ShellOutput::Stdout(line) => self.log.append_msg(line),
+                    ShellOutput::Stderr(line) => self.log.append_msg(format!("stderr: {}", line)),
+                    ShellOutput::Exit(status) => {
+                        self.log.append_msg(format!("$ {} [{}]", job.command, status));
+                        finished = true;
+                    },
+                }
+            }
+        }
+
+        if finished {
+            self.jobs.retain(|j| j.is_running());
+            self.refresh_tabs();
+        }
+        changed
+    }
+
+    /// The entries a batch operation works on: the selection of the
+    /// active page, or the cursor entry if nothing is selected.
+    fn batch_paths(&mut self) -> std::vec::Vec<std::path::PathBuf> {
+        let side = self.active_side;
+        let page = match self.pane_mut(side).active_page() {
+            Some(page) => page,
+            None       => return vec![],
+        };
+        let selected = page.selected_paths();
+        if !selected.is_empty() {
+            return selected;
+        }
+        page.cursor_path().into_iter().collect()
+    }
+
+    /// Asks for the argument of a chmod, chown or touch of the selected
+    /// entries.
+    fn start_attribute_query(&mut self, kind: QueryKind) {
+        let count = self.batch_paths().len();
+        if count == 0 {
+            return;
+        }
+        let prompt =
+            match kind {
+                QueryKind::Chmod => "chmod [-R] [-f|-d] MODE",
+                QueryKind::Chown => "chown [-R] [-f|-d] USER:GROUP",
+                _                => "touch [-R] [-f|-d] [-a|-m] now|TIME|ref PATH",
+            };
+        self.start_query(kind, &format!("{} ({} entries): ", prompt, count), "", true);
+    }
+
+    /// Runs chmod, chown or touch over the selected entries. Failing
+    /// entries are logged and skipped.
+    fn change_attributes(&mut self, kind: QueryKind, input: &str) {
+        let (recursive, filter, arg) = parse_batch_options(input);
+        let paths = self.batch_paths();
+        let mut log = vec![];
+
+        let mut op : EntryOp =
+            match kind {
+                QueryKind::Chmod => {
+                    match ModeChange::parse(&arg) {
+                        Ok(change) => Box::new(move |p| change_mode(p, &change)),
+                        Err(e) => {
+                            self.log.append_msg(format!("chmod: {}", e));
+                            return;
+                        },
+                    }
+                },
+                QueryKind::Chown => {
+                    match parse_owner(&arg) {
+                        Some((uid, gid)) => Box::new(move |p| set_owner(p, uid, gid)),
+                        None => {
+                            self.log.append_msg(
+                                format!("chown: unknown owner '{}', expected user:group", arg));
+                            return;
+                        },
+                    }
+                },
+                _ => {
+                    let side = self.active_side;
+                    let base =
+                        self.pane_mut(side).tabs.get(0)
+                            .map(|t| t.base.clone())
+                            .unwrap_or_default();
+                    match TouchSpec::parse(&arg, &base) {
+                        Ok(spec) => Box::new(move |p| set_times(p, spec.atime, spec.mtime)),
+                        Err(e) => {
+                            self.log.append_msg(format!("touch: {}", e));
+                            return;
+                        },
+                    }
+                },
+            };
+
+        let mut count = 0;
+        for path in paths.iter() {
+            count += apply_recursive(path, recursive, filter, &mut op, &mut log);
+        }
+
+        let failed = log.len();
+        for msg in log {
+            self.log.append_msg(msg);
+        }
+        let name =
+            match kind {
+                QueryKind::Chmod => "chmod",
+                QueryKind::Chown => "chown",
+                _                => "touch",
+            };
+        self.log.append_msg(
+            format!("{} '{}': changed {} entries, {} failed", name, arg, count, failed));
+
+        let side = self.active_side;
+        if let Some(page) = self.pane_mut(side).active_page() {
+            page.clear_selection();
+        }
+        self.refresh_tabs();
+    }
+
+    /// Reads the directory listings of both panes again, keeping the
+    /// cursor on the same entry.
+    fn refresh_tabs(&mut self) {
+        self.refresh_pane(FileManagerSide::Left);
+        self.refresh_pane(FileManagerSide::Right);
+    }
+
+    /// Reads the directory listings of all tabs on the given side again,
+    /// with the filter of the pane.
+    fn refresh_pane(&mut self, side: FileManagerSide) {
+        for idx in 0..self.pane_mut(side).tabs.len() {
+            self.refresh_tab(side, idx);
+        }
+    }
+
+    /// Reads the directory listing of a tab on the given side again,
+    /// keeping the cursor on the same entry if it is still listed, or
+    /// near its old position if not.
+    fn refresh_tab(&mut self, side: FileManagerSide, idx: usize) {
+        let base =
+            match self.pane_mut(side).tabs.get(idx) {
+                Some(old) => old.base.clone(),
+                None      => return,
+            };
+        let mut ps = match self.read_listing(side, &base) {
+            Ok(ps) => ps,
+            Err(_) => return,
+        };
+        let pane = self.pane_mut(side);
+        let old = &mut pane.tabs[idx];
+        ps.set_render_feedback(old.render_feedback.clone());
+        ps.cursor.cursor_idx    = old.cursor.cursor_idx;
+        ps.cursor.scroll_offset = old.cursor.scroll_offset;
+        // Keep showing the old git status until it is read again:
+        if ps.filter.git_status {
+            ps.git_status = old.git_status.clone();
+        }
+        // The free space too, and don't query it again while the last
+        // query still hangs:
+        ps.fs_space = old.fs_space;
+        if old.fs_space_job.is_some() {
+            ps.fs_space_job = old.fs_space_job.take();
+        }
+        ps.history    = old.history.clone();
+        if let Some(cursor) = old.cursor_path() {
+            ps.set_cursor_path(&cursor);
+        }
+        // Clamps the cursor and the scroll offset if the listing shrank:
+        let len = ps.paths.len();
+        let fb  = ps.render_feedback.clone();
+        ps.cursor.do_control(len, &fb, PageControl::Refresh);
+        pane.tabs[idx] = ps;
     }
 
-    pub fn set_fm_api(&mut self, fm: VVal) {
-        self.fm_api = fm;
+    /// Shows or hides the dotfiles in the active pane.
+    fn toggle_hidden(&mut self) {
+        let side = self.active_side;
+        let filter = &mut self.pane_mut(side).filter;
+        filter.show_hidden = !filter.show_hidden;
+        self.refresh_pane(side);
     }
-}
 
-fn init_wlambda() -> WLCallbacks {
-    let genv = GlobalEnv::new_default();
+    /// Shows or hides the git status column in the active pane.
+    fn toggle_git_status(&mut self) {
+        let side = self.active_side;
+        let filter = &mut self.pane_mut(side).filter;
+        filter.git_status = !filter.git_status;
+        self.refresh_pane(side);
+    }
 
-//    wlambda_bind(&mut genv.borrow_mut(), fm.clone());
+    /// Switches the active pane between showing, dimming and hiding the
+    /// entries matched by the ignore rules.
+    fn cycle_ignored_mode(&mut self) {
+        let side = self.active_side;
+        let filter = &mut self.pane_mut(side).filter;
+        filter.ignored = filter.ignored.next();
+        self.refresh_pane(side);
+    }
 
-//    let lfmr =
-//        std::rc::Rc::new(std::cell::RefCell::new(
-//            wlambda::compiler::LocalFileModuleResolver::new()));
-//    genv.borrow_mut().set_resolver(lfmr);
-    let mut wl_eval_ctx = EvalContext::new_default();
+    /// Handles events while a query is active. Returns true if the
+    /// event was consumed by the query.
+    fn handle_query_event(&mut self, event: &Event) -> bool {
+        if self.query.is_none() {
+            return false;
+        }
 
-    match wl_eval_ctx.eval_file("main.wl") {
-        Ok(v) => {
-            if v.is_err() {
-                panic!(format!("'main.wl' SCRIPT ERROR: {}", v.s()));
-            }
+        match event {
+            Event::TextInput { text, .. } => {
+                if self.query_skip_text {
+                    self.query_skip_text = false;
+                } else {
+                    self.input_line.handle_input(
+                        TextInputAction::Insert(text.to_string()));
+                    if let Some(QueryKind::SetMark) | Some(QueryKind::JumpMark) = self.query {
+                        self.submit_query();
+                    } else {
+                        self.query_changed();
+                    }
+                }
+                true
+            },
+            Event::KeyDown { keycode: Some(kc), keymod, .. } => {
+                let live = self.is_live_query();
+                let shift =
+                    keymod.intersects(sdl2::keyboard::Mod::LSHIFTMOD | sdl2::keyboard::Mod::RSHIFTMOD);
+                match *kc {
+                    Keycode::Return | Keycode::KpEnter
+                        if shift && self.query == Some(QueryKind::Fuzzy) => {
+                        self.open_fuzzy_match();
+                    },
+                    Keycode::Return | Keycode::KpEnter => self.submit_query(),
+                    Keycode::Escape    => {
+                        self.end_query();
+                        if live {
+                            self.process_page_control(PageControl::Back, None);
+                        }
+                    },
+                    Keycode::Up   if live => self.process_page_control(PageControl::CursorUp, None),
+                    Keycode::Down if live => self.process_page_control(PageControl::CursorDown, None),
+                    Keycode::Backspace => {
+                        self.input_line.handle_input(TextInputAction::Backspace);
+                        self.query_changed();
+                    },
+                    Keycode::Left      => self.input_line.handle_input(TextInputAction::CursorLeft),
+                    Keycode::Right     => self.input_line.handle_input(TextInputAction::CursorRight),
+                    Keycode::Home      => self.input_line.handle_input(TextInputAction::CursorBegin),
+                    Keycode::End       => self.input_line.handle_input(TextInputAction::CursorEnd),
+                    _ => (),
+                }
+                true
+            },
+            Event::KeyUp { .. } => true,
+            _ => false,
+        }
+    }
 
-            let cb_input =
-                    wl_eval_ctx
-                    .get_global_var("on_input")
-                    .expect("'on_input' global callback in main.wl");
-            let cb_text =
-                    wl_eval_ctx
-                    .get_global_var("on_text")
-                    .expect("'on_text' global callback in main.wl");
-            WLCallbacks {
-                ctx:        Rc::new(RefCell::new(wl_eval_ctx)),
-                fm_api:     VVal::None,
-                cb_input,
-                cb_text,
-            }
-        },
-        Err(e) => { panic!(format!("'main.wl' SCRIPT ERROR: {}", e)); }
+    fn is_live_query(&self) -> bool {
+        match self.query {
+            Some(QueryKind::Jump) | Some(QueryKind::Fuzzy) => true,
+            _ => false,
+        }
     }
-}
 
-impl FileManager {
-    fn open_path_in(&mut self, path: &std::path::Path, pos: PanePos) {
-        let mut ps = PathSheet::read(path).expect("No broken paths please");
-        ps.sort_by_column(0);
-        match pos {
-            PanePos::LeftTab  => self.left.push(ps),
-            PanePos::RightTab => self.right.push(ps),
+    /// Passes the text of a live query to the active page after every
+    /// change, like to the jump page.
+    fn query_changed(&mut self) {
+        if self.is_live_query() {
+            let (_, _, text) = self.input_line.get_line_info();
+            let text = text.to_string();
+            self.process_page_control(PageControl::Search(text), None);
         }
     }
 
     fn toggle_active_side(&mut self) {
-        self.active_side =
-            match self.active_side {
-                FileManagerSide::Left => FileManagerSide::Right,
-                FileManagerSide::Right => FileManagerSide::Left,
-            };
+        self.active_side = self.other_side();
+
+        if let Some(qv) = self.quick_view.as_mut() {
+            qv.reset();
+            self.left.preview  = None;
+            self.right.preview = None;
+        }
+    }
+
+    fn other_side(&self) -> FileManagerSide {
+        match self.active_side {
+            FileManagerSide::Left => FileManagerSide::Right,
+            FileManagerSide::Right => FileManagerSide::Left,
+        }
+    }
+
+    /// Switches the quick view mode, in which the inactive pane shows a
+    /// preview of the entry under the cursor of the active pane.
+    fn toggle_quick_view(&mut self) {
+        if self.quick_view.is_some() {
+            self.quick_view    = None;
+            self.left.preview  = None;
+            self.right.preview = None;
+        } else {
+            self.quick_view = Some(QuickView::new());
+        }
+    }
+
+    fn update_quick_view(&mut self) -> bool {
+        if self.quick_view.is_none() {
+            return false;
+        }
+
+        let side   = self.active_side;
+        let cursor = self.pane_mut(side).active_page().and_then(|p| p.cursor_path());
+
+        let qv = self.quick_view.as_mut().unwrap();
+        qv.follow(cursor);
+        let preview = match qv.poll() {
+            Some(preview) => preview,
+            None          => return false,
+        };
+
+        let other = self.other_side();
+        match preview {
+            Ok(page) => { self.pane_mut(other).preview = Some(page); },
+            Err(e) => {
+                self.pane_mut(other).preview = None;
+                self.log.append_msg(format!("Quick view failed: {}", e));
+            },
+        }
+        true
     }
 
     fn action(&mut self, fmact: FileManagerAction) {
@@ -165,21 +1665,81 @@ impl FileManager {
             FileManagerAction::TextInput(txtact) => {
                 self.input_line.handle_input(txtact);
             },
+            FileManagerAction::AddAssociation(matcher, command, name) => {
+                self.assoc.add(matcher, &command, &name);
+            },
+            FileManagerAction::SetEditor(command, in_terminal) => {
+                self.editor.command     = command;
+                self.editor.in_terminal = in_terminal;
+            },
+            FileManagerAction::SetTerminal(command) => {
+                self.editor.terminal = command;
+            },
+            FileManagerAction::Panelize(title, paths) => {
+                let paths : Vec<std::path::PathBuf> =
+                    paths.iter().map(std::path::PathBuf::from).collect();
+                self.panelize(&title, &paths);
+            },
+            FileManagerAction::SetBookmark(name, path) => {
+                self.set_bookmark(&name, std::path::Path::new(&path));
+            },
+            FileManagerAction::RemoveBookmark(name) => {
+                self.remove_bookmark(&name);
+            },
+            FileManagerAction::OpenBookmark(name) => {
+                self.open_bookmark(&name);
+            },
+            FileManagerAction::SetShowHidden(show) => {
+                self.left.filter.show_hidden  = show;
+                self.right.filter.show_hidden = show;
+                self.refresh_tabs();
+            },
+            FileManagerAction::SetShowGitStatus(show) => {
+                self.left.filter.git_status  = show;
+                self.right.filter.git_status = show;
+                self.refresh_tabs();
+            },
+            FileManagerAction::SetIgnoredMode(mode) => {
+                match IgnoredMode::parse(&mode) {
+                    Some(mode) => {
+                        self.left.filter.ignored  = mode;
+                        self.right.filter.ignored = mode;
+                        self.refresh_tabs();
+                    },
+                    None => {
+                        self.log.append_msg(format!(
+                            "Unknown ignored mode '{}', use show, dim or hide", mode));
+                    },
+                }
+            },
+            FileManagerAction::SetGitignore(on) => {
+                self.left.filter.gitignore  = on;
+                self.right.filter.gitignore = on;
+                self.refresh_tabs();
+            },
+            FileManagerAction::IgnoreGlob(glob) => {
+                self.left.filter.globs.push(glob.clone());
+                self.right.filter.globs.push(glob);
+                self.refresh_tabs();
+            },
+            FileManagerAction::SetColor(glob, codes) => {
+                self.ls_colors.set_override(&glob, TextAttrs::parse_sgr(&codes));
+                self.refresh_tabs();
+            },
         }
     }
 
     fn process_page_control(&mut self, ctrl: PageControl, mouse: Option<(i32, i32)>) {
         if let Some((x, y)) = mouse {
-            if !self.left.is_empty() {
-                let fm_page : &mut dyn FmPage = self.left.get_mut(0).unwrap();
-                if fm_page.is_inside_screen_rect(x, y) {
-                    fm_page.do_control(ctrl);
+            for side in [FileManagerSide::Left, FileManagerSide::Right].iter() {
+                let mut event = None;
+                if let Some(fm_page) = self.pane_mut(*side).active_page() {
+                    if fm_page.is_inside_screen_rect(x, y) {
+                        event = fm_page.do_control(ctrl.clone());
+                    }
                 }
-            }
-            if !self.right.is_empty() {
-                let fm_page : &mut dyn FmPage = self.right.get_mut(0).unwrap();
-                if fm_page.is_inside_screen_rect(x, y) {
-                    fm_page.do_control(ctrl);
+                if let Some(ev) = event {
+                    self.handle_page_event(*side, ev);
                 }
             }
             let fm_page : &mut dyn FmPage = &mut self.log;
@@ -188,27 +1748,24 @@ impl FileManager {
             }
 
         } else {
-            match self.active_side {
-                FileManagerSide::Left => {
-                    if self.left.is_empty() { return; }
-                    self.left.get_mut(0).unwrap().do_control(ctrl);
-                },
-                FileManagerSide::Right => {
-                    if self.right.is_empty() { return; }
-                    self.right.get_mut(0).unwrap().do_control(ctrl);
-                },
-            };
+            let side = self.active_side;
+            let event =
+                match self.pane_mut(side).active_page() {
+                    Some(fm_page) => fm_page.do_control(ctrl),
+                    None          => return,
+                };
+            if let Some(ev) = event {
+                self.handle_page_event(side, ev);
+            }
         }
     }
 
     fn handle_resize(&mut self) {
-        if !self.left.is_empty() {
-            let fm_page : &mut dyn FmPage = self.left.get_mut(0).unwrap();
+        if let Some(fm_page) = self.left.active_page() {
             fm_page.do_control(PageControl::Refresh);
         }
 
-        if !self.right.is_empty() {
-            let fm_page : &mut dyn FmPage = self.right.get_mut(0).unwrap();
+        if let Some(fm_page) = self.right.active_page() {
             fm_page.do_control(PageControl::Refresh);
         }
 
@@ -224,11 +1781,11 @@ impl FileManager {
         let tab_height = win_size.1 - log_height - input_height;
         let log_offs_y = tab_height as i32;
 
-        if !self.left.is_empty() {
-            let fm_page : &mut dyn FmPage = self.left.get_mut(0).unwrap();
-            draw_fm_page(fm_page, gui_painter,
+        let is_left_active = self.active_side == FileManagerSide::Left;
+        if let Some(fm_page) = self.left.active_page() {
+            draw_fm_page(fm_page, gui_painter, &self.ls_colors,
                 0, 0, half_width, tab_height,
-                self.active_side == FileManagerSide::Left);
+                is_left_active);
         }
 
         gui_painter.canvas.set_draw_color(DIVIDER_COLOR);
@@ -237,15 +1794,15 @@ impl FileManager {
             Point::new(half_width as i32, win_size.1 as i32))
             .expect("drawing a line");
 
-        if !self.right.is_empty() {
-            let fm_page : &mut dyn FmPage = self.right.get_mut(0).unwrap();
-            draw_fm_page(fm_page, gui_painter,
+        let is_right_active = self.active_side == FileManagerSide::Right;
+        if let Some(fm_page) = self.right.active_page() {
+            draw_fm_page(fm_page, gui_painter, &self.ls_colors,
                 half_width as i32, 0, half_width, tab_height,
-                self.active_side == FileManagerSide::Right);
+                is_right_active);
         }
 
         let fm_page : &mut dyn FmPage = &mut self.log;
-        draw_fm_page(fm_page, gui_painter,
+        draw_fm_page(fm_page, gui_painter, &self.ls_colors,
             0, log_offs_y, win_size.0, log_height,
             true);
 
@@ -293,6 +1850,41 @@ impl<'a, 'b> GUIPainter<'a, 'b> {
         self.canvas.present();
     }
 
+    /// Draws the surface centered into `area`, either scaled to fit or by
+    /// a fixed factor. Parts outside of `area` are clipped.
+    fn draw_image(&mut self, surface: &sdl2::surface::Surface, scale: ImageScale, area: Rect) {
+        let (sw, sh) = surface.size();
+        if sw == 0 || sh == 0 {
+            return;
+        }
+
+        let factor =
+            match scale {
+                ImageScale::Fit => {
+                    let fx = area.width()  as f32 / sw as f32;
+                    let fy = area.height() as f32 / sh as f32;
+                    fx.min(fy)
+                },
+                ImageScale::Factor(f) => f,
+            };
+
+        let dw = ((sw as f32 * factor) as u32).max(1);
+        let dh = ((sh as f32 * factor) as u32).max(1);
+        let dx = area.x() + (area.width()  as i32 - dw as i32) / 2;
+        let dy = area.y() + (area.height() as i32 - dh as i32) / 2;
+
+        let txt_crt = self.canvas.texture_creator();
+        let txt = match txt_crt.create_texture_from_surface(surface) {
+            Ok(txt) => txt,
+            Err(_)  => return,
+        };
+
+        self.canvas.set_clip_rect(Some(area));
+        self.canvas.copy(&txt, None, Some(Rect::new(dx, dy, dw, dh)))
+            .map_err(|e| e.to_string()).unwrap();
+        self.canvas.set_clip_rect(None);
+    }
+
     fn calc_column_text_widths(&mut self, table: &mut Table) {
         for col in table.columns.iter_mut() {
             if let ColumnSizing::TextWidth(txt) = &col.size {
@@ -345,6 +1937,7 @@ impl<'a, 'b> GUIPainter<'a, 'b> {
     }
 
     fn draw_table_row(&mut self, row: &StyleString,
+                      colors: &LsColors,
                       col_idx: i32,
                       row_idx: usize,
                       has_focus: bool,
@@ -356,10 +1949,23 @@ impl<'a, 'b> GUIPainter<'a, 'b> {
                       col_gap: i32,
                       row_height: i32) {
 
+        // Only the entry names of listings follow LS_COLORS:
+        let attrs =
+            match row.style {
+                Style::Entry(kind) => colors.attrs(kind, &row.text),
+                _                  => TextAttrs::default(),
+            };
         let mut fg_color = match row.style {
-            Style::Dir     => DIR_FG_COLOR,
-            Style::Special => LNK_FG_COLOR,
-            _              => NORM_FG_COLOR,
+            Style::Entry(_)  => attrs.fg.unwrap_or(NORM_FG_COLOR),
+            Style::Dir       => DIR_FG_COLOR,
+            Style::Special   => LNK_FG_COLOR,
+            Style::Ignored   => IGN_FG_COLOR,
+            Style::Added     => ADD_FG_COLOR,
+            Style::Removed   => DEL_FG_COLOR,
+            Style::Changed   => CHG_FG_COLOR,
+            Style::Success   => ADD_FG_COLOR,
+            Style::Error     => DEL_FG_COLOR,
+            _                => NORM_FG_COLOR,
         };
 
         let mut bg_color = if row_idx % 2 == 0 {
@@ -396,17 +2002,47 @@ impl<'a, 'b> GUIPainter<'a, 'b> {
         self.canvas.set_draw_color(bg_color);
         self.canvas.fill_rect(Rect::new(x, y, width as u32, row_height as u32))
             .expect("filling rectangle");
-        draw_bg_text(
-            &mut self.canvas,
-            &mut self.font.borrow_mut(),
-            fg_color, bg_color,
-            x, y, width - col_gap, row_height,
-            &row.text);
+
+        let mut font_style = sdl2::ttf::FontStyle::NORMAL;
+        if attrs.bold      { font_style |= sdl2::ttf::FontStyle::BOLD; }
+        if attrs.italic    { font_style |= sdl2::ttf::FontStyle::ITALIC; }
+        if attrs.underline { font_style |= sdl2::ttf::FontStyle::UNDERLINE; }
+        self.font.borrow_mut().set_style(font_style);
+
+        if let Style::Bar(frac) = row.style {
+            let bar_w = ((width - col_gap) as f32 * frac.max(0.0).min(1.0)) as u32;
+            if !specially_marked_row && bar_w > 0 {
+                self.canvas.set_draw_color(BAR_BG_COLOR);
+                self.canvas.fill_rect(Rect::new(x, y, bar_w, row_height as u32))
+                    .expect("filling rectangle");
+            }
+            draw_text(
+                &mut self.font.borrow_mut(), fg_color, &mut self.canvas,
+                x, y, width - col_gap, &row.text);
+
+        } else if row.highlight.is_empty() {
+            draw_bg_text(
+                &mut self.canvas,
+                &mut self.font.borrow_mut(),
+                fg_color, bg_color,
+                x, y, width - col_gap, row_height,
+                &row.text);
+        } else {
+            draw_highlighted_text(
+                &mut self.canvas,
+                &mut self.font.borrow_mut(),
+                fg_color, bg_color,
+                x, y, width - col_gap, row_height,
+                &row.text, &row.highlight);
+        }
+
+        self.font.borrow_mut().set_style(sdl2::ttf::FontStyle::NORMAL);
     }
 
     fn draw_table(
         &mut self,
         fm_page: &mut dyn FmPage,
+        colors: &LsColors,
         x_offs: i32,
         y_offs: i32,
         table_width: i32,
@@ -459,7 +2095,7 @@ impl<'a, 'b> GUIPainter<'a, 'b> {
                                     .skip(fm_page.get_scroll_offs())
                                     .take(row_count) {
                 self.draw_table_row(
-                    row, col_idx as i32, row_idx, has_focus, is_active,
+                    row, colors, col_idx as i32, row_idx, has_focus, is_active,
                     fm_page,
                     x, y,
                     *width, table_ref.col_gap as i32, row_height);
@@ -540,6 +2176,74 @@ fn draw_bg_text(canvas: &mut sdl2::render::Canvas<sdl2::video::Window>,
     draw_text(font, color, canvas, x, y, max_w, txt)
 }
 
+/// Draws `txt` with the character ranges in `highlight` in the
+/// highlight colors.
+fn draw_highlighted_text(
+    canvas: &mut sdl2::render::Canvas<sdl2::video::Window>,
+    font: &mut sdl2::ttf::Font,
+    color: Color,
+    bg_color: Color,
+    x: i32,
+    y: i32,
+    max_w: i32,
+    h: i32,
+    txt: &str,
+    highlight: &[(usize, usize)]) -> (u32, u32) {
+
+    canvas.set_draw_color(bg_color);
+    canvas.fill_rect(Rect::new(x, y, max_w as u32, h as u32))
+        .expect("filling rectangle");
+
+    let chars : Vec<char> = txt.chars().collect();
+    let mut segments : Vec<(String, bool)> = vec![];
+    let mut pos = 0;
+    for (start, end) in highlight.iter() {
+        let start = (*start).min(chars.len()).max(pos);
+        let end   = (*end).min(chars.len());
+        if start > pos {
+            segments.push((chars[pos..start].iter().collect(), false));
+        }
+        if end > start {
+            segments.push((chars[start..end].iter().collect(), true));
+            pos = end;
+        }
+    }
+    if pos < chars.len() {
+        segments.push((chars[pos..].iter().collect(), false));
+    }
+
+    let mut xs = x;
+    let mut max_h = 0;
+    for (seg, is_high) in segments.iter() {
+        let rest_w = max_w - (xs - x);
+        if rest_w <= 0 { break; }
+
+        let (w, th) =
+            if *is_high {
+                with_text2texture(font, canvas, HIGH_FG_COLOR, seg, |canvas, t| {
+                    let tq = t.query();
+                    let w : i32 = if rest_w < (tq.width as i32) { rest_w } else { tq.width as i32 };
+                    canvas.set_draw_color(HIGH_BG_COLOR);
+                    canvas.fill_rect(Rect::new(xs, y, w as u32, h as u32))
+                        .expect("filling rectangle");
+                    canvas.copy(
+                        &t,
+                        Some(Rect::new(0, 0, w as u32, tq.height)),
+                        Some(Rect::new(xs, y, w as u32, tq.height))
+                    ).map_err(|e| e.to_string()).unwrap();
+                    (w as u32, tq.height)
+                })
+            } else {
+                draw_text(font, color, canvas, xs, y, rest_w, seg)
+            };
+
+        xs += w as i32;
+        if th > max_h { max_h = th; }
+    }
+
+    ((xs - x) as u32, max_h)
+}
+
 fn draw_bg_text_cursor(
     canvas: &mut sdl2::render::Canvas<sdl2::video::Window>,
     font: &mut sdl2::ttf::Font,
@@ -695,12 +2399,23 @@ pub fn main() -> Result<(), String> {
 
     let fm = FileManager {
         active_side:        FileManagerSide::Left,
-        left:               Vec::new(),
-        right:              Vec::new(),
+        left:               Pane::new(),
+        right:              Pane::new(),
         log:                LogSheet::new(),
         input_line:         TextInputLine::new(),
         prompt:             String::from("[NORMAL]"),
         show_input_line:    false,
+        query:              None,
+        query_skip_text:    false,
+        saved_prompt:       (String::from(""), false),
+        quick_view:         None,
+        assoc:              Associations::new(),
+        jobs:               vec![],
+        file_ops:           vec![],
+        editor:             EditorConfig::from_env(),
+        edits:              vec![],
+        frecency:           FrecencyDb::load().unwrap_or_default(),
+        ls_colors:          LsColors::from_env(),
     };
 
     let fm = Rc::new(RefCell::new(fm));
@@ -734,6 +2449,124 @@ pub fn main() -> Result<(), String> {
         Ok(VVal::None)
     });
 
+    set_vval_method!(fm_api, fm_actions, assoc_glob, Some(2), Some(3), env, _argc, {
+        fm_actions.borrow_mut().push(
+            FileManagerAction::AddAssociation(
+                AssocMatch::Glob(env.arg(0).s_raw()),
+                env.arg(1).s_raw(),
+                env.arg(2).s_raw()));
+        Ok(VVal::None)
+    });
+
+    set_vval_method!(fm_api, fm_actions, assoc_mime, Some(2), Some(3), env, _argc, {
+        fm_actions.borrow_mut().push(
+            FileManagerAction::AddAssociation(
+                AssocMatch::Mime(env.arg(0).s_raw()),
+                env.arg(1).s_raw(),
+                env.arg(2).s_raw()));
+        Ok(VVal::None)
+    });
+
+    set_vval_method!(fm_api, fm_actions, set_editor, Some(2), Some(2), env, _argc, {
+        fm_actions.borrow_mut().push(
+            FileManagerAction::SetEditor(
+                env.arg(0).s_raw(),
+                env.arg(1).b()));
+        Ok(VVal::None)
+    });
+
+    set_vval_method!(fm_api, fm_actions, set_terminal, Some(1), Some(1), env, _argc, {
+        fm_actions.borrow_mut().push(
+            FileManagerAction::SetTerminal(env.arg(0).s_raw()));
+        Ok(VVal::None)
+    });
+
+    set_vval_method!(fm_api, fm_actions, panelize, Some(2), Some(2), env, _argc, {
+        let list = env.arg(1);
+        fm_actions.borrow_mut().push(
+            FileManagerAction::Panelize(
+                env.arg(0).s_raw(),
+                (0..list.len()).map(|i| list.v_s_raw(i)).collect()));
+        Ok(VVal::None)
+    });
+
+    set_vval_method!(fm_api, fm_actions, bookmark, Some(2), Some(2), env, _argc, {
+        fm_actions.borrow_mut().push(
+            FileManagerAction::SetBookmark(
+                env.arg(0).s_raw(),
+                env.arg(1).s_raw()));
+        Ok(VVal::None)
+    });
+
+    set_vval_method!(fm_api, fm_actions, bookmark_remove, Some(1), Some(1), env, _argc, {
+        fm_actions.borrow_mut().push(
+            FileManagerAction::RemoveBookmark(env.arg(0).s_raw()));
+        Ok(VVal::None)
+    });
+
+    set_vval_method!(fm_api, fm_actions, bookmark_open, Some(1), Some(1), env, _argc, {
+        fm_actions.borrow_mut().push(
+            FileManagerAction::OpenBookmark(env.arg(0).s_raw()));
+        Ok(VVal::None)
+    });
+
+    set_vval_method!(fm_api, fm_actions, show_hidden, Some(1), Some(1), env, _argc, {
+        fm_actions.borrow_mut().push(
+            FileManagerAction::SetShowHidden(env.arg(0).b()));
+        Ok(VVal::None)
+    });
+
+    set_vval_method!(fm_api, fm_actions, show_git_status, Some(1), Some(1), env, _argc, {
+        fm_actions.borrow_mut().push(
+            FileManagerAction::SetShowGitStatus(env.arg(0).b()));
+        Ok(VVal::None)
+    });
+
+    // "show", "dim" or "hide" the entries matched by the ignore rules:
+    set_vval_method!(fm_api, fm_actions, set_ignored_mode, Some(1), Some(1), env, _argc, {
+        fm_actions.borrow_mut().push(
+            FileManagerAction::SetIgnoredMode(env.arg(0).s_raw()));
+        Ok(VVal::None)
+    });
+
+    set_vval_method!(fm_api, fm_actions, use_gitignore, Some(1), Some(1), env, _argc, {
+        fm_actions.borrow_mut().push(
+            FileManagerAction::SetGitignore(env.arg(0).b()));
+        Ok(VVal::None)
+    });
+
+    set_vval_method!(fm_api, fm_actions, ignore_glob, Some(1), Some(1), env, _argc, {
+        fm_actions.borrow_mut().push(
+            FileManagerAction::IgnoreGlob(env.arg(0).s_raw()));
+        Ok(VVal::None)
+    });
+
+    // Colors the names matching the glob, with LS_COLORS codes like "01;32":
+    set_vval_method!(fm_api, fm_actions, set_color, Some(2), Some(2), env, _argc, {
+        fm_actions.borrow_mut().push(
+            FileManagerAction::SetColor(
+                env.arg(0).s_raw(),
+                env.arg(1).s_raw()));
+        Ok(VVal::None)
+    });
+
+    // Returns the bookmarks as a list of [name, directory] pairs:
+    set_vval_method!(fm_api, fm_actions, bookmarks, Some(0), Some(0), _env, _argc, {
+        let list = VVal::vec();
+        for (name, path) in Bookmarks::load().map(|bm| bm.list).unwrap_or_default() {
+            let pair = VVal::vec();
+            pair.push(VVal::new_str_mv(name));
+            pair.push(VVal::new_str_mv(path.to_string_lossy().to_string()));
+            list.push(pair);
+        }
+        Ok(list)
+    });
+
+    wlcbs.on_init();
+    for act in fm_actions.borrow_mut().drain(..) {
+        fm.borrow_mut().action(act);
+    }
+
     fm.borrow_mut().log.append_msg(String::from("FOo bar foiwe jfowi fewoi fewoif jewof weof iewjo jfewo iwejf oiwejfo iwejf owiejf oweifj weoi fjweoi w 1"));
     fm.borrow_mut().log.append_msg(String::from("FOo bar foiwe jfowi fewoi fewoif jewof weof iewjo jfewo iwejf oiwejfo iwejf owiejf oweifj weoi fjweoi w 2"));
     fm.borrow_mut().log.append_msg(String::from("FOo bar foiwe jfowi fewoi fewoif jewof weof iewjo jfewo iwejf oiwejfo iwejf owiejf oweifj weoi fjweoi w 3"));
@@ -754,21 +2587,26 @@ pub fn main() -> Result<(), String> {
     fm.borrow_mut().log.append_msg(String::from("FOo bar foiwe jfowi fewoi fewoif jewof weof iewjo jfewo iwejf oiwejfo iwejf owiejf oweifj weoi fjweoi w 18"));
     fm.borrow_mut().log.append_msg(String::from("FOo bar foiwe jfowi fewoi fewoif jewof weof iewjo jfewo iwejf oiwejfo iwejf owiejf oweifj weoi fjweoi w 19"));
 
-    let pth = std::path::Path::new(".");
-    fm.borrow_mut().open_path_in(pth, PanePos::LeftTab);
-    let pth = std::path::Path::new("..");
-    fm.borrow_mut().open_path_in(pth, PanePos::RightTab);
+    // Absolute, so going up from them works:
+    let pth = std::fs::canonicalize(".").unwrap_or_else(|_| std::path::PathBuf::from("."));
+    fm.borrow_mut().open_path_in(&pth, PanePos::LeftTab);
+    let pth = std::fs::canonicalize("..").unwrap_or_else(|_| std::path::PathBuf::from(".."));
+    fm.borrow_mut().open_path_in(&pth, PanePos::RightTab);
 
     let mut last_frame = Instant::now();
     let mut is_first = true;
     'running: loop {
         let mut force_redraw = false;
-        let event = event_pump.wait_event_timeout(1000);
+        let timeout = if fm.borrow().is_busy() { 16 } else { 1000 };
+        let event = event_pump.wait_event_timeout(timeout);
         let mouse_state = event_pump.mouse_state();
+        let had_event = event.is_some();
         if let Some(event) = event {
             let mut fm = fm.borrow_mut();
             println!("EV: {:?}", event);
+            let query_event = fm.handle_query_event(&event);
             match &event {
+                _ if query_event => {},
                 Event::KeyDown { keycode, keymod, .. } => {
                     let keystr = sdl2keydown2str(&event);
                     println!("STR KEY: '{}'", keystr);
@@ -780,9 +2618,7 @@ pub fn main() -> Result<(), String> {
                 _ => {},
             }
 
-            let mut vecref = fm_actions.borrow_mut();
-            while !vecref.is_empty() {
-                let act = vecref.pop().expect("something in the action vec");
+            for act in fm_actions.borrow_mut().drain(..) {
                 fm.action(act);
             }
 
@@ -790,9 +2626,19 @@ pub fn main() -> Result<(), String> {
                 Event::Quit {..} => {
                     break 'running
                 },
+                _ if query_event => {},
+                Event::KeyDown { keycode: Some(Keycode::Tab), keymod, .. }
+                    if keymod.intersects(sdl2::keyboard::Mod::LCTRLMOD | sdl2::keyboard::Mod::RCTRLMOD) => {
+                    let side = fm.active_side;
+                    fm.pane_mut(side).cycle_tabs();
+                },
                 Event::KeyDown { keycode: Some(Keycode::Tab), .. } => {
                     fm.toggle_active_side();
                 },
+                Event::KeyDown { keycode: Some(Keycode::H), keymod, .. }
+                    if keymod.intersects(sdl2::keyboard::Mod::LSHIFTMOD | sdl2::keyboard::Mod::RSHIFTMOD) => {
+                    fm.step_history(-1);
+                },
                 Event::KeyDown { keycode: Some(Keycode::H), .. } => {
                     fm.process_page_control(PageControl::Back, None);
                 },
@@ -802,9 +2648,147 @@ pub fn main() -> Result<(), String> {
                 Event::KeyDown { keycode: Some(Keycode::K), .. } => {
                     fm.process_page_control(PageControl::CursorUp, None);
                 },
+                Event::KeyDown { keycode: Some(Keycode::L), keymod, .. }
+                    if keymod.intersects(sdl2::keyboard::Mod::LSHIFTMOD | sdl2::keyboard::Mod::RSHIFTMOD) => {
+                    fm.step_history(1);
+                },
+                Event::KeyDown { keycode: Some(Keycode::Z), .. } => {
+                    fm.start_jump();
+                },
+                Event::KeyDown { keycode: Some(Keycode::R), .. } => {
+                    fm.show_history();
+                },
                 Event::KeyDown { keycode: Some(Keycode::L), .. } => {
                     fm.process_page_control(PageControl::Access, None);
                 },
+                Event::KeyDown { keycode: Some(Keycode::F3), .. } => {
+                    fm.view_cursor_entry();
+                },
+                Event::KeyDown { keycode: Some(Keycode::Slash), .. } => {
+                    fm.start_query(QueryKind::Search, "/", "", true);
+                },
+                Event::KeyDown { keycode: Some(Keycode::Q), .. } => {
+                    fm.toggle_quick_view();
+                },
+                Event::KeyDown { keycode: Some(Keycode::P), keymod, .. }
+                    if keymod.intersects(sdl2::keyboard::Mod::LCTRLMOD | sdl2::keyboard::Mod::RCTRLMOD) => {
+                    fm.start_fuzzy();
+                },
+                Event::KeyDown { keycode: Some(Keycode::P), .. } => {
+                    fm.show_properties();
+                },
+                Event::KeyDown { keycode: Some(Keycode::G), keymod, .. }
+                    if keymod.intersects(sdl2::keyboard::Mod::LSHIFTMOD | sdl2::keyboard::Mod::RSHIFTMOD) => {
+                    fm.toggle_git_status();
+                },
+                Event::KeyDown { keycode: Some(Keycode::G), .. } => {
+                    fm.start_query(QueryKind::Goto, "goto: ", "", true);
+                },
+                Event::KeyDown { keycode: Some(Keycode::N), .. } => {
+                    fm.process_page_control(PageControl::SearchNext, None);
+                },
+                Event::KeyDown { keycode: Some(Keycode::W), keymod, .. }
+                    if keymod.intersects(sdl2::keyboard::Mod::LCTRLMOD | sdl2::keyboard::Mod::RCTRLMOD) => {
+                    let side = fm.active_side;
+                    fm.pane_mut(side).close_tab();
+                },
+                Event::KeyDown { keycode: Some(Keycode::W), .. } => {
+                    fm.process_page_control(PageControl::CycleMode, None);
+                },
+                Event::KeyDown { keycode: Some(Keycode::Space), .. }
+                | Event::KeyDown { keycode: Some(Keycode::Insert), .. } => {
+                    fm.process_page_control(PageControl::ToggleSelect, None);
+                },
+                Event::KeyDown { keycode: Some(Keycode::F4), keymod, .. }
+                    if keymod.intersects(sdl2::keyboard::Mod::LSHIFTMOD | sdl2::keyboard::Mod::RSHIFTMOD) => {
+                    fm.start_query(QueryKind::NewFile, "edit new file: ", "", false);
+                },
+                Event::KeyDown { keycode: Some(Keycode::F4), .. } => {
+                    fm.edit_cursor_entry();
+                },
+                Event::KeyDown { keycode: Some(Keycode::F), .. } => {
+                    fm.start_query(QueryKind::Find, "find: ", "", true);
+                },
+                Event::KeyDown { keycode: Some(Keycode::S), .. } => {
+                    fm.start_query(QueryKind::Grep, "grep: ", "", true);
+                },
+                Event::KeyDown { keycode: Some(Keycode::C), keymod, .. }
+                    if keymod.intersects(sdl2::keyboard::Mod::LSHIFTMOD | sdl2::keyboard::Mod::RSHIFTMOD) => {
+                    fm.compare_files();
+                },
+                Event::KeyDown { keycode: Some(Keycode::C), .. } => {
+                    fm.copy_batch();
+                },
+                Event::KeyDown { keycode: Some(Keycode::D), keymod, .. }
+                    if keymod.intersects(sdl2::keyboard::Mod::LSHIFTMOD | sdl2::keyboard::Mod::RSHIFTMOD) => {
+                    fm.start_trash();
+                },
+                Event::KeyDown { keycode: Some(Keycode::D), .. } => {
+                    fm.start_delete();
+                },
+                Event::KeyDown { keycode: Some(Keycode::B), .. } => {
+                    fm.start_query(QueryKind::Checksum,
+                        "checksum (md5, sha1, sha256, blake3): ", "sha256", true);
+                },
+                Event::KeyDown { keycode: Some(Keycode::Y), .. } => {
+                    fm.show_dupes();
+                },
+                Event::KeyDown { keycode: Some(Keycode::X), keymod, .. }
+                    if keymod.intersects(sdl2::keyboard::Mod::LSHIFTMOD | sdl2::keyboard::Mod::RSHIFTMOD) => {
+                    fm.start_query(QueryKind::Panelize, "panelize $ ", "", true);
+                },
+                Event::KeyDown { keycode: Some(Keycode::X), .. } => {
+                    fm.start_query(QueryKind::Shell, "$ ", "", true);
+                },
+                Event::KeyDown { keycode: Some(Keycode::V), .. } => {
+                    fm.panelize_page();
+                },
+                Event::KeyDown { keycode: Some(Keycode::E), .. } => {
+                    fm.show_tree();
+                },
+                Event::KeyDown { keycode: Some(Keycode::U), keymod, .. }
+                    if keymod.intersects(sdl2::keyboard::Mod::LSHIFTMOD | sdl2::keyboard::Mod::RSHIFTMOD) => {
+                    fm.show_drives();
+                },
+                Event::KeyDown { keycode: Some(Keycode::U), .. } => {
+                    fm.show_disk_usage();
+                },
+                Event::KeyDown { keycode: Some(Keycode::Equals), .. } => {
+                    fm.sync_other_pane();
+                },
+                Event::KeyDown { keycode: Some(Keycode::A), .. } => {
+                    fm.show_open_with();
+                },
+                Event::KeyDown { keycode: Some(Keycode::M), keymod, .. }
+                    if keymod.intersects(sdl2::keyboard::Mod::LSHIFTMOD | sdl2::keyboard::Mod::RSHIFTMOD) => {
+                    fm.start_query(QueryKind::SetMark, "mark: ", "", true);
+                },
+                Event::KeyDown { keycode: Some(Keycode::Quote), keymod, .. }
+                    if keymod.intersects(sdl2::keyboard::Mod::LSHIFTMOD | sdl2::keyboard::Mod::RSHIFTMOD) => {
+                    fm.show_bookmarks();
+                },
+                Event::KeyDown { keycode: Some(Keycode::Quote), .. } => {
+                    fm.start_query(QueryKind::JumpMark, "jump to mark: ", "", true);
+                },
+                Event::KeyDown { keycode: Some(Keycode::Escape), .. } => {
+                    fm.cancel_jobs();
+                },
+                Event::KeyDown { keycode: Some(Keycode::Period), keymod, .. }
+                    if keymod.intersects(sdl2::keyboard::Mod::LSHIFTMOD | sdl2::keyboard::Mod::RSHIFTMOD) => {
+                    fm.cycle_ignored_mode();
+                },
+                Event::KeyDown { keycode: Some(Keycode::Period), .. } => {
+                    fm.toggle_hidden();
+                },
+                Event::KeyDown { keycode: Some(Keycode::M), .. } => {
+                    fm.start_attribute_query(QueryKind::Chmod);
+                },
+                Event::KeyDown { keycode: Some(Keycode::O), .. } => {
+                    fm.start_attribute_query(QueryKind::Chown);
+                },
+                Event::KeyDown { keycode: Some(Keycode::T), .. } => {
+                    fm.start_attribute_query(QueryKind::Touch);
+                },
                 Event::MouseButtonDown { x, y, .. } => {
                     fm.process_page_control(PageControl::Click((x, y)), Some((x, y)));
                 },
@@ -846,20 +2830,27 @@ pub fn main() -> Result<(), String> {
                 },
                 _ => {}
             }
+        }
 
-            let frame_time = last_frame.elapsed().as_millis();
-            //d// println!("FO {},{},{}", frame_time, is_first, force_redraw);
+        let mut fm = fm.borrow_mut();
+        if fm.update() {
+            force_redraw = true;
+        }
 
-            if is_first || force_redraw || frame_time >= 16 {
-                gui_painter.clear();
-                fm.redraw(&mut gui_painter);
-                gui_painter.done();
-                last_frame = Instant::now();
-            }
+        let frame_time = last_frame.elapsed().as_millis();
+        //d// println!("FO {},{},{}", frame_time, is_first, force_redraw);
 
-            is_first = false;
+        if is_first || force_redraw || (had_event && frame_time >= 16) {
+            gui_painter.clear();
+            fm.redraw(&mut gui_painter);
+            gui_painter.done();
+            last_frame = Instant::now();
         }
+
+        is_first = false;
     }
 
+    fm.borrow_mut().save_visits(true);
+
     Ok(())
 }
@@ -0,0 +1,142 @@
+use std::io::Read;
+
+/// Magic byte signatures at the start of a file.
+const MAGIC : [(&[u8], &str); 18] = [
+    (b"\x89PNG",             "image/png"),
+    (b"\xFF\xD8\xFF",        "image/jpeg"),
+    (b"GIF8",                "image/gif"),
+    (b"BM",                  "image/bmp"),
+    (b"II*\0",               "image/tiff"),
+    (b"MM\0*",               "image/tiff"),
+    (b"%PDF",                "application/pdf"),
+    (b"PK\x03\x04",          "application/zip"),
+    (b"\x1F\x8B",            "application/gzip"),
+    (b"BZh",                 "application/x-bzip2"),
+    (b"\xFD7zXZ\0",          "application/x-xz"),
+    (b"7z\xBC\xAF\x27\x1C",  "application/x-7z-compressed"),
+    (b"\x7FELF",             "application/x-executable"),
+    (b"MZ",                  "application/x-msdownload"),
+    (b"ID3",                 "audio/mpeg"),
+    (b"OggS",                "audio/ogg"),
+    (b"fLaC",                "audio/flac"),
+    (b"#!",                  "text/x-script"),
+];
+
+/// Fallback by file name extension, for files without a signature.
+const EXTENSIONS : [(&str, &str); 30] = [
+    ("txt",  "text/plain"),
+    ("md",   "text/markdown"),
+    ("rs",   "text/x-rust"),
+    ("c",    "text/x-c"),
+    ("h",    "text/x-c"),
+    ("cpp",  "text/x-c++"),
+    ("py",   "text/x-python"),
+    ("sh",   "application/x-sh"),
+    ("wl",   "text/x-wlambda"),
+    ("toml", "application/toml"),
+    ("json", "application/json"),
+    ("xml",  "application/xml"),
+    ("html", "text/html"),
+    ("htm",  "text/html"),
+    ("css",  "text/css"),
+    ("js",   "text/javascript"),
+    ("csv",  "text/csv"),
+    ("svg",  "image/svg+xml"),
+    ("ico",  "image/x-icon"),
+    ("tga",  "image/x-tga"),
+    ("mp3",  "audio/mpeg"),
+    ("wav",  "audio/wav"),
+    ("mp4",  "video/mp4"),
+    ("mkv",  "video/x-matroska"),
+    ("avi",  "video/x-msvideo"),
+    ("tar",  "application/x-tar"),
+    ("deb",  "application/vnd.debian.binary-package"),
+    ("ttf",  "font/ttf"),
+    ("dll",  "application/x-msdownload"),
+    ("exe",  "application/x-msdownload"),
+];
+
+/// The MIME type of a FIFO, socket or device.
+#[cfg(unix)]
+fn special_file_mime(ft: &std::fs::FileType) -> &'static str {
+    use std::os::unix::fs::FileTypeExt;
+    if ft.is_fifo() {
+        "inode/fifo"
+    } else if ft.is_socket() {
+        "inode/socket"
+    } else if ft.is_block_device() {
+        "inode/blockdevice"
+    } else if ft.is_char_device() {
+        "inode/chardevice"
+    } else {
+        "application/octet-stream"
+    }
+}
+
+#[cfg(not(unix))]
+fn special_file_mime(_ft: &std::fs::FileType) -> &'static str { "application/octet-stream" }
+
+/// Guesses the MIME type of a file from its first bytes, then from its
+/// name, and otherwise by checking if it looks like text.
+pub fn detect_mime(path: &std::path::Path) -> String {
+    if let Ok(md) = path.symlink_metadata() {
+        if md.file_type().is_symlink() && !path.exists() {
+            return String::from("inode/symlink");
+        }
+    }
+    // Only regular files are read, opening a FIFO would block:
+    match path.metadata() {
+        Ok(md) if md.is_dir()   => return String::from("inode/directory"),
+        Ok(md) if !md.is_file() => return String::from(special_file_mime(&md.file_type())),
+        Ok(_)  => (),
+        Err(_) => return String::from("application/octet-stream"),
+    }
+
+    let mut sample = vec![0u8; 4096];
+    let len =
+        match std::fs::File::open(path).and_then(|mut f| f.read(&mut sample)) {
+            Ok(len) => len,
+            Err(_)  => return String::from("application/octet-stream"),
+        };
+    let sample = &sample[0..len];
+
+    if len == 0 {
+        return String::from("application/x-empty");
+    }
+
+    for (magic, mime) in MAGIC.iter() {
+        if sample.starts_with(magic) {
+            return mime.to_string();
+        }
+    }
+
+    if sample.len() >= 12 && &sample[0..4] == b"RIFF" {
+        match &sample[8..12] {
+            b"WEBP" => return String::from("image/webp"),
+            b"WAVE" => return String::from("audio/wav"),
+            b"AVI " => return String::from("video/x-msvideo"),
+            _ => (),
+        }
+    }
+    if sample.len() >= 12 && &sample[4..8] == b"ftyp" {
+        return String::from("video/mp4");
+    }
+    if sample.len() >= 262 && &sample[257..262] == b"ustar" {
+        return String::from("application/x-tar");
+    }
+
+    if let Some(ext) = path.extension() {
+        let ext = ext.to_string_lossy().to_lowercase();
+        for (e, mime) in EXTENSIONS.iter() {
+            if *e == ext {
+                return mime.to_string();
+            }
+        }
+    }
+
+    if sample.contains(&0) {
+        String::from("application/octet-stream")
+    } else {
+        String::from("text/plain")
+    }
+}
@@ -0,0 +1,122 @@
+use crate::fm_page::*;
+use crate::cursor::PageCursor;
+use crate::assoc::*;
+
+pub struct Handler {
+    pub name: String,
+    pub args: Vec<String>,
+}
+
+/// Lists the programs a file can be opened with, the default handler
+/// first. Access starts the chosen one and closes the page.
+pub struct OpenWithSheet {
+    pub path:            std::path::PathBuf,
+    pub cwd:             std::path::PathBuf,
+    pub handlers:        Vec<Handler>,
+    pub done:            bool,
+    pub render_feedback: RenderFeedback,
+    pub cursor:          PageCursor,
+    pub rendered:        TableRef,
+}
+
+impl OpenWithSheet {
+    pub fn new(assoc: &Associations, ctx: &CommandContext) -> Self {
+        let mut handlers : Vec<Handler> =
+            assoc.handlers_for(&ctx.path).iter().map(|a| Handler {
+                name: a.name.clone(),
+                args: ctx.expand_args(&a.command),
+            }).collect();
+        handlers.push(Handler {
+            name: String::from("system default"),
+            args: ctx.expand_args(FALLBACK_COMMAND),
+        });
+
+        OpenWithSheet {
+            path:            ctx.path.clone(),
+            cwd:             ctx.dir.clone(),
+            handlers,
+            done:            false,
+            render_feedback: RenderFeedback::new(),
+            cursor:          PageCursor::new(),
+            rendered:        Table::new_ref(),
+        }
+    }
+}
+
+impl FmPage for OpenWithSheet {
+    fn len(&self) -> usize { self.handlers.len() }
+    fn get_scroll_offs(&self) -> usize { self.cursor.scroll_offset }
+    fn is_cursor_idx(&self, idx: usize) -> bool { self.cursor.is_cursor_idx(idx) }
+    fn is_selected(&self, _idx: usize) -> bool { false }
+    fn is_highlighted(&self, _idx: usize) -> bool { false }
+    fn needs_repage(&self) -> bool { true }
+    fn needs_redraw(&self) -> bool { true }
+    fn sort_by_column(&mut self, _col_idx: usize) { }
+
+    fn set_render_feedback(&mut self, fb: RenderFeedback) {
+        self.render_feedback = fb;
+    }
+
+    fn is_inside_screen_rect(&self, x: i32, y: i32) -> bool {
+        self.render_feedback.is_inside_screen_rect(x, y)
+    }
+
+    fn cursor_path(&self) -> Option<std::path::PathBuf> {
+        Some(self.path.clone())
+    }
+
+    fn update(&mut self) -> Option<PageEvent> {
+        if self.done {
+            self.done = false;
+            return Some(PageEvent::Close);
+        }
+        None
+    }
+
+    fn do_control(&mut self, ctrl: PageControl) -> Option<PageEvent> {
+        match ctrl {
+            PageControl::Back => Some(PageEvent::Close),
+            PageControl::Access => {
+                let handler = self.handlers.get(self.cursor.cursor_idx)?;
+                self.done = true;
+                Some(PageEvent::Launch(handler.args.clone(), self.cwd.clone()))
+            },
+            _ => {
+                self.cursor.do_control(self.handlers.len(), &self.render_feedback, ctrl);
+                None
+            },
+        }
+    }
+
+    fn as_drawable_table(&mut self) -> TableRef {
+        self.rendered =
+            std::rc::Rc::new(std::cell::RefCell::new(Table {
+                title: format!("open with: {}", self.path.to_string_lossy()),
+                row_gap: 2,
+                col_gap: 4,
+                columns: vec![
+                    Column {
+                        head: String::from("handler"),
+                        size: ColumnSizing::ExpandFract(1),
+                        calc_size: None,
+                        rows: self.handlers.iter().enumerate().map(|(i, h)| StyleString {
+                            text: h.name.clone(),
+                            style: if i == 0 { Style::Dir } else { Style::Default },
+                            highlight: vec![],
+                        }).collect(),
+                    },
+                    Column {
+                        head: String::from("command"),
+                        size: ColumnSizing::ExpandFract(2),
+                        calc_size: None,
+                        rows: self.handlers.iter().map(|h| StyleString {
+                            text: h.args.join(" "),
+                            style: Style::Default,
+                            highlight: vec![],
+                        }).collect(),
+                    },
+                ],
+            }));
+        self.rendered.clone()
+    }
+}
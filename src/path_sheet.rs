@@ -2,6 +2,13 @@ use chrono::DateTime;
 use chrono::offset::Utc;
 use crate::fm_page::*;
 use crate::cursor::PageCursor;
+use crate::shell::{ShellJob, ShellOutput};
+use crate::git_status::{GitStatus, GitStatusJob, GitWatch};
+use crate::drives::{FsSpace, FsSpaceJob};
+use crate::history::NavHistory;
+use crate::ignore::IgnoreStack;
+use crate::glob::glob_match_dotfiles;
+use crate::ls_colors::file_kind;
 use std::fs;
 
 #[derive(Debug)]
@@ -16,22 +23,135 @@ impl std::convert::From<std::io::Error> for FMError {
 }
 
 
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum PathRecordType {
     File,
     Dir,
     SymLink,
 }
 
+#[derive(Debug, Clone)]
 pub struct PathRecord {
     pub path:       std::path::PathBuf,
     pub size:       u64,
     pub mtime:      std::time::SystemTime,
     pub path_type:  PathRecordType,
+    /// The unix file type and permission bits, 0 elsewhere.
+    pub mode:       u32,
+}
+
+#[cfg(unix)]
+pub fn file_mode(md: &std::fs::Metadata) -> u32 {
+    use std::os::unix::fs::MetadataExt;
+    md.mode()
+}
+
+#[cfg(not(unix))]
+pub fn file_mode(_md: &std::fs::Metadata) -> u32 { 0 }
+
+/// How entries matched by the ignore rules are listed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IgnoredMode {
+    Show,
+    Dim,
+    Hide,
+}
+
+impl IgnoredMode {
+    pub fn parse(s: &str) -> Option<IgnoredMode> {
+        match s {
+            "show" => Some(IgnoredMode::Show),
+            "dim"  => Some(IgnoredMode::Dim),
+            "hide" => Some(IgnoredMode::Hide),
+            _      => None,
+        }
+    }
+
+    pub fn next(self) -> IgnoredMode {
+        match self {
+            IgnoredMode::Show => IgnoredMode::Dim,
+            IgnoredMode::Dim  => IgnoredMode::Hide,
+            IgnoredMode::Hide => IgnoredMode::Show,
+        }
+    }
+}
+
+/// Which entries of a directory are listed, set per pane.
+#[derive(Debug, Clone)]
+pub struct ListingFilter {
+    pub show_hidden: bool,
+    pub ignored:     IgnoredMode,
+    /// Whether the `.gitignore` files count as ignore rules.
+    pub gitignore:   bool,
+    /// File name globs the user wants ignored.
+    pub globs:       Vec<String>,
+    /// Whether `git status` is read for the git column.
+    pub git_status:  bool,
+}
+
+impl Default for ListingFilter {
+    fn default() -> Self {
+        ListingFilter {
+            show_hidden: true,
+            ignored:     IgnoredMode::Show,
+            gitignore:   true,
+            globs:       vec![],
+            git_status:  true,
+        }
+    }
+}
+
+impl ListingFilter {
+    fn is_ignored(&self, rules: &Option<IgnoreStack>, pr: &PathRecord) -> bool {
+        let name = pr.path.file_name().unwrap_or_default().to_string_lossy();
+        if self.globs.iter().any(|g| glob_match_dotfiles(g, &name)) {
+            return true;
+        }
+        match rules {
+            Some(rules) => rules.is_ignored(&pr.path, pr.path_type == PathRecordType::Dir),
+            None        => false,
+        }
+    }
+
+    /// A note for the title of a listing, empty if everything is shown.
+    pub fn describe(&self) -> String {
+        let mut notes = vec![];
+        if !self.show_hidden {
+            notes.push("hidden off");
+        }
+        match self.ignored {
+            IgnoredMode::Show => (),
+            IgnoredMode::Dim  => notes.push("ignored dimmed"),
+            IgnoredMode::Hide => notes.push("ignored hidden"),
+        }
+        notes.join(", ")
+    }
 }
 
 pub struct PathSheet {
     pub base:               std::path::PathBuf,
     pub paths:              std::vec::Vec<PathRecord>,
+    /// Set for a panelized listing of arbitrary paths instead of one
+    /// directory. Names are shown relative to `base` then.
+    pub panel:              Option<String>,
+    /// The command whose output is panelized, while it runs.
+    pub panel_job:          Option<ShellJob>,
+    /// The paths the panelized listing got so far, to skip the ones a
+    /// command prints again.
+    pub panel_paths:        std::collections::HashSet<std::path::PathBuf>,
+    /// The git status of the entries, if `base` is inside a work tree.
+    pub git_status:         Option<GitStatus>,
+    pub git_job:            Option<GitStatusJob>,
+    pub git_watch:          Option<GitWatch>,
+    /// The size of the file system of `base`, queried once per listing.
+    pub fs_space:           Option<FsSpace>,
+    pub fs_space_job:       Option<FsSpaceJob>,
+    /// The directories visited in this tab.
+    pub history:            NavHistory,
+    /// The filter the entries were listed with.
+    pub filter:             ListingFilter,
+    /// The entries matched by the ignore rules, when they are dimmed.
+    pub ignored:            std::collections::HashSet<std::path::PathBuf>,
     pub paths_dirty:        bool,
     pub state_dirty:        bool,
     pub selection:          std::collections::HashSet<usize>,
@@ -41,35 +161,100 @@ pub struct PathSheet {
     pub rendered:           TableRef,
 }
 
-impl PathSheet {
-    pub fn read(path: &std::path::Path) -> Result<PathSheet, FMError> {
-        let mut sheet_paths = Vec::new();
-
-        for e in fs::read_dir(path)? {
-            let entry = e?;
-            let path  = entry.path();
-            let md    = path.symlink_metadata()?;
-            let ft    = md.file_type();
-
-            let pr = PathRecord {
-                path,
-                size:  md.len(),
-                mtime: md.modified()?,
-                path_type: if ft.is_symlink() {
-                    PathRecordType::SymLink
-                } else if ft.is_dir() {
-                    PathRecordType::Dir
-                } else {
-                    PathRecordType::File
-                },
-            };
+/// Reads the entries of a directory. Unlike a `PathSheet` the records
+/// can be sent between threads.
+pub fn read_path_records(path: &std::path::Path) -> Result<Vec<PathRecord>, FMError> {
+    let mut sheet_paths = Vec::new();
+
+    for e in fs::read_dir(path)? {
+        let entry = e?;
+        sheet_paths.push(read_path_record(&entry.path())?);
+    }
+
+    Ok(sheet_paths)
+}
+
+fn read_path_record(path: &std::path::Path) -> std::io::Result<PathRecord> {
+    let md = path.symlink_metadata()?;
+    let ft = md.file_type();
+    Ok(PathRecord {
+        path:  path.to_path_buf(),
+        size:  md.len(),
+        mtime: md.modified()?,
+        path_type: if ft.is_symlink() {
+            PathRecordType::SymLink
+        } else if ft.is_dir() {
+            PathRecordType::Dir
+        } else {
+            PathRecordType::File
+        },
+        mode: file_mode(&md),
+    })
+}
 
-            sheet_paths.push(pr);
+/// Reads the records of a list of paths, relative ones are resolved
+/// against `base`. Duplicates are skipped, paths that can't be read are
+/// returned as error messages.
+pub fn read_path_list<P: AsRef<std::path::Path>>(base: &std::path::Path, paths: &[P])
+    -> (Vec<PathRecord>, Vec<String>) {
+
+    let mut records = vec![];
+    let mut errors  = vec![];
+    let mut seen    = std::collections::HashSet::new();
+    for path in paths.iter() {
+        let path = base.join(path.as_ref());
+        if !seen.insert(path.clone()) {
+            continue;
+        }
+        match read_path_record(&path) {
+            Ok(pr) => records.push(pr),
+            Err(e) => errors.push(format!("{}: {}", path.to_string_lossy(), e)),
         }
+    }
+    (records, errors)
+}
+
+/// Formats a file size for the size column, rounded up to whole units.
+pub fn format_size(size: u64) -> String {
+    if size >= 1024_u64.pow(4) {
+        let rnd = 1024_u64.pow(4) - 1;
+        format!("{:-4}  TB", (size + rnd) / 1024_u64.pow(4))
+    } else if size >= 1024_u64.pow(3) {
+        let rnd = 1024_u64.pow(3) - 1;
+        format!("{:-4}  GB", (size + rnd) / 1024_u64.pow(3))
+    } else if size >= 1024_u64.pow(2) {
+        let rnd = 1024_u64.pow(2) - 1;
+        format!("{:-4}  MB", (size + rnd) / 1024_u64.pow(2))
+    } else if size >= 1024_u64.pow(1) {
+        let rnd = 1024_u64.pow(1) - 1;
+        format!("{:-4}  kB", (size + rnd) / 1024_u64.pow(1))
+    } else {
+        format!("{:-4}  B", size)
+    }
+}
 
-        Ok(PathSheet {
+impl PathSheet {
+    pub fn read(path: &std::path::Path) -> Result<PathSheet, FMError> {
+        let mut ps = Self::from_records(path, read_path_records(path)?);
+        ps.fs_space_job = Some(FsSpaceJob::start(path));
+        Ok(ps)
+    }
+
+    pub fn from_records(path: &std::path::Path, records: Vec<PathRecord>) -> PathSheet {
+        PathSheet {
             base:           path.to_path_buf(),
-            paths:          sheet_paths,
+            paths:          records,
+            panel:          None,
+            panel_job:      None,
+            panel_paths:    std::collections::HashSet::new(),
+            git_status:     None,
+            git_job:        None,
+            git_watch:      None,
+            fs_space:       None,
+            fs_space_job:   None,
+            history:        NavHistory::default(),
+            filter:         ListingFilter::default(),
+            ignored:        std::collections::HashSet::new(),
             render_feedback: RenderFeedback::new(),
             cursor:         PageCursor::new(),
             selection:      std::collections::HashSet::new(),
@@ -77,7 +262,147 @@ impl PathSheet {
             paths_dirty:    false,
             state_dirty:    false,
             rendered:       Table::new_ref(),
-        })
+        }
+    }
+
+    /// A listing of arbitrary paths, like the output of a command or
+    /// search results.
+    pub fn panelize(base: &std::path::Path, title: &str, records: Vec<PathRecord>) -> PathSheet {
+        let mut ps = Self::from_records(base, records);
+        ps.panel = Some(title.to_string());
+        ps.panel_paths = ps.paths.iter().map(|p| p.path.clone()).collect();
+        ps.paths_dirty = true;
+        ps
+    }
+
+    /// Panelizes the paths a shell command prints, one per line, as
+    /// they arrive.
+    pub fn panelize_command(base: &std::path::Path, job: ShellJob) -> PathSheet {
+        let mut ps = Self::panelize(base, &format!("$ {}", job.command), vec![]);
+        ps.panel_job = Some(job);
+        ps
+    }
+
+    /// Drops the hidden and ignored entries the filter doesn't want
+    /// listed, and remembers the ignored ones to dim. Starts reading the
+    /// git status if the filter wants the git column, or drops it.
+    pub fn apply_filter(&mut self, filter: &ListingFilter) {
+        if !filter.git_status {
+            self.git_job    = None;
+            self.git_watch  = None;
+            self.git_status = None;
+        } else if self.panel.is_none() && self.git_job.is_none() && self.git_status.is_none() {
+            self.git_job = Some(GitStatusJob::start(&self.base));
+        }
+
+        let rules =
+            if filter.gitignore && filter.ignored != IgnoredMode::Show {
+                Some(IgnoreStack::for_dir(&self.base))
+            } else {
+                None
+            };
+        let mut ignored = std::collections::HashSet::new();
+        self.paths.retain(|pr| {
+            let hidden =
+                pr.path.file_name().map(|n| n.to_string_lossy().starts_with('.')).unwrap_or(false);
+            if hidden && !filter.show_hidden {
+                return false;
+            }
+            if filter.ignored != IgnoredMode::Show && filter.is_ignored(&rules, pr) {
+                if filter.ignored == IgnoredMode::Hide {
+                    return false;
+                }
+                ignored.insert(pr.path.clone());
+            }
+            true
+        });
+        self.ignored     = ignored;
+        self.filter      = filter.clone();
+        self.paths_dirty = true;
+    }
+
+    /// The text of the name column. Panelized listings show the path
+    /// relative to the base directory.
+    fn display_name(&self, pr: &PathRecord) -> String {
+        if self.panel.is_some() {
+            if let Ok(rel) = pr.path.strip_prefix(&self.base) {
+                return rel.to_string_lossy().to_string();
+            }
+            return pr.path.to_string_lossy().to_string();
+        }
+        String::from(pr.path.file_name()
+                        .unwrap_or(std::ffi::OsStr::new(""))
+                        .to_string_lossy())
+    }
+
+    /// Stats the paths of a panelized listing again and drops the ones
+    /// that vanished.
+    fn reread_panel(&mut self) {
+        let selected = self.selected_paths();
+        let cursor   = self.cursor_path();
+        let paths : Vec<std::path::PathBuf> = self.paths.iter().map(|p| p.path.clone()).collect();
+        self.paths = read_path_list(&self.base, &paths).0;
+        self.panel_paths = self.paths.iter().map(|p| p.path.clone()).collect();
+        self.selection =
+            self.paths.iter().enumerate()
+                .filter(|(_, p)| selected.contains(&p.path))
+                .map(|(i, _)| i)
+                .collect();
+        if let Some(cursor) = cursor {
+            self.set_cursor_path(&cursor);
+        }
+        self.paths_dirty = true;
+    }
+
+    /// Picks up the result of the git status job, and starts it again
+    /// when the index, the directory or one of its files changed.
+    fn update_git_status(&mut self) -> bool {
+        if let Some(job) = &self.git_job {
+            let status =
+                match job.poll() {
+                    Some(status) => status,
+                    None         => return false,
+                };
+            let files : Vec<std::path::PathBuf> =
+                self.paths.iter()
+                    .filter(|p| p.path_type == PathRecordType::File)
+                    .map(|p| p.path.clone())
+                    .collect();
+            self.git_job   = None;
+            self.git_watch =
+                status.as_ref().map(|st| GitWatch::new(&st.git_dir, &self.base, &files));
+            self.git_status = status;
+            self.paths_dirty = true;
+            return true;
+        }
+
+        if self.git_watch.as_mut().map(|w| w.changed()).unwrap_or(false) {
+            self.git_job = Some(GitStatusJob::start(&self.base));
+        }
+        false
+    }
+
+    /// Picks up the size of the file system once it was queried.
+    fn update_fs_space(&mut self) -> bool {
+        let space =
+            match self.fs_space_job.as_ref().and_then(|job| job.poll()) {
+                Some(space) => space,
+                None        => return false,
+            };
+        self.fs_space_job = None;
+        self.fs_space     = space;
+        self.paths_dirty  = true;
+        true
+    }
+
+    /// Moves the cursor onto the entry with the given path, if it is
+    /// listed in this sheet.
+    pub fn set_cursor_path(&mut self, path: &std::path::Path) {
+        if let Some(idx) = self.paths.iter().position(|p| p.path == path) {
+            self.cursor.cursor_idx = idx;
+            self.cursor.do_control(
+                self.paths.len(), &self.render_feedback, PageControl::Refresh);
+        }
     }
 }
 
@@ -91,16 +416,14 @@ impl FmPage for PathSheet {
     fn needs_redraw(&self) -> bool { self.state_dirty }
 
     fn sort_by_column(&mut self, col_idx: usize) {
+        // The selection is stored by index, remember it by path:
+        let selected = self.selected_paths();
+
         if col_idx == 0 {
-            self.paths.sort_by(|a, b| {
-                let s1 = String::from(
-                    a.path.file_name()
-                    .unwrap_or(std::ffi::OsStr::new(""))
-                    .to_string_lossy()).to_lowercase();
-                let s2 = String::from(
-                    b.path.file_name()
-                    .unwrap_or(std::ffi::OsStr::new(""))
-                    .to_string_lossy()).to_lowercase();
+            let mut paths = std::mem::take(&mut self.paths);
+            paths.sort_by(|a, b| {
+                let s1 = self.display_name(a).to_lowercase();
+                let s2 = self.display_name(b).to_lowercase();
 
                 if let PathRecordType::Dir = a.path_type {
                     if let PathRecordType::Dir = b.path_type {
@@ -116,12 +439,18 @@ impl FmPage for PathSheet {
                     }
                 }
             });
+            self.paths = paths;
         } else if col_idx == 1 {
             self.paths.sort_by(|a, b| a.mtime.partial_cmp(&b.mtime).unwrap());
         } else if col_idx == 2 {
             self.paths.sort_by(|a, b| a.size.partial_cmp(&b.size).unwrap());
         }
 
+        self.selection =
+            self.paths.iter().enumerate()
+                .filter(|(_, p)| selected.contains(&p.path))
+                .map(|(i, _)| i)
+                .collect();
         self.paths_dirty = true;
     }
 
@@ -133,76 +462,195 @@ impl FmPage for PathSheet {
         self.render_feedback.is_inside_screen_rect(x, y)
     }
 
-    fn do_control(&mut self, ctrl: PageControl) {
-        self.cursor.do_control(self.len(), &self.render_feedback, ctrl);
+    fn cursor_path(&self) -> Option<std::path::PathBuf> {
+        self.paths.get(self.cursor.cursor_idx).map(|p| p.path.clone())
+    }
+
+    fn selected_paths(&self) -> std::vec::Vec<std::path::PathBuf> {
+        let mut idxs : Vec<usize> = self.selection.iter().copied().collect();
+        idxs.sort();
+        idxs.iter().filter_map(|i| self.paths.get(*i)).map(|p| p.path.clone()).collect()
+    }
+
+    fn clear_selection(&mut self) {
+        self.selection.clear();
+        self.state_dirty = true;
+    }
+
+    fn cancel(&mut self) {
+        if let Some(job) = &self.panel_job {
+            job.cancel();
+        }
+    }
+
+    fn is_busy(&self) -> bool {
+        self.panel_job.is_some() || self.git_job.is_some() || self.fs_space_job.is_some()
+    }
+
+    fn update(&mut self) -> Option<PageEvent> {
+        let changed = self.update_git_status() | self.update_fs_space();
+        let output =
+            match self.panel_job.as_mut() {
+                Some(job) => job.poll(),
+                None      => vec![],
+            };
+        if output.is_empty() {
+            return if changed { Some(PageEvent::Redraw) } else { None };
+        }
+
+        let mut lines = vec![];
+        let mut log   = vec![];
+        for out in output {
+            match out {
+                ShellOutput::Stdout(line) => {
+                    if !line.trim().is_empty() {
+                        lines.push(line);
+                    }
+                },
+                ShellOutput::Stderr(line) => log.push(format!("stderr: {}", line)),
+                ShellOutput::Exit(status) => {
+                    let cmd = self.panel_job.take().map(|j| j.command.clone()).unwrap_or_default();
+                    log.push(format!("$ {} [{}]", cmd, status));
+                },
+            }
+        }
+
+        let (records, mut errors) = read_path_list(&self.base, &lines);
+        log.append(&mut errors);
+        for pr in records {
+            if self.panel_paths.insert(pr.path.clone()) {
+                self.paths.push(pr);
+            }
+        }
+        self.paths_dirty = true;
+
+        if log.is_empty() {
+            Some(PageEvent::Redraw)
+        } else {
+            Some(PageEvent::Log(log))
+        }
+    }
+
+    fn do_control(&mut self, ctrl: PageControl) -> Option<PageEvent> {
+        match ctrl {
+            PageControl::Refresh if self.panel.is_some() => {
+                self.reread_panel();
+                self.cursor.do_control(self.len(), &self.render_feedback, ctrl);
+                None
+            },
+            PageControl::ToggleSelect => {
+                let idx = self.cursor.cursor_idx;
+                if idx < self.paths.len() && !self.selection.remove(&idx) {
+                    self.selection.insert(idx);
+                }
+                self.state_dirty = true;
+                self.cursor.do_control(self.len(), &self.render_feedback, PageControl::CursorDown);
+                None
+            },
+            PageControl::Access => {
+                let path = self.cursor_path()?;
+                if path.is_dir() {
+                    Some(PageEvent::OpenDir(path))
+                } else {
+                    Some(PageEvent::OpenFile(path))
+                }
+            },
+            PageControl::Back if self.panel.is_some() => Some(PageEvent::Close),
+            PageControl::Back => {
+                let parent = self.base.parent()?;
+                Some(PageEvent::OpenDir(parent.to_path_buf()))
+            },
+            _ => {
+                self.cursor.do_control(self.len(), &self.render_feedback, ctrl);
+                None
+            },
+        }
     }
 
     fn as_drawable_table(&mut self) -> TableRef {
         if !self.needs_repage() {
             return self.rendered.clone();
         }
+        let title =
+            match &self.panel {
+                Some(panel) => {
+                    let running = if self.panel_job.is_some() { ", running..." } else { "" };
+                    format!("{} in {} [{} entries{}]",
+                        panel, self.base.to_string_lossy(), self.paths.len(), running)
+                },
+                None => {
+                    let mut title = String::from(self.base.to_string_lossy());
+                    if let Some(git) = &self.git_status {
+                        title += &format!(" [{}]", git.branch_info());
+                    }
+                    if let Some(space) = &self.fs_space {
+                        title += &format!(" ({} free)", format_size(space.avail).trim());
+                    }
+                    let filter = self.filter.describe();
+                    if !filter.is_empty() {
+                        title += &format!(" [{}]", filter);
+                    }
+                    title
+                },
+            };
+        let mut columns = vec![
+            Column {
+                head: String::from("name"),
+                size: ColumnSizing::ExpandFract(1),
+                calc_size: None,
+                rows: self.paths.iter().map(|p| {
+                    let mut path_postfix = String::from("");
+                    if let PathRecordType::Dir = p.path_type {
+                        path_postfix = std::path::MAIN_SEPARATOR.to_string();
+                    };
+
+                    StyleString {
+                        text: self.display_name(p) + &path_postfix,
+                        style:
+                            if self.ignored.contains(&p.path) {
+                                Style::Ignored
+                            } else {
+                                Style::Entry(file_kind(p))
+                            },
+                        highlight: vec![],
+                    }
+                }).collect(),
+            },
+            Column {
+                head: String::from("time"),
+                size: ColumnSizing::TextWidth(String::from("MMMM-MM-MM MM:MM:MM")),
+                calc_size: None,
+                rows: self.paths.iter().map(|p| {
+                    let dt : DateTime<Utc> = p.mtime.into();
+                    StyleString { text: format!("{}", dt.format("%Y-%m-%d %H:%M:%S")), style: Style::Default, highlight: vec![] }
+                }).collect(),
+            },
+            Column {
+                head: String::from("size"),
+                size: ColumnSizing::TextWidth(String::from("MMMMMMMM")),
+                calc_size: None,
+                rows: self.paths.iter().map(|p| {
+                    StyleString { text: format_size(p.size), style: Style::Default, highlight: vec![] }
+                }).collect(),
+            },
+        ];
+        if let Some(git) = &self.git_status {
+            columns.push(Column {
+                head: String::from("git"),
+                size: ColumnSizing::TextWidth(String::from("MMM")),
+                calc_size: None,
+                rows: self.paths.iter().map(|p| {
+                    let flags = git.flags(p.path.file_name().unwrap_or_default());
+                    StyleString { text: flags.label(), style: flags.style(), highlight: vec![] }
+                }).collect(),
+            });
+        }
         self.rendered =
             std::rc::Rc::new(std::cell::RefCell::new(Table {
-                title: String::from(self.base.to_string_lossy()),
+                title,
                 row_gap: 2,
                 col_gap: 4,
-                columns: vec![
-                    Column {
-                        head: String::from("name"),
-                        size: ColumnSizing::ExpandFract(1),
-                        calc_size: None,
-                        rows: self.paths.iter().map(|p| {
-                            let mut path_postfix = String::from("");
-                            if let PathRecordType::Dir = p.path_type {
-                                path_postfix = std::path::MAIN_SEPARATOR.to_string();
-                            };
-
-                            StyleString {
-                                text: String::from(p.path.file_name()
-                                                    .unwrap_or(std::ffi::OsStr::new(""))
-                                                    .to_string_lossy()) + &path_postfix,
-                                style: match p.path_type {
-                                    PathRecordType::File    => Style::File,
-                                    PathRecordType::Dir     => Style::Dir,
-                                    PathRecordType::SymLink => Style::Special,
-                                }
-                            }
-                        }).collect(),
-                    },
-                    Column {
-                        head: String::from("time"),
-                        size: ColumnSizing::TextWidth(String::from("MMMM-MM-MM MM:MM:MM")),
-                        calc_size: None,
-                        rows: self.paths.iter().map(|p| {
-                            let dt : DateTime<Utc> = p.mtime.into();
-                            StyleString { text: format!("{}", dt.format("%Y-%m-%d %H:%M:%S")), style: Style::Default }
-                        }).collect(),
-                    },
-                    Column {
-                        head: String::from("size"),
-                        size: ColumnSizing::TextWidth(String::from("MMMMMMMM")),
-                        calc_size: None,
-                        rows: self.paths.iter().map(|p| {
-                            let text =
-                                if p.size >= 1024_u64.pow(4) {
-                                    let rnd = 1024_u64.pow(4) - 1;
-                                    format!("{:-4}  TB", (p.size + rnd) / 1024_u64.pow(4))
-                                } else if p.size >= 1024_u64.pow(3) {
-                                    let rnd = 1024_u64.pow(3) - 1;
-                                    format!("{:-4}  GB", (p.size + rnd) / 1024_u64.pow(3))
-                                } else if p.size >= 1024_u64.pow(2) {
-                                    let rnd = 1024_u64.pow(2) - 1;
-                                    format!("{:-4}  MB", (p.size + rnd) / 1024_u64.pow(2))
-                                } else if p.size >= 1024_u64.pow(1) {
-                                    let rnd = 1024_u64.pow(1) - 1;
-                                    format!("{:-4}  kB", (p.size + rnd) / 1024_u64.pow(1))
-                                } else {
-                                    format!("{:-4}  B", p.size)
-                                };
-                            StyleString { text, style: Style::Default }
-                        }).collect(),
-                    },
-                ],
+                columns,
             }));
         return self.rendered.clone();
     }
@@ -0,0 +1,311 @@
+use crate::fm_page::*;
+use crate::cursor::PageCursor;
+use crate::attr_ops::*;
+use crate::mime::detect_mime;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PropField {
+    Info,
+    Mode,
+    Owner,
+    Modified,
+    Recursive,
+}
+
+pub struct PropRow {
+    pub field: PropField,
+    pub name:  String,
+    pub value: String,
+}
+
+impl PropRow {
+    fn info(name: &str, value: String) -> Self {
+        PropRow { field: PropField::Info, name: name.to_string(), value }
+    }
+
+    fn is_editable(&self) -> bool { self.field != PropField::Info }
+}
+
+/// Shows all metadata of one file system entry. Permission bits, owner
+/// and modification time can be edited, for directories optionally
+/// recursively.
+pub struct PropertiesSheet {
+    pub path:            std::path::PathBuf,
+    pub rows:            std::vec::Vec<PropRow>,
+    pub recursive:       bool,
+    pub editing:         Option<PropField>,
+    pub render_feedback: RenderFeedback,
+    pub cursor:          PageCursor,
+    pub rendered:        TableRef,
+}
+
+impl PropertiesSheet {
+    pub fn new(path: &std::path::Path) -> Self {
+        let mut ps = PropertiesSheet {
+            path:            path.to_path_buf(),
+            rows:            vec![],
+            recursive:       false,
+            editing:         None,
+            render_feedback: RenderFeedback::new(),
+            cursor:          PageCursor::new(),
+            rendered:        Table::new_ref(),
+        };
+        ps.reload();
+        ps
+    }
+
+    pub fn reload(&mut self) {
+        self.rows.clear();
+
+        let md = match self.path.symlink_metadata() {
+            Ok(md) => md,
+            Err(e) => {
+                self.rows.push(PropRow::info("path", self.path.to_string_lossy().to_string()));
+                self.rows.push(PropRow::info("error", e.to_string()));
+                return;
+            },
+        };
+        let ft = md.file_type();
+
+        self.rows.push(PropRow::info("path", self.path.to_string_lossy().to_string()));
+        self.rows.push(PropRow::info("type",
+            String::from(
+                if ft.is_symlink()   { "symbolic link" }
+                else if ft.is_dir()  { "directory" }
+                else if ft.is_file() { "regular file" }
+                else                 { "special file" })));
+        self.rows.push(PropRow::info("size", format!("{} bytes", md.len())));
+
+        if let Ok(t) = md.modified() {
+            self.rows.push(PropRow {
+                field: PropField::Modified,
+                name:  String::from("modified"),
+                value: format_time(t),
+            });
+        }
+        if let Ok(t) = md.accessed() {
+            self.rows.push(PropRow::info("accessed", format_time(t)));
+        }
+        if let Ok(t) = md.created() {
+            self.rows.push(PropRow::info("created", format_time(t)));
+        }
+
+        self.push_unix_rows(&md);
+
+        if ft.is_symlink() {
+            let target =
+                match std::fs::read_link(&self.path) {
+                    Ok(t)  => t.to_string_lossy().to_string(),
+                    Err(e) => e.to_string(),
+                };
+            self.rows.push(PropRow::info("link target", target));
+        }
+
+        self.rows.push(PropRow::info("mime type", detect_mime(&self.path)));
+
+        if ft.is_dir() {
+            self.rows.push(PropRow {
+                field: PropField::Recursive,
+                name:  String::from("apply recursively"),
+                value: String::from(if self.recursive { "yes" } else { "no" }),
+            });
+        }
+    }
+
+    #[cfg(unix)]
+    fn push_unix_rows(&mut self, md: &std::fs::Metadata) {
+        use std::os::unix::fs::MetadataExt;
+        use chrono::{DateTime, NaiveDateTime};
+        use chrono::offset::Utc;
+        use crate::users::*;
+
+        let ctime = NaiveDateTime::from_timestamp(md.ctime(), md.ctime_nsec() as u32);
+        let ctime = DateTime::<Utc>::from_utc(ctime, Utc);
+        self.rows.push(PropRow::info("changed", format!("{}", ctime.format(TIME_FORMAT))));
+
+        self.rows.push(PropRow {
+            field: PropField::Mode,
+            name:  String::from("mode"),
+            value: format!("{:04o} {}", md.mode() & 0o7777, mode_string(md.mode())),
+        });
+        self.rows.push(PropRow {
+            field: PropField::Owner,
+            name:  String::from("owner"),
+            value: owner_string(md.uid(), md.gid()),
+        });
+        self.rows.push(PropRow::info("uid/gid", format!("{}/{}", md.uid(), md.gid())));
+        self.rows.push(PropRow::info("inode", md.ino().to_string()));
+        // Linux splits major and minor numbers over the 64 bit dev_t:
+        let dev   = md.dev();
+        let major = ((dev >> 8) & 0xFFF) | ((dev >> 32) & !0xFFF);
+        let minor = (dev & 0xFF) | ((dev >> 12) & !0xFF);
+        self.rows.push(PropRow::info("device", format!("{}:{}", major, minor)));
+        self.rows.push(PropRow::info("links", md.nlink().to_string()));
+    }
+
+    #[cfg(not(unix))]
+    fn push_unix_rows(&mut self, md: &std::fs::Metadata) {
+        self.rows.push(PropRow::info("read only",
+            String::from(if md.permissions().readonly() { "yes" } else { "no" })));
+    }
+
+    /// Applies an edited value to the entry, and to everything below it if
+    /// recursive mode is on. Returns the messages for the log.
+    fn apply_edit(&mut self, field: PropField, value: &str) -> Vec<String> {
+        let mut log = vec![];
+        let path = self.path.clone();
+        let what;
+
+        let count =
+            match field {
+                PropField::Mode => {
+                    let change = match ModeChange::parse(value) {
+                        Ok(change) => change,
+                        Err(e) => return vec![format!("Bad mode: {}", e)],
+                    };
+                    what = format!("mode {}", value.trim());
+                    apply_recursive(&path, self.recursive, EntryFilter::All,
+                        &mut |p| change_mode(p, &change), &mut log)
+                },
+                PropField::Owner => {
+                    let (uid, gid) = match parse_owner(value) {
+                        Some(ids) => ids,
+                        None => return vec![format!("Unknown owner '{}', expected user:group", value)],
+                    };
+                    what = format!("owner {}", value.trim());
+                    apply_recursive(&path, self.recursive, EntryFilter::All,
+                        &mut |p| set_owner(p, uid, gid), &mut log)
+                },
+                PropField::Modified => {
+                    let t = match parse_time(value) {
+                        Some(t) => t,
+                        None => return vec![format!("Bad time '{}', expected YYYY-MM-DD HH:MM:SS", value)],
+                    };
+                    what = format!("mtime {}", value.trim());
+                    apply_recursive(&path, self.recursive, EntryFilter::All,
+                        &mut |p| set_mtime(p, t), &mut log)
+                },
+                _ => return log,
+            };
+
+        log.push(format!("Set {} on {} entries of {} ({} failed)",
+            what, count, path.to_string_lossy(), log.len()));
+        self.reload();
+        log
+    }
+}
+
+/// Parses `user`, `user:group` or `:group`. Names and numeric ids are
+/// accepted. Returns `None` if a name is unknown.
+pub fn parse_owner(s: &str) -> Option<(Option<u32>, Option<u32>)> {
+    use crate::users::*;
+
+    let s = s.trim();
+    let (user, group) =
+        match s.find(':') {
+            Some(i) => (&s[0..i], &s[(i + 1)..]),
+            None    => (s, ""),
+        };
+    let uid = if user.is_empty()  { None } else { Some(lookup_uid(user)?) };
+    let gid = if group.is_empty() { None } else { Some(lookup_gid(group)?) };
+    if uid.is_none() && gid.is_none() {
+        return None;
+    }
+    Some((uid, gid))
+}
+
+impl FmPage for PropertiesSheet {
+    fn len(&self) -> usize { self.rows.len() }
+    fn get_scroll_offs(&self) -> usize { self.cursor.scroll_offset }
+    fn is_cursor_idx(&self, idx: usize) -> bool { self.cursor.is_cursor_idx(idx) }
+    fn is_selected(&self, _idx: usize) -> bool { false }
+    fn is_highlighted(&self, _idx: usize) -> bool { false }
+    fn needs_repage(&self) -> bool { true }
+    fn needs_redraw(&self) -> bool { true }
+    fn sort_by_column(&mut self, _col_idx: usize) { }
+
+    fn set_render_feedback(&mut self, fb: RenderFeedback) {
+        self.render_feedback = fb;
+    }
+
+    fn is_inside_screen_rect(&self, x: i32, y: i32) -> bool {
+        self.render_feedback.is_inside_screen_rect(x, y)
+    }
+
+    fn cursor_path(&self) -> Option<std::path::PathBuf> {
+        Some(self.path.clone())
+    }
+
+    fn do_control(&mut self, ctrl: PageControl) -> Option<PageEvent> {
+        match ctrl {
+            PageControl::Back => Some(PageEvent::Close),
+            PageControl::Access => {
+                let row = self.rows.get(self.cursor.cursor_idx)?;
+                match row.field {
+                    PropField::Info => None,
+                    PropField::Recursive => {
+                        self.recursive = !self.recursive;
+                        self.reload();
+                        None
+                    },
+                    field => {
+                        self.editing = Some(field);
+                        let value =
+                            if field == PropField::Mode {
+                                // Only the octal part is editable:
+                                row.value.split(' ').next().unwrap_or("").to_string()
+                            } else {
+                                row.value.clone()
+                            };
+                        Some(PageEvent::Prompt(format!("{}: ", row.name), value))
+                    },
+                }
+            },
+            PageControl::Edit(value) => {
+                let field = self.editing.take()?;
+                Some(PageEvent::Log(self.apply_edit(field, &value)))
+            },
+            PageControl::Refresh => {
+                self.reload();
+                self.cursor.do_control(self.rows.len(), &self.render_feedback, ctrl);
+                None
+            },
+            _ => {
+                self.cursor.do_control(self.rows.len(), &self.render_feedback, ctrl);
+                None
+            },
+        }
+    }
+
+    fn as_drawable_table(&mut self) -> TableRef {
+        self.rendered =
+            std::rc::Rc::new(std::cell::RefCell::new(Table {
+                title: format!("properties: {}", self.path.to_string_lossy()),
+                row_gap: 2,
+                col_gap: 4,
+                columns: vec![
+                    Column {
+                        head: String::from("property"),
+                        size: ColumnSizing::TextWidth(String::from("apply recursively")),
+                        calc_size: None,
+                        rows: self.rows.iter().map(|r| StyleString {
+                            text: r.name.clone(),
+                            style: if r.is_editable() { Style::Dir } else { Style::Default },
+                            highlight: vec![],
+                        }).collect(),
+                    },
+                    Column {
+                        head: String::from("value"),
+                        size: ColumnSizing::ExpandFract(1),
+                        calc_size: None,
+                        rows: self.rows.iter().map(|r| StyleString {
+                            text: r.value.clone(),
+                            style: Style::Default,
+                            highlight: vec![],
+                        }).collect(),
+                    },
+                ],
+            }));
+        self.rendered.clone()
+    }
+}
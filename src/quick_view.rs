@@ -0,0 +1,145 @@
+use crate::fm_page::*;
+use crate::path_sheet::*;
+use crate::text_view::*;
+use crate::hex_view::*;
+use crate::image_view::*;
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+use std::time::{Duration, Instant};
+
+/// Time the cursor has to rest on an entry before its preview is loaded.
+const DEBOUNCE_TIME : Duration = Duration::from_millis(150);
+
+/// The data of a preview, as loaded by the worker thread.
+pub enum Preview {
+    Dir(Vec<PathRecord>),
+    Text(TextFile),
+    Image(Vec<u8>),
+    Binary,
+    Error(String),
+}
+
+impl Preview {
+    /// Does all the file system access of a preview, so that it can be
+    /// called off the main thread.
+    pub fn load(path: &std::path::Path) -> Preview {
+        if path.is_dir() {
+            return match read_path_records(path) {
+                Ok(records) => Preview::Dir(records),
+                Err(e)      => Preview::Error(format!("{:?}", e)),
+            };
+        }
+
+        if is_image_file(path) {
+            return match std::fs::read(path) {
+                Ok(bytes) => Preview::Image(bytes),
+                Err(e)    => Preview::Error(e.to_string()),
+            };
+        }
+
+        match is_binary_file(path) {
+            Ok(true)  => Preview::Binary,
+            Ok(false) => match TextFile::open(path) {
+                Ok(tf) => Preview::Text(tf),
+                Err(e) => Preview::Error(e.to_string()),
+            },
+            Err(e) => Preview::Error(e.to_string()),
+        }
+    }
+
+    pub fn into_page(self, path: &std::path::Path) -> Result<Box<dyn FmPage>, String> {
+        match self {
+            Preview::Dir(records) => {
+                let mut ps = PathSheet::from_records(path, records);
+                ps.sort_by_column(0);
+                Ok(Box::new(ps))
+            },
+            Preview::Text(tf) => Ok(Box::new(TextViewSheet::from_file(tf))),
+            // SDL surfaces can't be sent between threads, so only the
+            // reading is done by the worker:
+            Preview::Image(bytes) =>
+                ImageViewSheet::from_bytes(path, &bytes)
+                    .map(|iv| Box::new(iv) as Box<dyn FmPage>),
+            Preview::Binary   =>
+                HexViewSheet::open(path)
+                    .map(|hv| Box::new(hv) as Box<dyn FmPage>)
+                    .map_err(|e| e.to_string()),
+            Preview::Error(e) => Err(e),
+        }
+    }
+}
+
+/// Keeps track of the entry under the cursor of the active pane and
+/// loads a preview page for it, once the cursor stopped moving.
+pub struct QuickView {
+    pub target:     Option<std::path::PathBuf>,
+    pub changed_at: Instant,
+    pub loaded:     bool,
+    loading:        Option<Receiver<(std::path::PathBuf, Preview)>>,
+}
+
+impl QuickView {
+    pub fn new() -> Self {
+        QuickView {
+            target:     None,
+            changed_at: Instant::now(),
+            loaded:     false,
+            loading:    None,
+        }
+    }
+
+    /// Forgets the current target, so that the next call to `follow`
+    /// loads the preview again.
+    pub fn reset(&mut self) {
+        self.target  = None;
+        self.loaded  = false;
+        self.loading = None;
+    }
+
+    pub fn follow(&mut self, cursor: Option<std::path::PathBuf>) {
+        if cursor != self.target {
+            self.target     = cursor;
+            self.changed_at = Instant::now();
+            self.loaded     = false;
+            self.loading    = None;
+        }
+
+        if self.loaded || self.loading.is_some()
+           || self.changed_at.elapsed() < DEBOUNCE_TIME {
+            return;
+        }
+
+        if let Some(path) = self.target.clone() {
+            let (tx, rx) = channel();
+            std::thread::spawn(move || {
+                let preview = Preview::load(&path);
+                let _ = tx.send((path, preview));
+            });
+            self.loading = Some(rx);
+        }
+    }
+
+    /// Returns the preview page once it finished loading.
+    pub fn poll(&mut self) -> Option<Result<Box<dyn FmPage>, String>> {
+        let (path, preview) =
+            match self.loading.as_ref()?.try_recv() {
+                Ok(loaded)                      => loaded,
+                Err(TryRecvError::Empty)        => return None,
+                Err(TryRecvError::Disconnected) => {
+                    self.loading = None;
+                    return None;
+                },
+            };
+
+        self.loading = None;
+        if Some(&path) != self.target.as_ref() {
+            return None;
+        }
+
+        self.loaded = true;
+        Some(preview.into_page(&path))
+    }
+
+    pub fn is_busy(&self) -> bool {
+        self.target.is_some() && !self.loaded
+    }
+}
@@ -0,0 +1,316 @@
+use crate::fm_page::*;
+use crate::cursor::PageCursor;
+use crate::log_sheet::append_msg_rows;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TryRecvError};
+use std::collections::VecDeque;
+
+/// Output lines kept by an `OutputSheet`, older ones are dropped.
+const MAX_OUTPUT_LINES : usize = 10000;
+
+/// Output lines buffered between the reader threads and `poll`. A
+/// command writing faster than that waits for the pipe.
+const OUTPUT_BUFFER_LINES : usize = 1000;
+
+/// How often the waiter thread checks if the command exited or was
+/// canceled.
+const WAIT_INTERVAL_MS : u64 = 50;
+
+pub enum ShellOutput {
+    Stdout(String),
+    Stderr(String),
+    /// The command finished, with a description of its exit status.
+    Exit(String),
+}
+
+/// A command run by `sh -c`, whose output is collected by threads.
+/// Canceling or dropping the job kills the command and everything it
+/// started.
+pub struct ShellJob {
+    pub command: String,
+    pub status:  Option<String>,
+    cancel:      Arc<AtomicBool>,
+    rx:          Receiver<ShellOutput>,
+}
+
+fn read_lines<R, F>(pipe: R, tx: SyncSender<ShellOutput>, wrap: F)
+    -> std::thread::JoinHandle<()>
+    where R: std::io::Read + Send + 'static,
+          F: Fn(String) -> ShellOutput + Send + 'static {
+
+    std::thread::spawn(move || {
+        use std::io::BufRead;
+        let mut rd = std::io::BufReader::new(pipe);
+        let mut buf = vec![];
+        loop {
+            buf.clear();
+            match rd.read_until(b'\n', &mut buf) {
+                Ok(0) | Err(_) => break,
+                Ok(_) => {
+                    while buf.last() == Some(&b'\n') || buf.last() == Some(&b'\r') {
+                        buf.pop();
+                    }
+                    // Keep draining even if nobody listens anymore, until
+                    // the command is killed:
+                    let _ = tx.send(wrap(String::from_utf8_lossy(&buf).to_string()));
+                },
+            }
+        }
+    })
+}
+
+/// Runs the command in a process group of its own, so the commands of
+/// a pipeline can be killed together.
+#[cfg(unix)]
+fn new_process_group(cmd: &mut std::process::Command) {
+    use std::os::unix::process::CommandExt;
+    cmd.process_group(0);
+}
+
+#[cfg(not(unix))]
+fn new_process_group(_cmd: &mut std::process::Command) { }
+
+/// Kills the process group of the child. The child is not waited for
+/// yet, so its id can't be reused in the meantime.
+#[cfg(unix)]
+fn kill_process_group(child: &mut std::process::Child) {
+    unsafe { libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL); }
+}
+
+#[cfg(not(unix))]
+fn kill_process_group(child: &mut std::process::Child) {
+    let _ = child.kill();
+}
+
+impl ShellJob {
+    pub fn start(command: &str, cwd: &std::path::Path) -> std::io::Result<ShellJob> {
+        use std::process::{Command, Stdio};
+
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c")
+           .arg(command)
+           .current_dir(cwd)
+           .stdin(Stdio::null())
+           .stdout(Stdio::piped())
+           .stderr(Stdio::piped());
+        new_process_group(&mut cmd);
+        let mut child = cmd.spawn()?;
+
+        let (tx, rx) = sync_channel(OUTPUT_BUFFER_LINES);
+        let out = read_lines(child.stdout.take().unwrap(), tx.clone(), ShellOutput::Stdout);
+        let err = read_lines(child.stderr.take().unwrap(), tx.clone(), ShellOutput::Stderr);
+
+        let cancel = Arc::new(AtomicBool::new(false));
+        let stop   = cancel.clone();
+        std::thread::spawn(move || {
+            let mut killed = false;
+            let status =
+                loop {
+                    if !killed && stop.load(Ordering::Relaxed) {
+                        kill_process_group(&mut child);
+                        killed = true;
+                    }
+                    match child.try_wait() {
+                        Ok(Some(st)) => break st.to_string(),
+                        Ok(None) =>
+                            std::thread::sleep(std::time::Duration::from_millis(WAIT_INTERVAL_MS)),
+                        Err(e) => break e.to_string(),
+                    }
+                };
+            let _ = out.join();
+            let _ = err.join();
+            let status = if killed { format!("{}, canceled", status) } else { status };
+            let _ = tx.send(ShellOutput::Exit(status));
+        });
+
+        Ok(ShellJob {
+            command: command.to_string(),
+            status:  None,
+            cancel,
+            rx,
+        })
+    }
+
+    /// Kills the command, the job finishes with the exit status of the
+    /// kill.
+    pub fn cancel(&self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns the output that arrived since the last call, at most as
+    /// many lines as are buffered.
+    pub fn poll(&mut self) -> Vec<ShellOutput> {
+        let mut out = vec![];
+        while self.status.is_none() && out.len() < OUTPUT_BUFFER_LINES {
+            match self.rx.try_recv() {
+                Ok(ShellOutput::Exit(status)) => {
+                    self.status = Some(status.clone());
+                    out.push(ShellOutput::Exit(status));
+                },
+                Ok(line) => out.push(line),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.status = Some(String::from("unknown exit status"));
+                },
+            }
+        }
+        out
+    }
+
+    pub fn is_running(&self) -> bool { self.status.is_none() }
+}
+
+impl Drop for ShellJob {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+/// Shows the output of a shell command while it runs. stderr lines are
+/// drawn in a different color.
+pub struct OutputSheet {
+    pub job:             ShellJob,
+    /// The output lines, whether they are from stderr and the number of
+    /// rows they are wrapped to.
+    pub lines:           VecDeque<(String, bool, usize)>,
+    pub rows:            VecDeque<(String, bool)>,
+    pub last_width_in_m_chars: usize,
+    pub render_feedback: RenderFeedback,
+    pub cursor:          PageCursor,
+    pub rendered:        TableRef,
+}
+
+impl OutputSheet {
+    pub fn new(job: ShellJob) -> Self {
+        OutputSheet {
+            job,
+            lines:           VecDeque::new(),
+            rows:            VecDeque::new(),
+            last_width_in_m_chars: 0,
+            render_feedback: RenderFeedback::new(),
+            cursor:          PageCursor::new(),
+            rendered:        Table::new_ref(),
+        }
+    }
+
+    fn push_line(&mut self, line: String, is_err: bool) {
+        let mut rows = vec![];
+        append_msg_rows(&mut rows, &line, self.render_feedback.width_in_m_chars);
+        self.lines.push_back((line, is_err, rows.len()));
+        self.rows.extend(rows.into_iter().map(|r| (r, is_err)));
+
+        if self.lines.len() > MAX_OUTPUT_LINES {
+            if let Some((_, _, row_count)) = self.lines.pop_front() {
+                self.rows.drain(0..row_count);
+                self.cursor.cursor_idx = self.cursor.cursor_idx.saturating_sub(row_count);
+            }
+        }
+    }
+
+    fn rewrap(&mut self) {
+        self.rows.clear();
+        for (line, is_err, row_count) in self.lines.iter_mut() {
+            let mut rows = vec![];
+            append_msg_rows(&mut rows, line, self.render_feedback.width_in_m_chars);
+            *row_count = rows.len();
+            self.rows.extend(rows.into_iter().map(|r| (r, *is_err)));
+        }
+        self.last_width_in_m_chars = self.render_feedback.width_in_m_chars;
+    }
+}
+
+impl FmPage for OutputSheet {
+    fn len(&self) -> usize { self.rows.len() }
+    fn get_scroll_offs(&self) -> usize { self.cursor.scroll_offset }
+    fn is_cursor_idx(&self, idx: usize) -> bool { self.cursor.is_cursor_idx(idx) }
+    fn is_selected(&self, _idx: usize) -> bool { false }
+    fn is_highlighted(&self, _idx: usize) -> bool { false }
+    fn needs_repage(&self) -> bool { true }
+    fn needs_redraw(&self) -> bool { true }
+    fn sort_by_column(&mut self, _col_idx: usize) { }
+
+    fn set_render_feedback(&mut self, fb: RenderFeedback) {
+        let width_in_m_chars = fb.width_in_m_chars;
+        self.render_feedback = fb;
+        if self.last_width_in_m_chars != width_in_m_chars {
+            self.rewrap();
+        }
+    }
+
+    fn is_inside_screen_rect(&self, x: i32, y: i32) -> bool {
+        self.render_feedback.is_inside_screen_rect(x, y)
+    }
+
+    fn is_busy(&self) -> bool { self.job.is_running() }
+    fn cancel(&mut self) { self.job.cancel(); }
+
+    fn update(&mut self) -> Option<PageEvent> {
+        let output = self.job.poll();
+        if output.is_empty() {
+            return None;
+        }
+
+        // Keep following the output while the cursor is on the last row:
+        let follow = self.cursor.cursor_idx + 1 >= self.rows.len();
+        let mut finished = false;
+        for out in output {
+            match out {
+                ShellOutput::Stdout(line) => self.push_line(line, false),
+                ShellOutput::Stderr(line) => self.push_line(line, true),
+                ShellOutput::Exit(status) => {
+                    self.push_line(format!("[{}]", status), false);
+                    finished = true;
+                },
+            }
+        }
+        if follow {
+            self.cursor.cursor_idx = self.rows.len().saturating_sub(1);
+        }
+        self.cursor.do_control(self.rows.len(), &self.render_feedback, PageControl::Refresh);
+
+        if finished {
+            Some(PageEvent::RefreshDirs)
+        } else {
+            Some(PageEvent::Redraw)
+        }
+    }
+
+    fn do_control(&mut self, ctrl: PageControl) -> Option<PageEvent> {
+        match ctrl {
+            PageControl::Back => Some(PageEvent::Close),
+            _ => {
+                self.cursor.do_control(self.rows.len(), &self.render_feedback, ctrl);
+                None
+            },
+        }
+    }
+
+    fn as_drawable_table(&mut self) -> TableRef {
+        let status =
+            match &self.job.status {
+                Some(status) => status.clone(),
+                None         => String::from("running"),
+            };
+
+        self.rendered =
+            std::rc::Rc::new(std::cell::RefCell::new(Table {
+                title: format!("$ {} [{}]", self.job.command, status),
+                row_gap: 2,
+                col_gap: 0,
+                columns: vec![
+                    Column {
+                        head: String::from("output"),
+                        size: ColumnSizing::ExpandFract(1),
+                        calc_size: None,
+                        rows: self.rows.iter().map(|(text, is_err)| StyleString {
+                            text: text.clone(),
+                            style: if *is_err { Style::Error } else { Style::Default },
+                            highlight: vec![],
+                        }).collect(),
+                    },
+                ],
+            }));
+        self.rendered.clone()
+    }
+}
@@ -12,6 +12,7 @@ pub enum TextInputAction {
     Insert(String),
     Replace(String),
     Clear,
+    Backspace,
     CursorRight,
     CursorLeft,
     CursorBegin,
@@ -62,6 +63,15 @@ impl TextInputLine {
                 self.cursor_pos = 0;
                 self.history_pos = None;
             },
+            TextInputAction::Backspace => {
+                if self.cursor_pos > 0 {
+                    let left : String = self.text.chars().take(self.cursor_pos - 1).collect();
+                    let right : String = self.text.chars().skip(self.cursor_pos).collect();
+                    self.text = left + &right;
+                    self.cursor_pos -= 1;
+                }
+                self.history_pos = None;
+            },
             TextInputAction::CursorLeft => {
                 if self.cursor_pos > 0 {
                     self.cursor_pos -= 1;
@@ -0,0 +1,639 @@
+use crate::fm_page::*;
+use crate::log_sheet::append_msg_rows;
+use std::io::{Read, Seek, SeekFrom, BufRead, BufReader};
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+
+/// Number of lines between two entries of the line offset index.
+const INDEX_STRIDE : usize = 256;
+/// Size of the chunks the indexer and the encoding detection read.
+const CHUNK_SIZE   : usize = 64 * 1024;
+/// Rows built for the first page, before the render feedback is known.
+const DEFAULT_ROWS : usize = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TextEncoding {
+    Utf8,
+    Utf16LE,
+    Utf16BE,
+    Latin1,
+}
+
+impl TextEncoding {
+    pub fn name(&self) -> &'static str {
+        match self {
+            TextEncoding::Utf8    => "UTF-8",
+            TextEncoding::Utf16LE => "UTF-16LE",
+            TextEncoding::Utf16BE => "UTF-16BE",
+            TextEncoding::Latin1  => "Latin-1",
+        }
+    }
+
+    /// Looks for a byte order mark and otherwise checks if the sample
+    /// is valid UTF-8. Returns the encoding and the length of the BOM.
+    pub fn detect(sample: &[u8]) -> (TextEncoding, u64) {
+        if sample.starts_with(&[0xEF, 0xBB, 0xBF]) {
+            (TextEncoding::Utf8, 3)
+        } else if sample.starts_with(&[0xFF, 0xFE]) {
+            (TextEncoding::Utf16LE, 2)
+        } else if sample.starts_with(&[0xFE, 0xFF]) {
+            (TextEncoding::Utf16BE, 2)
+        } else {
+            match std::str::from_utf8(sample) {
+                Ok(_) => (TextEncoding::Utf8, 0),
+                // A multi byte character cut off at the end of the sample
+                // is still valid UTF-8:
+                Err(e) if e.error_len().is_none() => (TextEncoding::Utf8, 0),
+                Err(_) => (TextEncoding::Latin1, 0),
+            }
+        }
+    }
+
+    fn is_wide(&self) -> bool {
+        match self {
+            TextEncoding::Utf16LE | TextEncoding::Utf16BE => true,
+            _ => false,
+        }
+    }
+
+    fn is_newline(&self, unit: &[u8]) -> bool {
+        match self {
+            TextEncoding::Utf16LE => unit == [0x0A, 0x00],
+            TextEncoding::Utf16BE => unit == [0x00, 0x0A],
+            _                     => unit[0] == b'\n',
+        }
+    }
+
+    pub fn decode(&self, bytes: &[u8]) -> String {
+        let s = match self {
+            TextEncoding::Utf8   => String::from_utf8_lossy(bytes).to_string(),
+            TextEncoding::Latin1 => bytes.iter().map(|b| *b as char).collect(),
+            TextEncoding::Utf16LE | TextEncoding::Utf16BE => {
+                let units : Vec<u16> =
+                    bytes.chunks(2).filter(|c| c.len() == 2).map(|c|
+                        if *self == TextEncoding::Utf16LE {
+                            u16::from_le_bytes([c[0], c[1]])
+                        } else {
+                            u16::from_be_bytes([c[0], c[1]])
+                        }).collect();
+                String::from_utf16_lossy(&units)
+            },
+        };
+        s.trim_end_matches(|c| c == '\n' || c == '\r')
+         .replace('\t', "    ")
+    }
+}
+
+/// A text file that is read line by line on demand, instead of loading
+/// it completely into memory.
+#[derive(Debug, Clone)]
+pub struct TextFile {
+    pub path:       std::path::PathBuf,
+    pub encoding:   TextEncoding,
+    pub data_start: u64,
+}
+
+impl TextFile {
+    pub fn open(path: &std::path::Path) -> std::io::Result<TextFile> {
+        let mut f = std::fs::File::open(path)?;
+        let mut sample = vec![0; CHUNK_SIZE];
+        let len = read_full(&mut f, &mut sample)?;
+        let (encoding, data_start) = TextEncoding::detect(&sample[0..len]);
+        Ok(TextFile { path: path.to_path_buf(), encoding, data_start })
+    }
+
+    /// Returns a reader positioned at the given byte offset.
+    pub fn reader_at(&self, offs: u64) -> std::io::Result<BufReader<std::fs::File>> {
+        let mut f = std::fs::File::open(&self.path)?;
+        f.seek(SeekFrom::Start(offs))?;
+        Ok(BufReader::new(f))
+    }
+
+    /// Reads the raw bytes of the next line including the line terminator.
+    /// Returns `false` at the end of the file.
+    pub fn read_line_bytes<R: BufRead>(&self, rd: &mut R, line: &mut Vec<u8>)
+        -> std::io::Result<bool> {
+
+        line.clear();
+        if !self.encoding.is_wide() {
+            return Ok(rd.read_until(b'\n', line)? > 0);
+        }
+
+        let mut unit = [0u8; 2];
+        loop {
+            if read_full(rd, &mut unit)? < 2 {
+                return Ok(!line.is_empty());
+            }
+            line.extend_from_slice(&unit);
+            if self.encoding.is_newline(&unit) {
+                return Ok(true);
+            }
+        }
+    }
+}
+
+/// Checks the start of the file for NUL bytes, which do not appear in
+/// text files, except in UTF-16 encoded ones.
+pub fn is_binary_file(path: &std::path::Path) -> std::io::Result<bool> {
+    let mut f = std::fs::File::open(path)?;
+    let mut sample = vec![0; CHUNK_SIZE];
+    let len = read_full(&mut f, &mut sample)?;
+    let (encoding, _) = TextEncoding::detect(&sample[0..len]);
+    Ok(!encoding.is_wide() && sample[0..len].contains(&0))
+}
+
+fn read_full<R: Read>(rd: &mut R, buf: &mut [u8]) -> std::io::Result<usize> {
+    let mut len = 0;
+    while len < buf.len() {
+        let n = rd.read(&mut buf[len..])?;
+        if n == 0 { break; }
+        len += n;
+    }
+    Ok(len)
+}
+
+/// Progress report of the line indexer thread.
+struct IndexUpdate {
+    checkpoints: Vec<u64>,
+    line_count:  usize,
+    done:        bool,
+}
+
+/// Scans the whole file for line starts and reports the byte offset of
+/// every `INDEX_STRIDE`th line.
+fn index_lines(tf: TextFile, tx: std::sync::mpsc::Sender<IndexUpdate>) {
+    let mut f = match tf.reader_at(tf.data_start) {
+        Ok(f)  => f,
+        Err(_) => {
+            let _ = tx.send(IndexUpdate { checkpoints: vec![], line_count: 0, done: true });
+            return;
+        },
+    };
+
+    let unit_len     = if tf.encoding.is_wide() { 2 } else { 1 };
+    let mut offs     = tf.data_start;
+    let mut line_cnt = 0;
+    let mut at_start = true;
+    let mut new_cps  = vec![];
+    let mut buf      = vec![0; CHUNK_SIZE];
+    let mut chunks   = 0;
+
+    loop {
+        let len = match read_full(&mut f, &mut buf) {
+            Ok(len) => len,
+            Err(_)  => 0,
+        };
+        if len == 0 { break; }
+
+        for unit in buf[0..len].chunks(unit_len) {
+            if at_start {
+                if line_cnt % INDEX_STRIDE == 0 {
+                    new_cps.push(offs);
+                }
+                line_cnt += 1;
+                at_start = false;
+            }
+            offs += unit.len() as u64;
+            if unit.len() == unit_len && tf.encoding.is_newline(unit) {
+                at_start = true;
+            }
+        }
+
+        chunks += 1;
+        if chunks % 16 == 0 {
+            let upd = IndexUpdate {
+                checkpoints: std::mem::take(&mut new_cps),
+                line_count:  line_cnt,
+                done:        false,
+            };
+            if tx.send(upd).is_err() { return; }
+        }
+    }
+
+    let _ = tx.send(IndexUpdate { checkpoints: new_cps, line_count: line_cnt, done: true });
+}
+
+/// Searches forward from `start_line` and returns the first line
+/// containing `needle`.
+fn search_lines(tf: &TextFile, start_offs: u64, start_line: usize,
+                skip: usize, needle: &str) -> Option<usize> {
+    let mut rd   = tf.reader_at(start_offs).ok()?;
+    let mut buf  = vec![];
+    let mut line = start_line;
+    while tf.read_line_bytes(&mut rd, &mut buf).ok()? {
+        if line >= start_line + skip
+           && !find_char_ranges(&tf.encoding.decode(&buf), needle).is_empty() {
+            return Some(line);
+        }
+        line += 1;
+    }
+    None
+}
+
+/// Returns the character ranges of all occurences of `needle` in `text`.
+/// The comparison ignores case unless `needle` contains upper case letters.
+pub fn find_char_ranges(text: &str, needle: &str) -> Vec<(usize, usize)> {
+    let ignore_case = !needle.chars().any(|c| c.is_uppercase());
+    let fold = |c: char| {
+        if ignore_case { c.to_lowercase().next().unwrap_or(c) } else { c }
+    };
+
+    let hay : Vec<char> = text.chars().map(fold).collect();
+    let ndl : Vec<char> = needle.chars().map(fold).collect();
+
+    let mut ranges = vec![];
+    if ndl.is_empty() || ndl.len() > hay.len() {
+        return ranges;
+    }
+
+    let mut i = 0;
+    while i + ndl.len() <= hay.len() {
+        if hay[i..(i + ndl.len())] == ndl[..] {
+            ranges.push((i, i + ndl.len()));
+            i += ndl.len();
+        } else {
+            i += 1;
+        }
+    }
+    ranges
+}
+
+pub struct TextViewSheet {
+    pub file:            TextFile,
+    pub checkpoints:     std::vec::Vec<u64>,
+    pub line_count:      usize,
+    indexing:            Option<Receiver<IndexUpdate>>,
+    searching:           Option<Receiver<Option<usize>>>,
+    pub search:          Option<String>,
+    pub status:          String,
+    pub wrap:            bool,
+    pub top_line:        usize,
+    pub cursor_line:     usize,
+    pub window:          (usize, std::vec::Vec<String>),
+    pub row_lines:       std::vec::Vec<usize>,
+    pub render_feedback: RenderFeedback,
+    pub rendered:        TableRef,
+}
+
+impl TextViewSheet {
+    pub fn open(path: &std::path::Path) -> std::io::Result<TextViewSheet> {
+        Ok(Self::from_file(TextFile::open(path)?))
+    }
+
+    /// Creates the page for an already opened file and starts indexing
+    /// its lines in the background.
+    pub fn from_file(file: TextFile) -> TextViewSheet {
+        let (tx, rx) = channel();
+        let tf = file.clone();
+        std::thread::spawn(move || index_lines(tf, tx));
+
+        TextViewSheet {
+            file,
+            checkpoints:     vec![],
+            line_count:      0,
+            indexing:        Some(rx),
+            searching:       None,
+            search:          None,
+            status:          String::from(""),
+            wrap:            false,
+            top_line:        0,
+            cursor_line:     0,
+            window:          (0, vec![]),
+            row_lines:       vec![],
+            render_feedback: RenderFeedback::new(),
+            rendered:        Table::new_ref(),
+        }
+    }
+
+    fn visible_rows(&self) -> usize {
+        if self.render_feedback.recent_line_count == 0 {
+            DEFAULT_ROWS
+        } else {
+            self.render_feedback.recent_line_count
+        }
+    }
+
+    /// Reads `count` lines starting at `first` via the nearest index
+    /// checkpoint.
+    fn read_lines(&self, first: usize, count: usize) -> Vec<String> {
+        let mut lines = vec![];
+        let cp_idx = first / INDEX_STRIDE;
+        let offs = match self.checkpoints.get(cp_idx) {
+            Some(offs) => *offs,
+            None       => return lines,
+        };
+
+        let mut rd = match self.file.reader_at(offs) {
+            Ok(rd)  => rd,
+            Err(_)  => return lines,
+        };
+
+        let mut buf  = vec![];
+        let mut line = cp_idx * INDEX_STRIDE;
+        while lines.len() < count {
+            match self.file.read_line_bytes(&mut rd, &mut buf) {
+                Ok(true) => (),
+                _        => break,
+            }
+            if line >= first {
+                lines.push(self.file.encoding.decode(&buf));
+            }
+            line += 1;
+        }
+        lines
+    }
+
+    fn window_lines(&mut self, first: usize, count: usize) -> &[String] {
+        let (w_first, w_lines) = &self.window;
+        let covered = first >= *w_first
+            && (first + count <= w_first + w_lines.len()
+                || w_first + w_lines.len() >= self.line_count);
+        if !covered {
+            let lines = self.read_lines(first, count);
+            self.window = (first, lines);
+        }
+
+        let (w_first, w_lines) = &self.window;
+        let start = (first - w_first).min(w_lines.len());
+        let end   = (start + count).min(w_lines.len());
+        &w_lines[start..end]
+    }
+
+    fn ensure_cursor_visible(&mut self) {
+        if self.line_count > 0 && self.cursor_line >= self.line_count {
+            self.cursor_line = self.line_count - 1;
+        }
+
+        if self.cursor_line < self.top_line {
+            self.top_line = self.cursor_line;
+            return;
+        }
+
+        // In wrap mode a line may take more than one row, so the
+        // last line drawn tells how many lines actually fit:
+        let shown =
+            if self.wrap && !self.row_lines.is_empty() {
+                let last = *self.row_lines.last().unwrap();
+                if last > self.top_line { last - self.top_line } else { 1 }
+            } else {
+                self.visible_rows()
+            };
+
+        if self.cursor_line >= self.top_line + shown {
+            self.top_line = self.cursor_line + 1 - shown;
+        }
+    }
+
+    fn start_search(&mut self, skip: usize) {
+        let needle = match &self.search {
+            Some(needle) => needle.clone(),
+            None         => return,
+        };
+
+        let start_line = self.cursor_line - (self.cursor_line % INDEX_STRIDE);
+        let start_offs = match self.checkpoints.get(start_line / INDEX_STRIDE) {
+            Some(offs) => *offs,
+            None       => self.file.data_start,
+        };
+        let skip = self.cursor_line - start_line + skip;
+
+        let (tx, rx) = channel();
+        let tf = self.file.clone();
+        std::thread::spawn(move || {
+            let _ = tx.send(search_lines(&tf, start_offs, start_line, skip, &needle));
+        });
+        self.status = format!("searching '{}'...", self.search.as_ref().unwrap());
+        self.searching = Some(rx);
+    }
+}
+
+impl FmPage for TextViewSheet {
+    fn len(&self) -> usize { self.line_count }
+    fn cursor_line(&self) -> Option<usize> { Some(self.cursor_line + 1) }
+    fn get_scroll_offs(&self) -> usize { 0 }
+    fn is_cursor_idx(&self, idx: usize) -> bool {
+        self.row_lines.get(idx) == Some(&self.cursor_line)
+    }
+    fn is_selected(&self, _idx: usize) -> bool { false }
+    fn is_highlighted(&self, _idx: usize) -> bool { false }
+    fn needs_repage(&self) -> bool { true }
+    fn needs_redraw(&self) -> bool { true }
+    fn sort_by_column(&mut self, _col_idx: usize) { }
+
+    fn set_render_feedback(&mut self, fb: RenderFeedback) {
+        self.render_feedback = fb;
+    }
+
+    fn is_inside_screen_rect(&self, x: i32, y: i32) -> bool {
+        self.render_feedback.is_inside_screen_rect(x, y)
+    }
+
+    fn is_busy(&self) -> bool {
+        self.indexing.is_some() || self.searching.is_some()
+    }
+
+    fn update(&mut self) -> Option<PageEvent> {
+        let mut changed = false;
+
+        if let Some(rx) = &self.indexing {
+            loop {
+                match rx.try_recv() {
+                    Ok(upd) => {
+                        self.checkpoints.extend_from_slice(&upd.checkpoints);
+                        self.line_count = upd.line_count;
+                        changed = true;
+                        if upd.done {
+                            self.indexing = None;
+                            break;
+                        }
+                    },
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => {
+                        self.indexing = None;
+                        break;
+                    },
+                }
+            }
+        }
+
+        if let Some(rx) = &self.searching {
+            match rx.try_recv() {
+                Ok(found) => {
+                    self.searching = None;
+                    changed = true;
+                    if let Some(line) = found {
+                        self.status = String::from("");
+                        self.cursor_line = line;
+                        let half = self.visible_rows() / 2;
+                        self.top_line = if line > half { line - half } else { 0 };
+                    } else {
+                        self.status = format!("'{}' not found", self.search.as_ref().unwrap());
+                    }
+                },
+                Err(TryRecvError::Empty) => (),
+                Err(TryRecvError::Disconnected) => {
+                    self.searching = None;
+                },
+            }
+        }
+
+        if changed { Some(PageEvent::Redraw) } else { None }
+    }
+
+    fn do_control(&mut self, ctrl: PageControl) -> Option<PageEvent> {
+        match ctrl {
+            PageControl::Back => { return Some(PageEvent::Close); },
+            PageControl::CursorDown => {
+                self.cursor_line += 1;
+                self.ensure_cursor_visible();
+            },
+            PageControl::CursorUp => {
+                if self.cursor_line > 0 {
+                    self.cursor_line -= 1;
+                }
+                self.ensure_cursor_visible();
+            },
+            PageControl::Scroll(amount) => {
+                let amount = amount * 5;
+                if amount < 0 {
+                    self.top_line = self.top_line.saturating_sub((-amount) as usize);
+                } else {
+                    self.top_line += amount as usize;
+                }
+                if self.top_line >= self.line_count {
+                    self.top_line = self.line_count.saturating_sub(1);
+                }
+            },
+            PageControl::Click((x, y)) => {
+                let fb = &self.render_feedback;
+                if x < fb.start_rows.0 || x > fb.end_rows.0
+                   || y < fb.start_rows.1 || y > fb.end_rows.1
+                   || fb.row_height <= 0 {
+                    return None;
+                }
+                let row = ((y - fb.start_rows.1) / fb.row_height) as usize;
+                if let Some(line) = self.row_lines.get(row) {
+                    self.cursor_line = *line;
+                }
+            },
+            PageControl::Search(needle) => {
+                self.search = if needle.is_empty() { None } else { Some(needle) };
+                self.start_search(0);
+            },
+            PageControl::SearchNext => {
+                self.start_search(1);
+            },
+            PageControl::Goto(line) => {
+                match line.trim().parse::<usize>() {
+                    Ok(line) if line > 0 => {
+                        self.status = String::from("");
+                        self.cursor_line = line - 1;
+                        let half = self.visible_rows() / 2;
+                        self.top_line = self.cursor_line.saturating_sub(half);
+                        self.ensure_cursor_visible();
+                    },
+                    _ => { self.status = format!("bad line number '{}'", line); },
+                }
+            },
+            PageControl::CycleMode => {
+                self.wrap = !self.wrap;
+                self.ensure_cursor_visible();
+            },
+            _ => (),
+        }
+        None
+    }
+
+    fn as_drawable_table(&mut self) -> TableRef {
+        let rows  = self.visible_rows();
+        let top   = self.top_line;
+        let wrap  = self.wrap;
+        let width =
+            self.render_feedback.width_in_m_chars.saturating_sub(8).max(10);
+        let search = self.search.clone();
+        let lines : Vec<String> = self.window_lines(top, rows).to_vec();
+
+        let mut nums = vec![];
+        let mut text = vec![];
+        self.row_lines.clear();
+
+        for (i, line) in lines.iter().enumerate() {
+            let line_idx = top + i;
+            let ranges = match &search {
+                Some(needle) => find_char_ranges(line, needle),
+                None         => vec![],
+            };
+
+            let mut parts = vec![];
+            if wrap {
+                append_msg_rows(&mut parts, line, width);
+            } else {
+                parts.push(line.clone());
+            }
+
+            let mut part_start = 0;
+            for (part_idx, part) in parts.into_iter().enumerate() {
+                let part_len = part.chars().count();
+                let part_end = part_start + part_len;
+
+                nums.push(StyleString {
+                    text: if part_idx == 0 { format!("{:>7}", line_idx + 1) }
+                          else { String::from("") },
+                    style: Style::Special,
+                    highlight: vec![],
+                });
+                text.push(StyleString {
+                    text: part,
+                    style: Style::Default,
+                    highlight: ranges.iter().filter_map(|(s, e)| {
+                        if *e <= part_start || *s >= part_end {
+                            None
+                        } else {
+                            Some(((*s).max(part_start) - part_start,
+                                  (*e).min(part_end) - part_start))
+                        }
+                    }).collect(),
+                });
+                self.row_lines.push(line_idx);
+
+                part_start = part_end;
+            }
+
+            if self.row_lines.len() >= rows {
+                break;
+            }
+        }
+
+        let mut title = format!("{} [{}, {} lines{}{}]",
+            self.file.path.to_string_lossy(),
+            self.file.encoding.name(),
+            self.line_count,
+            if self.indexing.is_some() { "..." } else { "" },
+            if self.wrap { ", wrap" } else { "" });
+        if !self.status.is_empty() {
+            title += " ";
+            title += &self.status;
+        }
+
+        self.rendered =
+            std::rc::Rc::new(std::cell::RefCell::new(Table {
+                title,
+                row_gap: 2,
+                col_gap: 4,
+                columns: vec![
+                    Column {
+                        head: String::from("line"),
+                        size: ColumnSizing::TextWidth(String::from("MMMMMMM")),
+                        calc_size: None,
+                        rows: nums,
+                    },
+                    Column {
+                        head: String::from("text"),
+                        size: ColumnSizing::ExpandFract(1),
+                        calc_size: None,
+                        rows: text,
+                    },
+                ],
+            }));
+        self.rendered.clone()
+    }
+}
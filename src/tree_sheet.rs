@@ -0,0 +1,282 @@
+use crate::fm_page::*;
+use crate::cursor::PageCursor;
+use crate::path_sheet::{PathRecordType, read_path_records};
+
+/// Rows built when the page wasn't drawn yet.
+const DEFAULT_ROWS : usize = 50;
+
+/// One visible row of the tree.
+#[derive(Debug, Clone)]
+struct TreeNode {
+    path:      std::path::PathBuf,
+    path_type: PathRecordType,
+    depth:     usize,
+    expanded:  bool,
+    /// Whether this is the last child of its parent.
+    is_last:   bool,
+    /// For every ancestor below the root, whether more siblings follow
+    /// it, which decides where the guide lines continue.
+    guides:    Vec<bool>,
+}
+
+impl TreeNode {
+    fn is_dir(&self) -> bool { self.path_type == PathRecordType::Dir }
+
+    /// The name with the indentation and guide characters.
+    fn label(&self) -> String {
+        if self.depth == 0 {
+            return self.path.to_string_lossy().to_string();
+        }
+
+        let mut s = String::new();
+        for more in self.guides.iter() {
+            s += if *more { "│  " } else { "   " };
+        }
+        s += if self.is_last { "└─ " } else { "├─ " };
+        s += &self.path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+        match self.path_type {
+            PathRecordType::Dir if self.expanded => s += "/",
+            PathRecordType::Dir                  => s += "/ …",
+            _ => (),
+        }
+        s
+    }
+}
+
+/// Shows the directory hierarchy below a path. The visible rows are kept
+/// in a flat list, expanding a directory reads its children and inserts
+/// them after it. Only the rows on screen are built into the table.
+pub struct TreeSheet {
+    pub root:            std::path::PathBuf,
+    nodes:               Vec<TreeNode>,
+    pub render_feedback: RenderFeedback,
+    pub cursor:          PageCursor,
+    pub rendered:        TableRef,
+}
+
+impl TreeSheet {
+    pub fn new(root: &std::path::Path) -> Self {
+        let mut ts = TreeSheet {
+            root:            root.to_path_buf(),
+            nodes:           vec![TreeNode {
+                path:      root.to_path_buf(),
+                path_type: PathRecordType::Dir,
+                depth:     0,
+                expanded:  false,
+                is_last:   true,
+                guides:    vec![],
+            }],
+            render_feedback: RenderFeedback::new(),
+            cursor:          PageCursor::new(),
+            rendered:        Table::new_ref(),
+        };
+        ts.expand(0);
+        ts
+    }
+
+    fn visible_rows(&self) -> usize {
+        if self.render_feedback.recent_line_count == 0 {
+            DEFAULT_ROWS
+        } else {
+            self.render_feedback.recent_line_count
+        }
+    }
+
+    /// The index after the last descendant of the node at `idx`.
+    fn subtree_end(&self, idx: usize) -> usize {
+        let depth = self.nodes[idx].depth;
+        let mut end = idx + 1;
+        while end < self.nodes.len() && self.nodes[end].depth > depth {
+            end += 1;
+        }
+        end
+    }
+
+    /// Reads the children of a directory node and inserts them after it.
+    /// Returns an error message if the directory can't be read.
+    fn expand(&mut self, idx: usize) -> Option<String> {
+        let node = &self.nodes[idx];
+        if !node.is_dir() || node.expanded {
+            return None;
+        }
+
+        let mut records =
+            match read_path_records(&node.path) {
+                Ok(records) => records,
+                Err(e) => {
+                    return Some(format!("Can't read {}: {:?}", node.path.to_string_lossy(), e));
+                },
+            };
+        records.sort_by(|a, b| {
+            let a_dir = a.path_type == PathRecordType::Dir;
+            let b_dir = b.path_type == PathRecordType::Dir;
+            b_dir.cmp(&a_dir).then_with(|| {
+                let s1 = a.path.file_name().unwrap_or_default().to_string_lossy().to_lowercase();
+                let s2 = b.path.file_name().unwrap_or_default().to_string_lossy().to_lowercase();
+                s1.cmp(&s2)
+            })
+        });
+
+        let mut guides = node.guides.clone();
+        if node.depth > 0 {
+            guides.push(!node.is_last);
+        }
+        let depth = node.depth + 1;
+        let count = records.len();
+        let children =
+            records.into_iter().enumerate().map(|(i, pr)| TreeNode {
+                path:      pr.path,
+                path_type: pr.path_type,
+                depth,
+                expanded:  false,
+                is_last:   i + 1 == count,
+                guides:    guides.clone(),
+            });
+
+        self.nodes[idx].expanded = true;
+        let tail = self.nodes.split_off(idx + 1);
+        self.nodes.extend(children);
+        self.nodes.extend(tail);
+        None
+    }
+
+    fn collapse(&mut self, idx: usize) {
+        let end = self.subtree_end(idx);
+        self.nodes.drain((idx + 1)..end);
+        self.nodes[idx].expanded = false;
+    }
+
+    fn parent_idx(&self, idx: usize) -> Option<usize> {
+        let depth = self.nodes[idx].depth;
+        (0..idx).rev().find(|i| self.nodes[*i].depth < depth)
+    }
+
+    /// Collapses and expands the expanded directories again, to pick up
+    /// changes on disk. The cursor stays on the same path if it still
+    /// exists.
+    fn reload(&mut self) {
+        let cursor = self.cursor_path();
+        let expanded : Vec<std::path::PathBuf> =
+            self.nodes.iter().filter(|n| n.expanded).map(|n| n.path.clone()).collect();
+
+        self.collapse(0);
+        let mut idx = 0;
+        while idx < self.nodes.len() {
+            if expanded.contains(&self.nodes[idx].path) {
+                self.expand(idx);
+            }
+            idx += 1;
+        }
+
+        if let Some(path) = cursor {
+            if let Some(idx) = self.nodes.iter().position(|n| n.path == path) {
+                self.cursor.cursor_idx = idx;
+            }
+        }
+    }
+
+    fn move_cursor(&mut self, idx: usize) {
+        self.cursor.cursor_idx = idx;
+        self.cursor.do_control(self.nodes.len(), &self.render_feedback, PageControl::Refresh);
+    }
+}
+
+impl FmPage for TreeSheet {
+    fn len(&self) -> usize { self.nodes.len() }
+    // The table only holds the rows on screen, starting at the scroll
+    // offset:
+    fn get_scroll_offs(&self) -> usize { 0 }
+    fn is_cursor_idx(&self, idx: usize) -> bool {
+        self.cursor.is_cursor_idx(self.cursor.scroll_offset + idx)
+    }
+    fn is_selected(&self, _idx: usize) -> bool { false }
+    fn is_highlighted(&self, _idx: usize) -> bool { false }
+    fn needs_repage(&self) -> bool { true }
+    fn needs_redraw(&self) -> bool { true }
+    fn sort_by_column(&mut self, _col_idx: usize) { }
+
+    fn set_render_feedback(&mut self, fb: RenderFeedback) {
+        self.render_feedback = fb;
+        // Clicks map to rows relative to the scroll offset:
+        self.render_feedback.row_offset = self.cursor.scroll_offset;
+    }
+
+    fn is_inside_screen_rect(&self, x: i32, y: i32) -> bool {
+        self.render_feedback.is_inside_screen_rect(x, y)
+    }
+
+    fn cursor_path(&self) -> Option<std::path::PathBuf> {
+        self.nodes.get(self.cursor.cursor_idx).map(|n| n.path.clone())
+    }
+
+    fn do_control(&mut self, ctrl: PageControl) -> Option<PageEvent> {
+        let idx = self.cursor.cursor_idx.min(self.nodes.len().saturating_sub(1));
+        match ctrl {
+            PageControl::Access => {
+                let node = self.nodes.get(idx)?;
+                if !node.is_dir() {
+                    return Some(PageEvent::OpenFile(node.path.clone()));
+                }
+                if node.expanded {
+                    self.collapse(idx);
+                } else if let Some(err) = self.expand(idx) {
+                    return Some(PageEvent::Log(vec![err]));
+                }
+                self.move_cursor(idx);
+                None
+            },
+            PageControl::Back => {
+                // Collapse the cursor node, or go up to its parent, and
+                // close the page at the root:
+                if self.nodes.get(idx)?.expanded && idx > 0 {
+                    self.collapse(idx);
+                    self.move_cursor(idx);
+                    return None;
+                }
+                match self.parent_idx(idx) {
+                    Some(parent) => { self.move_cursor(parent); None },
+                    None         => Some(PageEvent::Close),
+                }
+            },
+            PageControl::Refresh => {
+                self.reload();
+                self.cursor.do_control(self.nodes.len(), &self.render_feedback, ctrl);
+                None
+            },
+            _ => {
+                self.cursor.do_control(self.nodes.len(), &self.render_feedback, ctrl);
+                None
+            },
+        }
+    }
+
+    fn as_drawable_table(&mut self) -> TableRef {
+        let start = self.cursor.scroll_offset.min(self.nodes.len());
+        let end   = (start + self.visible_rows()).min(self.nodes.len());
+        let rows  = &self.nodes[start..end];
+
+        self.rendered =
+            std::rc::Rc::new(std::cell::RefCell::new(Table {
+                title: format!("tree {} [{} rows]", self.root.to_string_lossy(), self.nodes.len()),
+                row_gap: 2,
+                col_gap: 4,
+                columns: vec![
+                    Column {
+                        head: String::from("name"),
+                        size: ColumnSizing::ExpandFract(1),
+                        calc_size: None,
+                        rows: rows.iter().map(|n| StyleString {
+                            text: n.label(),
+                            style: match n.path_type {
+                                PathRecordType::File    => Style::File,
+                                PathRecordType::Dir     => Style::Dir,
+                                PathRecordType::SymLink => Style::Special,
+                            },
+                            highlight: vec![],
+                        }).collect(),
+                    },
+                ],
+            }));
+        self.rendered.clone()
+    }
+}
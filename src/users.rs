@@ -0,0 +1,47 @@
+/// Looks up user and group names in `/etc/passwd` and `/etc/group`.
+/// Both files have one `name:password:id:...` entry per line.
+fn read_id_db(file: &str) -> Vec<(String, u32)> {
+    let content = match std::fs::read_to_string(file) {
+        Ok(c)  => c,
+        Err(_) => return vec![],
+    };
+
+    content.lines().filter_map(|line| {
+        let mut fields = line.split(':');
+        let name = fields.next()?;
+        let id   = fields.nth(1)?.parse::<u32>().ok()?;
+        Some((name.to_string(), id))
+    }).collect()
+}
+
+pub fn user_name(uid: u32) -> Option<String> {
+    read_id_db("/etc/passwd").into_iter().find(|(_, id)| *id == uid).map(|(n, _)| n)
+}
+
+pub fn group_name(gid: u32) -> Option<String> {
+    read_id_db("/etc/group").into_iter().find(|(_, id)| *id == gid).map(|(n, _)| n)
+}
+
+/// Resolves a user name or a numeric uid.
+pub fn lookup_uid(name: &str) -> Option<u32> {
+    if let Ok(uid) = name.parse::<u32>() {
+        return Some(uid);
+    }
+    read_id_db("/etc/passwd").into_iter().find(|(n, _)| n == name).map(|(_, id)| id)
+}
+
+/// Resolves a group name or a numeric gid.
+pub fn lookup_gid(name: &str) -> Option<u32> {
+    if let Ok(gid) = name.parse::<u32>() {
+        return Some(gid);
+    }
+    read_id_db("/etc/group").into_iter().find(|(n, _)| n == name).map(|(_, id)| id)
+}
+
+/// Formats an uid and gid as `user:group`, falling back to the numbers
+/// for unknown ids.
+pub fn owner_string(uid: u32, gid: u32) -> String {
+    format!("{}:{}",
+        user_name(uid).unwrap_or(uid.to_string()),
+        group_name(gid).unwrap_or(gid.to_string()))
+}